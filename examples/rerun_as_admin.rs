@@ -8,16 +8,8 @@
 //! to the spawned elevated process, either directly via lpParameters or leveraging
 //! IPC, e.g. by creating Windows named pipe and passing it via lpParameters.
 
-use std::env;
 use std::io::{Write, BufRead};
 
-use windows::core::*;
-use windows::Win32::Foundation;
-use windows::Win32::System::Threading;
-use windows::Win32::System::WindowsProgramming::INFINITE;
-use windows::Win32::UI::Shell;
-use windows::Win32::UI::WindowsAndMessaging;
-
 use libwdi as wdi;
 
 const VID: u16 = 0x0483;
@@ -26,82 +18,101 @@ const VENDOR: &str = "Example vendor";
 const DRIVER_PATH: &str = "C:\\usb_driver";
 const INF_NAME: &str = "MyDeviceWinUSB.inf";
 
-// Get executable for this program, in general it should be better to use a fixed known
-// path instead of env::current_exe.
-fn get_current_exe() -> std::path::PathBuf {
-    env::current_exe()
-        .expect("Could not get path to current executable")
-}
-
-fn se_err_string(err: u32) -> String {
-    match err {
-        Shell::SE_ERR_FNF => "File not found.".into(),
-        Shell::SE_ERR_PNF => "Path not found.".into(),
-        Shell::SE_ERR_ACCESSDENIED => "Access denied.".into(),
-        Shell::SE_ERR_OOM => "Out of memory.".into(),
-        Shell::SE_ERR_DLLNOTFOUND => "Dynamic-link library not found.".into(),
-        Shell::SE_ERR_SHARE => "Cannot share an open file.".into(),
-        Shell::SE_ERR_ASSOCINCOMPLETE => "File association information not complete.".into(),
-        Shell::SE_ERR_DDETIMEOUT => "DDE operation timed out.".into(),
-        Shell::SE_ERR_DDEFAIL => "DDE operation failed.".into(),
-        Shell::SE_ERR_DDEBUSY => "DDE operation is busy.".into(),
-        Shell::SE_ERR_NOASSOC => "File association not available.".into(),
-        _ => format!("Unexpected SE_ERR_* code: {}", err),
+#[cfg(windows)]
+mod elevate {
+    use std::env;
+
+    use windows::core::*;
+    use windows::Win32::Foundation;
+    use windows::Win32::System::Threading;
+    use windows::Win32::System::WindowsProgramming::INFINITE;
+    use windows::Win32::UI::Shell;
+    use windows::Win32::UI::WindowsAndMessaging;
+
+    // Get executable for this program, in general it should be better to use a fixed known
+    // path instead of env::current_exe.
+    fn get_current_exe() -> std::path::PathBuf {
+        env::current_exe()
+            .expect("Could not get path to current executable")
     }
-}
 
-// Re-run this executable with escalated privileges
-// See:
-// https://users.rust-lang.org/t/hi-guys-how-do-i-trigger-a-new-process-to-be-run-as-admin/60788/4
-// https://stackoverflow.com/a/17638969
-fn rerun_as_admin() {
-    // By using ShellExecuteEx with SHELLEXECUTEINFOW we can wait for the process to complete
-    let exe = HSTRING::from(get_current_exe().to_str().unwrap());
-    let show = WindowsAndMessaging::SW_NORMAL; // SW_HIDE to run in background
-
-    let mut exec_info = Shell::SHELLEXECUTEINFOW {
-        cbSize: std::mem::size_of::<Shell::SHELLEXECUTEINFOW>() as u32,
-        fMask: Shell::SEE_MASK_NOCLOSEPROCESS, // hProcess will receive process handle
-        hwnd: Foundation::HWND(0),
-        lpVerb: w!("runas"), // run [a]dmini[s]trator
-        lpFile: PCWSTR::from_raw(exe.as_ptr()),
-        lpParameters: w!(""),
-        lpDirectory: PCWSTR::null(),
-        nShow: show.0 as i32,
-        ..Default::default()
-    };
-
-    // With SEE_MASK_NOCLOSEPROCESS hInstApp is set to >=32 on success or SE_ERR_XXX on failure
-    unsafe {
-        if !Shell::ShellExecuteExW(&mut exec_info).as_bool() {
-            panic!("ShellExecuteExW failed");
+    fn se_err_string(err: u32) -> String {
+        match err {
+            Shell::SE_ERR_FNF => "File not found.".into(),
+            Shell::SE_ERR_PNF => "Path not found.".into(),
+            Shell::SE_ERR_ACCESSDENIED => "Access denied.".into(),
+            Shell::SE_ERR_OOM => "Out of memory.".into(),
+            Shell::SE_ERR_DLLNOTFOUND => "Dynamic-link library not found.".into(),
+            Shell::SE_ERR_SHARE => "Cannot share an open file.".into(),
+            Shell::SE_ERR_ASSOCINCOMPLETE => "File association information not complete.".into(),
+            Shell::SE_ERR_DDETIMEOUT => "DDE operation timed out.".into(),
+            Shell::SE_ERR_DDEFAIL => "DDE operation failed.".into(),
+            Shell::SE_ERR_DDEBUSY => "DDE operation is busy.".into(),
+            Shell::SE_ERR_NOASSOC => "File association not available.".into(),
+            _ => format!("Unexpected SE_ERR_* code: {}", err),
         }
     }
-    if let err @ 0..=31 = exec_info.hInstApp.0 as u32 {
-        panic!("Failed to rerun as administrator: {}", se_err_string(err));
-    } else if exec_info.hProcess.is_invalid() {
-        panic!("No process was spawned");
-    }
 
-    // Wait for the process and clean up
-    unsafe {
-        Threading::WaitForSingleObject(exec_info.hProcess, INFINITE);
-        Foundation::CloseHandle(exec_info.hProcess);
+    // Re-run this executable with escalated privileges
+    // See:
+    // https://users.rust-lang.org/t/hi-guys-how-do-i-trigger-a-new-process-to-be-run-as-admin/60788/4
+    // https://stackoverflow.com/a/17638969
+    pub fn rerun_as_admin() {
+        // By using ShellExecuteEx with SHELLEXECUTEINFOW we can wait for the process to complete
+        let exe = HSTRING::from(get_current_exe().to_str().unwrap());
+        let show = WindowsAndMessaging::SW_NORMAL; // SW_HIDE to run in background
+
+        let mut exec_info = Shell::SHELLEXECUTEINFOW {
+            cbSize: std::mem::size_of::<Shell::SHELLEXECUTEINFOW>() as u32,
+            fMask: Shell::SEE_MASK_NOCLOSEPROCESS, // hProcess will receive process handle
+            hwnd: Foundation::HWND(0),
+            lpVerb: w!("runas"), // run [a]dmini[s]trator
+            lpFile: PCWSTR::from_raw(exe.as_ptr()),
+            lpParameters: w!(""),
+            lpDirectory: PCWSTR::null(),
+            nShow: show.0 as i32,
+            ..Default::default()
+        };
+
+        // With SEE_MASK_NOCLOSEPROCESS hInstApp is set to >=32 on success or SE_ERR_XXX on failure
+        unsafe {
+            if !Shell::ShellExecuteExW(&mut exec_info).as_bool() {
+                panic!("ShellExecuteExW failed");
+            }
+        }
+        if let err @ 0..=31 = exec_info.hInstApp.0 as u32 {
+            panic!("Failed to rerun as administrator: {}", se_err_string(err));
+        } else if exec_info.hProcess.is_invalid() {
+            panic!("No process was spawned");
+        }
+
+        // Wait for the process and clean up
+        unsafe {
+            Threading::WaitForSingleObject(exec_info.hProcess, INFINITE);
+            Foundation::CloseHandle(exec_info.hProcess);
+        }
+
+        // Make sure it's not dropped earlier
+        drop(exe);
     }
+}
 
-    // Make sure it's not dropped earlier
-    drop(exe);
+#[cfg(not(windows))]
+mod elevate {
+    pub fn rerun_as_admin() {
+        panic!("Re-running as administrator is only supported on Windows");
+    }
 }
 
 fn main() {
     wdi::set_log_level(wdi::LogLevel::Info).unwrap();
 
-    let devices = wdi::CreateListOptions::new()
+    let mut devices = wdi::CreateListOptions::new()
         .list_all(true)
         .create_list()
         .expect("Failed to list USB devices");
 
-    let mut candidates: Vec<_> = devices.iter()
+    let candidates: Vec<_> = devices.iter_mut()
         .filter(|dev| dev.vid() == VID && dev.pid() == PID)
         .collect();
 
@@ -122,7 +133,7 @@ fn main() {
         println!("    driver version: {}", dev.upper_filter().unwrap_or("-".into()));
     }
 
-    if let Some(dev) = candidates.first_mut() {
+    if let Some(dev) = candidates.into_iter().next() {
         println!("Using first device: {:04x}:{:04x}", dev.vid(), dev.pid());
         println!("Continue? [yN] ");
         std::io::stdout().lock().flush().unwrap();
@@ -147,7 +158,7 @@ fn main() {
                 Ok(_) => {},
                 Err(wdi::Error::NeedsAdmin) => {
                     println!("Re-running as administrator ...");
-                    rerun_as_admin();
+                    elevate::rerun_as_admin();
                     println!("Done");
                 },
                 err => err.expect("Failed to install driver"),
@@ -0,0 +1,191 @@
+// Bindings to libwdi.h written by hand after the output of bindgen for x86_64-pc-windows-msvc,
+// without layout tests. Used in stub mode (docs.rs, non-Windows hosts) where libwdi.h cannot be
+// processed because it includes windows.h. Building on Windows with LIBWDI_UPDATE_BINDINGS=1
+// replaces this file with the actual bindgen output.
+
+pub type DWORD = ::std::os::raw::c_ulong;
+pub type BOOL = ::std::os::raw::c_int;
+pub type UINT = ::std::os::raw::c_uint;
+pub type UINT32 = ::std::os::raw::c_uint;
+pub type UINT64 = ::std::os::raw::c_ulonglong;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct HWND__ {
+    pub unused: ::std::os::raw::c_int,
+}
+pub type HWND = *mut HWND__;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct tagVS_FIXEDFILEINFO {
+    pub dwSignature: DWORD,
+    pub dwStrucVersion: DWORD,
+    pub dwFileVersionMS: DWORD,
+    pub dwFileVersionLS: DWORD,
+    pub dwProductVersionMS: DWORD,
+    pub dwProductVersionLS: DWORD,
+    pub dwFileFlagsMask: DWORD,
+    pub dwFileFlags: DWORD,
+    pub dwFileOS: DWORD,
+    pub dwFileType: DWORD,
+    pub dwFileSubtype: DWORD,
+    pub dwFileDateMS: DWORD,
+    pub dwFileDateLS: DWORD,
+}
+pub type VS_FIXEDFILEINFO = tagVS_FIXEDFILEINFO;
+pub mod wdi_driver_type {
+    pub type Type = ::std::os::raw::c_int;
+    pub const WDI_WINUSB: Type = 0;
+    pub const WDI_LIBUSB0: Type = 1;
+    pub const WDI_LIBUSBK: Type = 2;
+    pub const WDI_CDC: Type = 3;
+    pub const WDI_USER: Type = 4;
+    pub const WDI_NB_DRIVERS: Type = 5;
+}
+pub mod wdi_log_level {
+    pub type Type = ::std::os::raw::c_int;
+    pub const WDI_LOG_LEVEL_DEBUG: Type = 0;
+    pub const WDI_LOG_LEVEL_INFO: Type = 1;
+    pub const WDI_LOG_LEVEL_WARNING: Type = 2;
+    pub const WDI_LOG_LEVEL_ERROR: Type = 3;
+    pub const WDI_LOG_LEVEL_NONE: Type = 4;
+}
+pub mod wdi_error {
+    pub type Type = ::std::os::raw::c_int;
+    pub const WDI_SUCCESS: Type = 0;
+    pub const WDI_ERROR_IO: Type = -1;
+    pub const WDI_ERROR_INVALID_PARAM: Type = -2;
+    pub const WDI_ERROR_ACCESS: Type = -3;
+    pub const WDI_ERROR_NO_DEVICE: Type = -4;
+    pub const WDI_ERROR_NOT_FOUND: Type = -5;
+    pub const WDI_ERROR_BUSY: Type = -6;
+    pub const WDI_ERROR_TIMEOUT: Type = -7;
+    pub const WDI_ERROR_OVERFLOW: Type = -8;
+    pub const WDI_ERROR_PENDING_INSTALLATION: Type = -9;
+    pub const WDI_ERROR_INTERRUPTED: Type = -10;
+    pub const WDI_ERROR_RESOURCE: Type = -11;
+    pub const WDI_ERROR_NOT_SUPPORTED: Type = -12;
+    pub const WDI_ERROR_EXISTS: Type = -13;
+    pub const WDI_ERROR_USER_CANCEL: Type = -14;
+    pub const WDI_ERROR_NEEDS_ADMIN: Type = -15;
+    pub const WDI_ERROR_WOW64: Type = -16;
+    pub const WDI_ERROR_INF_SYNTAX: Type = -17;
+    pub const WDI_ERROR_CAT_MISSING: Type = -18;
+    pub const WDI_ERROR_UNSIGNED: Type = -19;
+    pub const WDI_ERROR_OTHER: Type = -99;
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct wdi_device_info {
+    pub next: *mut wdi_device_info,
+    pub vid: ::std::os::raw::c_ushort,
+    pub pid: ::std::os::raw::c_ushort,
+    pub is_composite: BOOL,
+    pub mi: ::std::os::raw::c_uchar,
+    pub desc: *mut ::std::os::raw::c_char,
+    pub driver: *mut ::std::os::raw::c_char,
+    pub device_id: *mut ::std::os::raw::c_char,
+    pub hardware_id: *mut ::std::os::raw::c_char,
+    pub compatible_id: *mut ::std::os::raw::c_char,
+    pub upper_filter: *mut ::std::os::raw::c_char,
+    pub driver_version: UINT64,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct wdi_options_create_list {
+    pub list_all: BOOL,
+    pub list_hubs: BOOL,
+    pub trim_whitespaces: BOOL,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct wdi_options_prepare_driver {
+    pub driver_type: ::std::os::raw::c_int,
+    pub vendor_name: *mut ::std::os::raw::c_char,
+    pub device_guid: *mut ::std::os::raw::c_char,
+    pub disable_cat: BOOL,
+    pub disable_signing: BOOL,
+    pub cert_subject: *mut ::std::os::raw::c_char,
+    pub use_wcid_driver: BOOL,
+    pub external_inf: BOOL,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct wdi_options_install_driver {
+    pub hWnd: HWND,
+    pub install_filter_driver: BOOL,
+    pub pending_install_timeout: UINT32,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct wdi_options_install_cert {
+    pub hWnd: HWND,
+    pub disable_warning: BOOL,
+}
+extern "C" {
+    pub fn wdi_strerror(errcode: ::std::os::raw::c_int) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn wdi_is_driver_supported(
+        driver_type: ::std::os::raw::c_int,
+        driver_info: *mut VS_FIXEDFILEINFO,
+    ) -> BOOL;
+}
+extern "C" {
+    pub fn wdi_is_file_embedded(
+        path: *const ::std::os::raw::c_char,
+        name: *const ::std::os::raw::c_char,
+    ) -> BOOL;
+}
+extern "C" {
+    pub fn wdi_get_vendor_name(vid: ::std::os::raw::c_ushort) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn wdi_create_list(
+        list: *mut *mut wdi_device_info,
+        options: *mut wdi_options_create_list,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_destroy_list(list: *mut wdi_device_info) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_prepare_driver(
+        device_info: *mut wdi_device_info,
+        path: *const ::std::os::raw::c_char,
+        inf_name: *const ::std::os::raw::c_char,
+        options: *mut wdi_options_prepare_driver,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_install_driver(
+        device_info: *mut wdi_device_info,
+        path: *const ::std::os::raw::c_char,
+        inf_name: *const ::std::os::raw::c_char,
+        options: *mut wdi_options_install_driver,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_install_trusted_certificate(
+        cert_name: *const ::std::os::raw::c_char,
+        options: *mut wdi_options_install_cert,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_set_log_level(level: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_register_logger(hWnd: HWND, message: UINT, buffsize: DWORD) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_unregister_logger(hWnd: HWND) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_read_logger(
+        buffer: *mut ::std::os::raw::c_char,
+        buffer_size: DWORD,
+        message_size: *mut DWORD,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn wdi_get_wdf_version() -> ::std::os::raw::c_int;
+}
@@ -23,6 +23,82 @@ fn generate_ffi_bindings() {
     bindings
         .write_to_file(out_dir.join("bindings.rs"))
         .expect("Could not write bindings file");
+
+    // Refresh bindings used in stub mode
+    println!("cargo:rerun-if-env-changed=LIBWDI_UPDATE_BINDINGS");
+    if env::var_os("LIBWDI_UPDATE_BINDINGS").is_some() {
+        let pregenerated = root_dir().join(PREGENERATED_BINDINGS);
+        println!("Updating pre-generated bindings: {}", pregenerated.display());
+        bindings
+            .write_to_file(pregenerated)
+            .expect("Could not update pre-generated bindings file");
+    }
+}
+
+/// Bindings generated from libwdi.h on Windows, used when bindgen cannot process the header
+const PREGENERATED_BINDINGS: &str = "bindings/libwdi.rs";
+
+/// Copy pre-generated bindings instead of running bindgen, which requires windows.h
+fn copy_pregenerated_bindings() {
+    let src = root_dir().join(PREGENERATED_BINDINGS);
+    println!("cargo:rerun-if-changed={}", src.display());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy(&src, out_dir.join("bindings.rs"))
+        .expect("Could not copy pre-generated bindings file");
+}
+
+/// Whether the build script runs on Windows, where libwdi can be built and bindgen can process libwdi.h
+fn host_is_windows() -> bool {
+    env::var("HOST").unwrap().contains("windows")
+}
+
+/// In stub mode only bindings are provided and libwdi is neither compiled nor linked.
+/// This is enabled when building documentation on docs.rs, when targeting other platforms than
+/// Windows, or when building on other hosts without a prebuilt library in LIBWDI_LIB_DIR (e.g.
+/// `cargo check --target x86_64-pc-windows-gnu` on Linux). Can be forced with LIBWDI_SYS_STUB=1/0.
+/// Always used with "fake" feature, as the fake implementation replaces libwdi.
+fn stub_mode() -> bool {
+    println!("cargo:rerun-if-env-changed=LIBWDI_SYS_STUB");
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    if env::var_os("CARGO_FEATURE_FAKE").is_some() {
+        return true;
+    }
+    if let Ok(value) = env::var("LIBWDI_SYS_STUB") {
+        return match value.as_str() {
+            "1" | "true" | "yes" => true,
+            "0" | "false" | "no" => false,
+            other => panic!("Invalid value of LIBWDI_SYS_STUB: \"{other}\", expected 1 or 0"),
+        };
+    }
+    println!("cargo:rerun-if-env-changed=LIBWDI_LIB_DIR");
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let can_build = host_is_windows() || env::var_os("LIBWDI_LIB_DIR").is_some();
+    env::var_os("DOCS_RS").is_some() || target_os != "windows" || !can_build
+}
+
+/// Rebuild libwdi only when any of its sources or build configuration changes
+fn rerun_if_sources_changed(src_dir: &Path) -> io::Result<()> {
+    fn visit(dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                visit(&path)?;
+                continue;
+            }
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+            // embedded.h is generated by the embedder during the build
+            let is_source = matches!(ext, "c" | "h" | "rc" | "def" | "in" | "vcxproj" | "props");
+            if is_source && name != "embedded.h" {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    println!("cargo:rerun-if-changed={}", src_dir.join("libwdi.sln").display());
+    visit(&src_dir.join("libwdi"))?;
+    visit(&src_dir.join("msvc"))
 }
 
 fn verify_file_exists<P: AsRef<Path>>(path: P) -> io::Result<()> {
@@ -39,64 +115,298 @@ fn verify_file_exists<P: AsRef<Path>>(path: P) -> io::Result<()> {
     }
 }
 
-fn build_library() {
-    // Should we always use Release of change to Debug when not building with --release?
-    let build_type = "Release";
-    let platform = "x64";
+/// How libwdi gets linked into the final binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkMode {
+    Static,
+    Dynamic,
+}
+
+impl LinkMode {
+    /// Select link mode based on crate features, LIBWDI_STATIC takes precedence if set
+    fn from_env() -> Self {
+        println!("cargo:rerun-if-env-changed=LIBWDI_STATIC");
+        if let Ok(value) = env::var("LIBWDI_STATIC") {
+            return match value.as_str() {
+                "1" | "true" | "yes" => Self::Static,
+                "0" | "false" | "no" => Self::Dynamic,
+                other => panic!("Invalid value of LIBWDI_STATIC: \"{other}\", expected 1 (static) or 0 (dynamic)"),
+            };
+        }
+
+        let static_feature = env::var_os("CARGO_FEATURE_STATIC").is_some();
+        let dynamic_feature = env::var_os("CARGO_FEATURE_DYNAMIC").is_some();
+        match (static_feature, dynamic_feature) {
+            (true, false) => Self::Static,
+            (false, true) => Self::Dynamic,
+            (true, true) => panic!(
+                "Features \"static\" and \"dynamic\" are mutually exclusive, \
+                use `default-features = false` when enabling \"dynamic\" or set LIBWDI_STATIC"),
+            (false, false) => panic!(
+                "Either \"static\" or \"dynamic\" feature must be enabled (or LIBWDI_STATIC must be set)"),
+        }
+    }
+
+    fn link_kind(self) -> &'static str {
+        match self {
+            Self::Static => "static",
+            Self::Dynamic => "dylib",
+        }
+    }
+
+    /// Subdirectory of MSBuild output directory used by libwdi.sln for given library type
+    fn output_subdir(self) -> &'static str {
+        match self {
+            Self::Static => "lib",
+            Self::Dynamic => "dll",
+        }
+    }
+}
+
+/// Driver that can be embedded in libwdi, selected using crate feature
+struct EmbeddedDriver {
+    /// Crate feature that enables this driver
+    feature: &'static str,
+    /// Embedded by libwdi built from the submodule even without the feature: libwdi decides what to
+    /// embed only from the *_DIR defines, and its config.h has a default WDK_DIR (CDC needs no files)
+    always_embedded: bool,
+    /// Variable used both as environment variable and as the #define in libwdi config
+    dir_var: Option<&'static str>,
+    /// Whether the directory must be provided for the driver to be embedded
+    dir_required: bool,
+    /// Entry that must exist in the driver directory, used to validate the path
+    expected_entry: &'static str,
+}
+
+const EMBEDDED_DRIVERS: [EmbeddedDriver; 5] = [
+    // WDK_DIR has a default in libwdi config, it is only needed if WDK is installed elsewhere
+    EmbeddedDriver { feature: "winusb", always_embedded: true, dir_var: Some("WDK_DIR"), dir_required: false, expected_entry: "redist" },
+    EmbeddedDriver { feature: "libusb0", always_embedded: false, dir_var: Some("LIBUSB0_DIR"), dir_required: true, expected_entry: "bin" },
+    EmbeddedDriver { feature: "libusbk", always_embedded: false, dir_var: Some("LIBUSBK_DIR"), dir_required: true, expected_entry: "sys" },
+    // CDC only uses an INF template which is a part of libwdi, so it needs no directory
+    EmbeddedDriver { feature: "cdc", always_embedded: true, dir_var: None, dir_required: false, expected_entry: "" },
+    EmbeddedDriver { feature: "user-driver", always_embedded: false, dir_var: Some("USER_DIR"), dir_required: true, expected_entry: "" },
+];
+
+impl EmbeddedDriver {
+    fn enabled(&self) -> bool {
+        let var = format!("CARGO_FEATURE_{}", self.feature.to_uppercase().replace('-', "_"));
+        env::var_os(var).is_some()
+    }
+}
+
+/// Tell the crate which drivers libwdi embeds (`libwdi_embedded` cfg). libwdi built from the submodule
+/// always embeds some of them, a prebuilt one (or none in stub mode) is described by the features.
+fn embedded_cfgs(from_submodule: bool) {
+    let values: Vec<_> = EMBEDDED_DRIVERS.iter().map(|driver| format!("\"{}\"", driver.feature)).collect();
+    println!("cargo:rustc-check-cfg=cfg(libwdi_embedded, values({}))", values.join(", "));
+    for driver in EMBEDDED_DRIVERS.iter() {
+        if driver.enabled() || (from_submodule && driver.always_embedded) {
+            println!("cargo:rustc-cfg=libwdi_embedded=\"{}\"", driver.feature);
+        }
+    }
+}
+
+/// Generate #defines with directories of the enabled drivers for libwdi config, validating that they
+/// exist. libwdi embeds a driver when its directory is defined, there are no other switches. Relative
+/// paths are resolved in the same way as in C sources, i.e. relative to `base_dir`.
+fn driver_defines(base_dir: &Path) -> Vec<String> {
+    let mut defs = vec![];
+    for driver in EMBEDDED_DRIVERS.iter() {
+        if driver.always_embedded && !driver.enabled() {
+            println!("cargo:warning=Feature \"{}\" is disabled, but libwdi embeds this driver anyway", driver.feature);
+        }
+        let Some(var) = driver.dir_var else {
+            continue;
+        };
+        println!("cargo:rerun-if-env-changed={var}");
+        let dir = env::var(var).ok();
+
+        if !driver.enabled() {
+            if dir.is_some() {
+                println!("cargo:warning={var} is ignored because feature \"{}\" is disabled", driver.feature);
+            }
+            continue;
+        }
+
+        let Some(dir) = dir else {
+            if driver.dir_required {
+                panic!("Feature \"{}\" requires {var} to point to the driver files", driver.feature);
+            }
+            continue;
+        };
+
+        let path = base_dir.join(&dir).join(driver.expected_entry);
+        if !path.exists() {
+            panic!("Invalid {var} for feature \"{}\": {} does not exist", driver.feature, path.display());
+        }
+
+        // Replace backslashes because this is later put in C file and backslashes are
+        // interpreted as escape sequences.
+        let dir = dir.replace('\\', "/");
+        let def = format!("{var}=\"{dir}\"");
+        println!("Using: {def}");
+        defs.push(def);
+    }
+    defs
+}
+
+/// MSBuild platform of libwdi.sln matching the Rust target, also used as output directory name
+fn msbuild_platform() -> &'static str {
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    if target_env != "msvc" {
+        panic!("libwdi can only be built with MSVC, unsupported target environment: \"{target_env}\" \
+            (use LIBWDI_LIB_DIR to link a prebuilt library)");
+    }
+
+    let platform = match arch.as_str() {
+        "x86" => "Win32",
+        "x86_64" => "x64",
+        "aarch64" => "ARM64",
+        other => panic!("Unsupported target architecture: \"{other}\", libwdi supports x86, x86_64 and aarch64"),
+    };
+
+    // There are no ARM64 builds of libusb0 and libusbK drivers
+    if platform == "ARM64" {
+        for feature in ["libusb0", "libusbk"] {
+            let var = format!("CARGO_FEATURE_{}", feature.to_uppercase());
+            if env::var_os(var).is_some() {
+                panic!("Feature \"{feature}\" is not supported on ARM64 targets");
+            }
+        }
+    }
+
+    platform
+}
+
+/// MSBuild configuration following the cargo profile, LIBWDI_BUILD_TYPE=Debug/Release takes precedence
+///
+/// The Debug configuration links the debug C runtime, while Rust code always uses the release one, so
+/// LIBWDI_BUILD_TYPE=Release may be needed in dev builds if the linker reports conflicting runtimes.
+fn msbuild_configuration() -> &'static str {
+    println!("cargo:rerun-if-env-changed=LIBWDI_BUILD_TYPE");
+    match env::var("LIBWDI_BUILD_TYPE") {
+        Ok(value) => match value.to_lowercase().as_str() {
+            "debug" => "Debug",
+            "release" => "Release",
+            _ => panic!("Invalid value of LIBWDI_BUILD_TYPE: \"{value}\", expected Debug or Release"),
+        },
+        // Custom profiles are reported as the built-in profile they inherit from
+        Err(_) => match env::var("PROFILE").as_deref() {
+            Ok("release") => "Release",
+            _ => "Debug",
+        },
+    }
+}
+
+fn build_library(mode: LinkMode) -> PathBuf {
+    let build_type = msbuild_configuration();
+    let platform = msbuild_platform();
 
     let src_dir = root_dir().join("libwdi");
-    let out_dir = src_dir.join(platform).join(build_type).join("lib");
+    let out_dir = src_dir.join(platform).join(build_type).join(mode.output_subdir());
 
     // Build libwdi using microsoft build tools:
     // MSBuild.exe libwdi.sln -p:Configuration=Release -p:Platform=x64
+    // which outputs libraries to {Platform}/{Configuration}/{lib,dll}
     let mut cmd = Command::new("MSBuild.exe");
-    cmd.current_dir(src_dir);
+    cmd.current_dir(&src_dir);
     cmd.arg("libwdi.sln");
     cmd.arg("-m"); // use multiple concurrent processes
     cmd.arg(format!("-p:Configuration={build_type}"));
     cmd.arg(format!("-p:Platform={platform}"));
 
-    // Generate build macros with #defines for custom library paths from env variables
-    let lib_vars = ["WDK_DIR", "LIBUSB0_DIR", "LIBUSBK_DIR"];
-    let mut defs = vec![];
-    for var in lib_vars {
-        println!("cargo:rerun-if-env-changed={var}");
-        if let Ok(dir) = env::var(var) {
-            // Replace backslashes because this is later put in C file and backslashes are
-            // interpreted as escape sequences.
-            let dir = dir.replace('\\', "/");
-            let def = format!("{var}=\"{dir}\"");
-            println!("Using: {def}");
-            defs.push(def);
-        }
-    }
-    if !defs.is_empty() {
-        let macros = defs.join(";");
-        cmd.arg(format!("-p:BuildMacros={macros}"));
-    }
+    // Generate build macros with #defines for the drivers selected via crate features
+    let macros = driver_defines(&src_dir.join("libwdi")).join(";");
+    cmd.arg(format!("-p:BuildMacros={macros}"));
 
     // Run build
     println!("Running MSBuild: {cmd:?}");
-    cmd.status().expect("Failed to build libwdi using MSBuild");
+    let status = cmd.status().expect("Failed to build libwdi using MSBuild");
+    if !status.success() {
+        panic!("MSBuild failed with {status}");
+    }
 
     // Fail if the output file doesn't exist for easier debugging
-    verify_file_exists(out_dir.join("libwdi"))
+    verify_file_exists(out_dir.join("libwdi.lib"))
         .expect("Library output file does not exist");
+    if mode == LinkMode::Dynamic {
+        println!("cargo:warning=libwdi.dll from {} must be available at runtime", out_dir.display());
+    }
+
+    rerun_if_sources_changed(&src_dir)
+        .expect("Could not list libwdi sources");
+
+    out_dir
+}
+
+/// Use libwdi built outside of this crate, e.g. installed system-wide
+fn prebuilt_library(lib_dir: &Path, mode: LinkMode) -> PathBuf {
+    if !lib_dir.is_dir() {
+        panic!("LIBWDI_LIB_DIR is not a directory: {}", lib_dir.display());
+    }
+
+    // For dynamic linking libwdi.lib is the import library of libwdi.dll
+    let lib = lib_dir.join("libwdi.lib");
+    if !lib.is_file() {
+        let what = match mode {
+            LinkMode::Static => "static library",
+            LinkMode::Dynamic => "import library",
+        };
+        panic!("Requested {mode:?} linking but LIBWDI_LIB_DIR does not contain libwdi.lib {what}: {}",
+            lib_dir.display());
+    }
+    if mode == LinkMode::Dynamic && !lib_dir.join("libwdi.dll").is_file() {
+        println!("cargo:warning=libwdi.dll not found in {}, it must be available at runtime", lib_dir.display());
+    }
+
+    lib_dir.to_path_buf()
+}
 
-    println!("cargo:rustc-link-search=native={}", out_dir.display());
-    println!("cargo:rustc-link-lib=static=libwdi");
-    // No "rerun-if-changed", just run msbuild every time as it does it's own cacheing.
+fn link_library(lib_dir: &Path, mode: LinkMode) {
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib={}=libwdi", mode.link_kind());
 
-    // Add libwdi link dependencies
-    println!("cargo:rustc-link-lib=setupapi");
-    println!("cargo:rustc-link-lib=ntdll");
-    // println!("cargo:rustc-link-lib=newdev");
-    println!("cargo:rustc-link-lib=user32");
-    println!("cargo:rustc-link-lib=shell32");
-    println!("cargo:rustc-link-lib=ole32");
+    // Add libwdi link dependencies, libwdi.dll already links to these on its own
+    if mode == LinkMode::Static {
+        println!("cargo:rustc-link-lib=setupapi");
+        println!("cargo:rustc-link-lib=ntdll");
+        // println!("cargo:rustc-link-lib=newdev");
+        println!("cargo:rustc-link-lib=user32");
+        println!("cargo:rustc-link-lib=shell32");
+        println!("cargo:rustc-link-lib=ole32");
+    }
 }
 
 fn main() {
-    generate_ffi_bindings();
-    build_library();
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if stub_mode() {
+        println!("Stub mode: using pre-generated bindings, libwdi will not be linked");
+        copy_pregenerated_bindings();
+        embedded_cfgs(false);
+        return;
+    }
+
+    // Cross-compiling with a prebuilt library, windows.h is usually not available
+    if host_is_windows() {
+        generate_ffi_bindings();
+    } else {
+        copy_pregenerated_bindings();
+    }
+
+    let mode = LinkMode::from_env();
+    println!("cargo:rerun-if-env-changed=LIBWDI_LIB_DIR");
+    let lib_dir = match env::var_os("LIBWDI_LIB_DIR") {
+        Some(dir) => {
+            embedded_cfgs(false);
+            prebuilt_library(Path::new(&dir), mode)
+        },
+        None => {
+            embedded_cfgs(true);
+            build_library(mode)
+        },
+    };
+    link_library(&lib_dir, mode);
 }
@@ -0,0 +1,636 @@
+//! Fake libwdi implemented in Rust, for testing code that uses the bindings
+//!
+//! With the "fake" feature libwdi is not built nor linked. Instead this module exports all `wdi_*`
+//! symbols, so that code calling the bindings runs on any platform (also under Miri). The fake keeps
+//! its configuration per thread, so that tests running in parallel don't interfere with each other.
+//! Only the log is shared by all threads, as libwdi logs from any thread to a single logger.
+//!
+//! Devices returned by `wdi_create_list` are configured with [`set_devices`], results of
+//! individual functions can be changed with [`set_result`] and calls that use device/strings
+//! passed from the caller are recorded and available through [`calls`].
+//!
+//! Checks that libwdi itself makes before touching the system are emulated, so that tests cover how
+//! the wrappers surface them: `wdi_prepare_driver` requires an INF name with the `.inf` extension and a
+//! supported driver type, and both `wdi_prepare_driver` with `external_inf` and `wdi_install_driver`
+//! fail with `WDI_ERROR_NOT_FOUND` unless the INF has been prepared on the same thread (since the last
+//! [`reset`]) or exists on disk. Like libwdi, the fake accepts a single logger window for all threads.
+
+// Exported functions have the same safety requirements as their libwdi counterparts (see libwdi.h)
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_ushort};
+use std::ptr;
+use std::sync::Mutex;
+
+use crate::wdi_error::*;
+use crate::*;
+
+/// Device that will be returned by `wdi_create_list`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeDevice {
+    pub vid: u16,
+    pub pid: u16,
+    pub is_composite: bool,
+    pub mi: u8,
+    pub desc: Option<Vec<u8>>,
+    pub driver: Option<Vec<u8>>,
+    pub device_id: Option<Vec<u8>>,
+    pub hardware_id: Option<Vec<u8>>,
+    pub compatible_id: Option<Vec<u8>>,
+    pub upper_filter: Option<Vec<u8>>,
+    pub driver_version: u64,
+    /// Hubs are only listed with `list_hubs` option
+    pub is_hub: bool,
+}
+
+/// Functions which result can be changed using [`set_result`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Function {
+    CreateList,
+    DestroyList,
+    PrepareDriver,
+    InstallDriver,
+    InstallTrustedCertificate,
+    SetLogLevel,
+    RegisterLogger,
+    UnregisterLogger,
+    ReadLogger,
+}
+
+/// Device as seen by the fake when passed to one of the functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeenDevice {
+    pub vid: u16,
+    pub pid: u16,
+    pub mi: u8,
+    pub desc: Option<Vec<u8>>,
+    pub hardware_id: Option<Vec<u8>>,
+    pub device_id: Option<Vec<u8>>,
+}
+
+/// Recorded call to the fake libwdi
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    CreateList {
+        list_all: bool,
+        list_hubs: bool,
+        trim_whitespaces: bool,
+    },
+    PrepareDriver {
+        device: SeenDevice,
+        path: String,
+        inf_name: String,
+        driver_type: c_int,
+        vendor_name: Option<String>,
+        device_guid: Option<String>,
+        cert_subject: Option<String>,
+        disable_cat: bool,
+        disable_signing: bool,
+        use_wcid_driver: bool,
+        external_inf: bool,
+    },
+    InstallDriver {
+        device: SeenDevice,
+        path: String,
+        inf_name: String,
+        hwnd: usize,
+        install_filter_driver: bool,
+        pending_install_timeout: u32,
+    },
+    InstallTrustedCertificate {
+        cert_name: String,
+        hwnd: usize,
+        disable_warning: bool,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    devices: Vec<FakeDevice>,
+    results: HashMap<Function, c_int>,
+    hooks: HashMap<Function, Box<dyn FnMut()>>,
+    calls: Vec<Call>,
+    /// Heads of lists returned by wdi_create_list that have not been destroyed yet
+    lists: HashSet<usize>,
+    vendor_names: HashMap<u16, CString>,
+    wdf_version: Option<c_int>,
+    unsupported_drivers: HashSet<c_int>,
+    embedded_files: HashSet<(Option<String>, String)>,
+    /// Path and INF name of drivers prepared successfully
+    prepared: HashSet<(String, String)>,
+    log_level: c_int,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+static LOG: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+/// Window registered with `wdi_register_logger`, libwdi supports only one
+static LOGGER: Mutex<Option<usize>> = Mutex::new(None);
+
+fn with_state<T>(f: impl FnOnce(&mut State) -> T) -> T {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+impl FakeDevice {
+    pub fn new(vid: u16, pid: u16) -> Self {
+        Self {
+            vid,
+            pid,
+            is_composite: false,
+            mi: 0,
+            desc: Some(b"Fake USB device".to_vec()),
+            driver: None,
+            device_id: Some(format!("USB\\VID_{vid:04X}&PID_{pid:04X}\\0001").into_bytes()),
+            hardware_id: Some(format!("USB\\VID_{vid:04X}&PID_{pid:04X}").into_bytes()),
+            compatible_id: Some(b"USB\\Class_ff&SubClass_00&Prot_00".to_vec()),
+            upper_filter: None,
+            driver_version: 0,
+            is_hub: false,
+        }
+    }
+
+    /// Mark device as interface `mi` of a composite device
+    pub fn interface(mut self, mi: u8) -> Self {
+        self.is_composite = true;
+        self.mi = mi;
+        self
+    }
+
+    pub fn desc(mut self, desc: impl Into<Vec<u8>>) -> Self {
+        self.desc = Some(desc.into());
+        self
+    }
+
+    pub fn driver(mut self, driver: impl Into<Vec<u8>>) -> Self {
+        self.driver = Some(driver.into());
+        self
+    }
+
+    pub fn device_id(mut self, id: impl Into<Vec<u8>>) -> Self {
+        self.device_id = Some(id.into());
+        self
+    }
+
+    pub fn hardware_id(mut self, id: impl Into<Vec<u8>>) -> Self {
+        self.hardware_id = Some(id.into());
+        self
+    }
+
+    pub fn compatible_id(mut self, id: impl Into<Vec<u8>>) -> Self {
+        self.compatible_id = Some(id.into());
+        self
+    }
+
+    pub fn hub(mut self) -> Self {
+        self.is_hub = true;
+        self
+    }
+}
+
+/// Restore default state of the fake for current thread
+pub fn reset() {
+    with_state(|state| *state = State::default());
+}
+
+/// Set devices returned by subsequent `wdi_create_list` calls
+pub fn set_devices(devices: Vec<FakeDevice>) {
+    with_state(|state| state.devices = devices);
+}
+
+/// Make `function` return given error code (or WDI_SUCCESS) until [`reset`]
+pub fn set_result(function: Function, code: wdi_error::Type) {
+    with_state(|state| state.results.insert(function, code));
+}
+
+/// Run `hook` whenever `function` is called, until [`reset`], e.g. to keep the call running
+pub fn set_hook(function: Function, hook: impl FnMut() + 'static) {
+    with_state(|state| state.hooks.insert(function, Box::new(hook)));
+}
+
+/// Take all calls recorded so far
+pub fn calls() -> Vec<Call> {
+    with_state(|state| std::mem::take(&mut state.calls))
+}
+
+/// Number of lists created with `wdi_create_list` and not yet destroyed
+pub fn live_lists() -> usize {
+    with_state(|state| state.lists.len())
+}
+
+pub fn set_vendor_name(vid: u16, name: &str) {
+    let name = CString::new(name).expect("Vendor name contains NUL");
+    with_state(|state| state.vendor_names.insert(vid, name));
+}
+
+/// Set value returned by `wdi_get_wdf_version`, None means no WDF support (-1)
+pub fn set_wdf_version(version: Option<c_int>) {
+    with_state(|state| state.wdf_version = version);
+}
+
+/// All driver types are supported by default
+pub fn set_driver_supported(driver_type: wdi_driver_type::Type, supported: bool) {
+    with_state(|state| {
+        if supported {
+            state.unsupported_drivers.remove(&driver_type);
+        } else {
+            state.unsupported_drivers.insert(driver_type);
+        }
+    });
+}
+
+/// No files are embedded by default
+pub fn set_file_embedded(path: Option<&str>, name: &str) {
+    with_state(|state| state.embedded_files.insert((path.map(String::from), name.to_string())));
+}
+
+/// Current log level set with `wdi_set_log_level`
+pub fn log_level() -> c_int {
+    with_state(|state| state.log_level)
+}
+
+/// Add message to be returned by `wdi_read_logger` on any thread, it is not removed by [`reset`]
+pub fn push_log(message: &str) {
+    LOG.lock().unwrap_or_else(|err| err.into_inner()).push_back(message.as_bytes().to_vec());
+}
+
+fn result(function: Function) -> c_int {
+    // The hook may use the fake itself, so it cannot run while the state is borrowed
+    if let Some(mut hook) = with_state(|state| state.hooks.remove(&function)) {
+        hook();
+        with_state(|state| { state.hooks.entry(function).or_insert(hook); });
+    }
+    with_state(|state| state.results.get(&function).copied().unwrap_or(WDI_SUCCESS))
+}
+
+fn driver_supported(driver_type: c_int) -> bool {
+    let unsupported = with_state(|state| state.unsupported_drivers.contains(&driver_type));
+    (wdi_driver_type::WDI_WINUSB..wdi_driver_type::WDI_NB_DRIVERS).contains(&driver_type) && !unsupported
+}
+
+/// Whether the INF would exist if the fake wrote files
+fn inf_exists(path: &str, inf_name: &str) -> bool {
+    with_state(|state| state.prepared.contains(&(path.to_string(), inf_name.to_string())))
+        || std::path::Path::new(path).join(inf_name).is_file()
+}
+
+fn opt_cstring(bytes: &Option<Vec<u8>>) -> *mut c_char {
+    match bytes {
+        Some(bytes) => CString::new(bytes.clone()).expect("Fake device string contains NUL").into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe fn free_cstring(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn opt_bytes(s: *const c_char) -> Option<Vec<u8>> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s).to_bytes().to_vec())
+    }
+}
+
+unsafe fn opt_string(s: *const c_char) -> Option<String> {
+    opt_bytes(s).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+unsafe fn string(s: *const c_char) -> String {
+    opt_string(s).expect("Unexpected NULL string")
+}
+
+/// Read all fields of a device, verifying that it is accessible
+unsafe fn seen_device(info: *const wdi_device_info) -> SeenDevice {
+    let info = &*info;
+    let _ = (opt_bytes(info.driver), opt_bytes(info.compatible_id), opt_bytes(info.upper_filter));
+    SeenDevice {
+        vid: info.vid,
+        pid: info.pid,
+        mi: info.mi,
+        desc: opt_bytes(info.desc),
+        hardware_id: opt_bytes(info.hardware_id),
+        device_id: opt_bytes(info.device_id),
+    }
+}
+
+fn trim(bytes: &Option<Vec<u8>>) -> Option<Vec<u8>> {
+    bytes.as_ref().map(|bytes| {
+        let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+        let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+        bytes[start..end].to_vec()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_create_list(list: *mut *mut wdi_device_info, options: *mut wdi_options_create_list) -> c_int {
+    if list.is_null() {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+    *list = ptr::null_mut();
+
+    // libwdi treats NULL options as all-false
+    let opts = if options.is_null() {
+        wdi_options_create_list { list_all: 0, list_hubs: 0, trim_whitespaces: 0 }
+    } else {
+        *options
+    };
+    with_state(|state| state.calls.push(Call::CreateList {
+        list_all: opts.list_all != 0,
+        list_hubs: opts.list_hubs != 0,
+        trim_whitespaces: opts.trim_whitespaces != 0,
+    }));
+
+    let code = result(Function::CreateList);
+    if code != WDI_SUCCESS {
+        return code;
+    }
+
+    let devices: Vec<_> = with_state(|state| state.devices.clone())
+        .into_iter()
+        .filter(|dev| opts.list_all != 0 || dev.driver.is_none())
+        .filter(|dev| opts.list_hubs != 0 || !dev.is_hub)
+        .collect();
+    if devices.is_empty() {
+        return WDI_ERROR_NO_DEVICE;
+    }
+
+    // Build the list starting from the last element
+    let mut head: *mut wdi_device_info = ptr::null_mut();
+    for dev in devices.iter().rev() {
+        let desc = if opts.trim_whitespaces != 0 { trim(&dev.desc) } else { dev.desc.clone() };
+        let info = Box::new(wdi_device_info {
+            next: head,
+            vid: dev.vid,
+            pid: dev.pid,
+            is_composite: dev.is_composite as BOOL,
+            mi: dev.mi,
+            desc: opt_cstring(&desc),
+            driver: opt_cstring(&dev.driver),
+            device_id: opt_cstring(&dev.device_id),
+            hardware_id: opt_cstring(&dev.hardware_id),
+            compatible_id: opt_cstring(&dev.compatible_id),
+            upper_filter: opt_cstring(&dev.upper_filter),
+            driver_version: dev.driver_version,
+        });
+        head = Box::into_raw(info);
+    }
+
+    with_state(|state| state.lists.insert(head as usize));
+    *list = head;
+    WDI_SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_destroy_list(list: *mut wdi_device_info) -> c_int {
+    if list.is_null() {
+        return WDI_SUCCESS;
+    }
+    let known = with_state(|state| state.lists.remove(&(list as usize)));
+    if !known {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+
+    let mut next = list;
+    while !next.is_null() {
+        let info = Box::from_raw(next);
+        next = info.next;
+        for s in [info.desc, info.driver, info.device_id, info.hardware_id, info.compatible_id, info.upper_filter] {
+            free_cstring(s);
+        }
+    }
+
+    result(Function::DestroyList)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_prepare_driver(
+    device_info: *mut wdi_device_info,
+    path: *const c_char,
+    inf_name: *const c_char,
+    options: *mut wdi_options_prepare_driver,
+) -> c_int {
+    if device_info.is_null() || path.is_null() || inf_name.is_null() {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+
+    let call = if options.is_null() {
+        Call::PrepareDriver {
+            device: seen_device(device_info),
+            path: string(path),
+            inf_name: string(inf_name),
+            driver_type: wdi_driver_type::WDI_WINUSB,
+            vendor_name: None,
+            device_guid: None,
+            cert_subject: None,
+            disable_cat: false,
+            disable_signing: false,
+            use_wcid_driver: false,
+            external_inf: false,
+        }
+    } else {
+        let opts = &*options;
+        Call::PrepareDriver {
+            device: seen_device(device_info),
+            path: string(path),
+            inf_name: string(inf_name),
+            driver_type: opts.driver_type,
+            vendor_name: opt_string(opts.vendor_name),
+            device_guid: opt_string(opts.device_guid),
+            cert_subject: opt_string(opts.cert_subject),
+            disable_cat: opts.disable_cat != 0,
+            disable_signing: opts.disable_signing != 0,
+            use_wcid_driver: opts.use_wcid_driver != 0,
+            external_inf: opts.external_inf != 0,
+        }
+    };
+    let Call::PrepareDriver { path, inf_name, driver_type, external_inf, .. } = &call else { unreachable!() };
+    let (path, inf_name) = (path.clone(), inf_name.clone());
+    let code = if !inf_name.ends_with(".inf") {
+        WDI_ERROR_INVALID_PARAM
+    } else if !driver_supported(*driver_type) {
+        WDI_ERROR_NOT_SUPPORTED
+    } else if *external_inf && !inf_exists(&path, &inf_name) {
+        WDI_ERROR_NOT_FOUND
+    } else {
+        WDI_SUCCESS
+    };
+    with_state(|state| state.calls.push(call));
+    if code != WDI_SUCCESS {
+        return code;
+    }
+
+    let code = result(Function::PrepareDriver);
+    if code == WDI_SUCCESS {
+        with_state(|state| state.prepared.insert((path, inf_name)));
+    }
+    code
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_install_driver(
+    device_info: *mut wdi_device_info,
+    path: *const c_char,
+    inf_name: *const c_char,
+    options: *mut wdi_options_install_driver,
+) -> c_int {
+    if device_info.is_null() || path.is_null() || inf_name.is_null() {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+
+    let (hwnd, install_filter_driver, pending_install_timeout) = if options.is_null() {
+        (0, false, 0)
+    } else {
+        let opts = &*options;
+        (opts.hWnd as usize, opts.install_filter_driver != 0, opts.pending_install_timeout)
+    };
+    let (path, inf_name) = (string(path), string(inf_name));
+    let found = inf_exists(&path, &inf_name);
+    let call = Call::InstallDriver {
+        device: seen_device(device_info),
+        path,
+        inf_name,
+        hwnd,
+        install_filter_driver,
+        pending_install_timeout,
+    };
+    with_state(|state| state.calls.push(call));
+    if !found {
+        return WDI_ERROR_NOT_FOUND;
+    }
+
+    result(Function::InstallDriver)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_install_trusted_certificate(cert_name: *const c_char, options: *mut wdi_options_install_cert) -> c_int {
+    if cert_name.is_null() {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+
+    let (hwnd, disable_warning) = if options.is_null() {
+        (0, false)
+    } else {
+        ((*options).hWnd as usize, (*options).disable_warning != 0)
+    };
+    let call = Call::InstallTrustedCertificate {
+        cert_name: string(cert_name),
+        hwnd,
+        disable_warning,
+    };
+    with_state(|state| state.calls.push(call));
+
+    result(Function::InstallTrustedCertificate)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_strerror(errcode: c_int) -> *const c_char {
+    let msg: &'static [u8] = match errcode {
+        WDI_SUCCESS => b"Success\0",
+        WDI_ERROR_NOT_SUPPORTED => b"Operation not supported or unimplemented on this platform\0",
+        _ => b"Fake libwdi error\0",
+    };
+    msg.as_ptr() as *const c_char
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_is_driver_supported(driver_type: c_int, driver_info: *mut VS_FIXEDFILEINFO) -> BOOL {
+    if !driver_info.is_null() {
+        ptr::write_bytes(driver_info, 0, 1);
+    }
+    driver_supported(driver_type) as BOOL
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_is_file_embedded(path: *const c_char, name: *const c_char) -> BOOL {
+    if name.is_null() {
+        return false as BOOL;
+    }
+    let key = (opt_string(path), string(name));
+    with_state(|state| state.embedded_files.contains(&key)) as BOOL
+}
+
+/// Returned pointer is valid until [`reset`] or until the thread exits
+#[no_mangle]
+pub unsafe extern "C" fn wdi_get_vendor_name(vid: c_ushort) -> *const c_char {
+    with_state(|state| state.vendor_names.get(&vid).map_or(ptr::null(), |name| name.as_ptr()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_get_wdf_version() -> c_int {
+    with_state(|state| state.wdf_version.unwrap_or(-1))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_set_log_level(level: c_int) -> c_int {
+    let code = result(Function::SetLogLevel);
+    if code == WDI_SUCCESS {
+        with_state(|state| state.log_level = level);
+    }
+    code
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_register_logger(hwnd: HWND, _message: UINT, _buffsize: DWORD) -> c_int {
+    let code = result(Function::RegisterLogger);
+    if code != WDI_SUCCESS {
+        return code;
+    }
+    let mut logger = LOGGER.lock().unwrap_or_else(|err| err.into_inner());
+    match *logger {
+        Some(other) if other != hwnd as usize => WDI_ERROR_EXISTS,
+        _ => {
+            *logger = Some(hwnd as usize);
+            WDI_SUCCESS
+        },
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wdi_unregister_logger(hwnd: HWND) -> c_int {
+    let code = result(Function::UnregisterLogger);
+    if code != WDI_SUCCESS {
+        return code;
+    }
+    let mut logger = LOGGER.lock().unwrap_or_else(|err| err.into_inner());
+    if *logger != Some(hwnd as usize) {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+    *logger = None;
+    WDI_SUCCESS
+}
+
+/// Returns messages added with [`push_log`], one per call, message_size is 0 if there are none
+#[no_mangle]
+pub unsafe extern "C" fn wdi_read_logger(buffer: *mut c_char, buffer_size: DWORD, message_size: *mut DWORD) -> c_int {
+    if buffer.is_null() || message_size.is_null() {
+        return WDI_ERROR_INVALID_PARAM;
+    }
+    *message_size = 0;
+
+    let code = result(Function::ReadLogger);
+    if code != WDI_SUCCESS {
+        return code;
+    }
+
+    let mut log = LOG.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(message) = log.front() else {
+        return WDI_SUCCESS;
+    };
+    // Message is NUL-terminated, like in libwdi
+    if message.len() + 1 > buffer_size as usize {
+        return WDI_ERROR_OVERFLOW;
+    }
+    ptr::copy_nonoverlapping(message.as_ptr(), buffer as *mut u8, message.len());
+    *buffer.add(message.len()) = 0;
+    *message_size = message.len() as DWORD;
+    log.pop_front();
+    WDI_SUCCESS
+}
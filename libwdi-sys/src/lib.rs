@@ -1,5 +1,53 @@
+//! Raw bindings to [libwdi](https://github.com/pbatard/libwdi)
+//!
+//! By default libwdi is built from the git submodule (MSVC only, for x86, x86_64 or aarch64) and
+//! linked statically. The build is controlled by crate features and environment variables:
+//!
+//! * `dynamic` (with `default-features = false`) links `libwdi.dll` instead, which must then be
+//!   available at runtime. `LIBWDI_STATIC=1`/`LIBWDI_STATIC=0` overrides the linking mode.
+//! * `LIBWDI_LIB_DIR` uses a prebuilt `libwdi.lib` (static library or import library) from the given
+//!   directory instead of building the submodule.
+//! * libwdi is built in Debug or Release configuration following the cargo profile,
+//!   `LIBWDI_BUILD_TYPE=Debug`/`LIBWDI_BUILD_TYPE=Release` overrides it. The Debug configuration links
+//!   the debug C runtime, so Release may be needed in dev builds if the linker reports a conflict.
+//!
+//! libwdi embeds a driver when the directory with its files is defined in its config, so only the
+//! `*_DIR` environment variables below control what is embedded. Features `libusb0`, `libusbk` and
+//! `user-driver` pass their directories (relative paths are resolved from `libwdi/libwdi`). WinUSB
+//! (config.h has a default `WDK_DIR`) and CDC (an INF template) are always embedded when libwdi is
+//! built from the submodule, their features only select `WDK_DIR`. With `LIBWDI_LIB_DIR` the features
+//! cannot change what the library embeds, so they should describe how it has been built.
+//!
+//! | Feature       | Variable      | Required |
+//! |---------------|---------------|----------|
+//! | `winusb`      | `WDK_DIR`     | no       |
+//! | `cdc`         |               |          |
+//! | `libusb0`     | `LIBUSB0_DIR` | yes      |
+//! | `libusbk`     | `LIBUSBK_DIR` | yes      |
+//! | `user-driver` | `USER_DIR`    | yes      |
+//!
+//! On docs.rs, when targeting a platform other than Windows, or on a non-Windows host without
+//! `LIBWDI_LIB_DIR`, the crate is built in stub mode: libwdi is neither compiled nor
+//! linked and the pre-generated bindings from `bindings/libwdi.rs` are used instead of bindgen (also
+//! when cross-compiling with `LIBWDI_LIB_DIR`). `LIBWDI_SYS_STUB=1`/`LIBWDI_SYS_STUB=0` forces stub mode
+//! on or off, `LIBWDI_UPDATE_BINDINGS=1` refreshes the pre-generated bindings when building on Windows.
+
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(feature = "fake")]
+pub mod fake;
+
+/// Whether WinUSB driver files are embedded in libwdi (always when built from the submodule)
+pub const EMBEDDED_WINUSB: bool = cfg!(libwdi_embedded = "winusb");
+/// Whether libusb0 driver files are embedded in libwdi ("libusb0" feature)
+pub const EMBEDDED_LIBUSB0: bool = cfg!(libwdi_embedded = "libusb0");
+/// Whether libusbK driver files are embedded in libwdi ("libusbk" feature)
+pub const EMBEDDED_LIBUSBK: bool = cfg!(libwdi_embedded = "libusbk");
+/// Whether CDC driver INF is available in libwdi (always when built from the submodule)
+pub const EMBEDDED_CDC: bool = cfg!(libwdi_embedded = "cdc");
+/// Whether user driver files are embedded in libwdi ("user-driver" feature)
+pub const EMBEDDED_USER: bool = cfg!(libwdi_embedded = "user-driver");
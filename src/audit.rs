@@ -0,0 +1,349 @@
+use std::{fs, io::Write, path::{Path, PathBuf}, sync::Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::core::DeviceInfo;
+use crate::enums::{Error, Result};
+use crate::package::{DeviceIdentity, PackageOptions};
+
+/// [`AuditLog`] file that cannot be read or verified
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AuditError {
+    #[error("Audit log {file} is invalid at line {line}: {reason}")]
+    Invalid { file: String, line: usize, reason: String },
+}
+
+/// Operation recorded in the [`AuditLog`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    PrepareDriver,
+    InstallDriver,
+    InstallCertificate,
+}
+
+/// What has been done, recorded by the wrappers of libwdi functions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub operation: AuditOperation,
+    pub device: Option<DeviceIdentity>,
+    /// Driver of the device before the operation
+    pub driver_before: Option<String>,
+    /// Driver requested by the operation (service name of the driver type), whether it has been
+    /// installed is told by `success`, the driver of the device is not read back
+    pub driver_requested: Option<String>,
+    pub options: Option<PackageOptions>,
+    pub path: Option<String>,
+    pub inf_name: Option<String>,
+    pub cert_name: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the hash chain, continuous across rotated files
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub user: Option<String>,
+    pub machine: Option<String>,
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    /// Hash of the previous entry, empty for the first one
+    pub prev_hash: String,
+    /// SHA-256 of this entry serialized with an empty `hash`, hex encoded
+    pub hash: String,
+}
+
+/// Append-only JSON Lines log of driver changes with a hash chain, see [`set_audit_log`]
+///
+/// When the file would exceed [`max_size`](Self::max_size) it is rotated: `audit.jsonl` is renamed
+/// to `audit.jsonl.1`, `audit.jsonl.1` to `audit.jsonl.2` and so on, keeping at most
+/// [`max_files`](Self::max_files) old files. The new file starts with an anchor line
+/// `{"anchor":{"seq":…,"hash":…}}` holding the last entry of the previous file, so that the chain
+/// can be verified even after that file has been rotated out. The log should be written by a single
+/// process at a time, e.g. one holding the [`InstallLock`](crate::InstallLock).
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    next_seq: u64,
+    last_hash: String,
+}
+
+/// Summary of a verified audit log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditVerification {
+    pub entries: usize,
+    /// Sequence number of the oldest entry, non-zero if the oldest files have been rotated out
+    pub first_seq: Option<u64>,
+    pub last_hash: Option<String>,
+}
+
+static AUDIT_LOG: Mutex<Option<AuditLog>> = Mutex::new(None);
+
+/// Set the log to which all prepare/install/certificate operations are recorded, `None` disables auditing
+///
+/// Every call is recorded once, also when it fails before reaching libwdi (e.g. invalid options or
+/// the install lock held by another process). Errors writing the log are reported with `log::error!`
+/// and don't change the result of the operation.
+pub fn set_audit_log(log: Option<AuditLog>) -> Option<AuditLog> {
+    std::mem::replace(&mut *AUDIT_LOG.lock().unwrap_or_else(|err| err.into_inner()), log)
+}
+
+/// Append the record to the global audit log, the record is only created if auditing is enabled
+pub(crate) fn record(f: impl FnOnce() -> AuditRecord) {
+    let mut audit = AUDIT_LOG.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(log) = audit.as_mut() {
+        if let Err(err) = log.append(f()) {
+            log::error!("Failed to write audit log: {err}");
+        }
+    }
+}
+
+impl AuditRecord {
+    fn new(operation: AuditOperation, result: &Result<()>) -> Self {
+        Self {
+            operation,
+            device: None,
+            driver_before: None,
+            driver_requested: None,
+            options: None,
+            path: None,
+            inf_name: None,
+            cert_name: None,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(ToString::to_string),
+        }
+    }
+
+    pub(crate) fn driver(
+        operation: AuditOperation,
+        dev: &DeviceInfo,
+        options: &PackageOptions,
+        path: &str,
+        inf_name: &str,
+        result: &Result<()>,
+    ) -> Self {
+        let requested = options.driver_type.metadata().service_name
+            .map(String::from)
+            .unwrap_or_else(|| options.driver_type.to_string());
+        Self {
+            device: Some(DeviceIdentity::of(dev)),
+            driver_before: dev.driver().map(|driver| driver.into_owned()),
+            driver_requested: Some(requested),
+            options: Some(options.clone()),
+            path: Some(path.to_string()),
+            inf_name: Some(inf_name.to_string()),
+            ..Self::new(operation, result)
+        }
+    }
+
+    pub(crate) fn certificate(cert_name: &str, result: &Result<()>) -> Self {
+        Self {
+            cert_name: Some(cert_name.to_string()),
+            ..Self::new(AuditOperation::InstallCertificate, result)
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Last entry of the previous file, written at the start of a file continuing the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Anchor {
+    seq: u64,
+    hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AnchorLine {
+    anchor: Anchor,
+}
+
+/// Entries of a log file and its anchor, if any
+struct LogFile {
+    anchor: Option<Anchor>,
+    entries: Vec<(usize, AuditEntry)>,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> Result<String> {
+        let unhashed = AuditEntry { hash: String::new(), ..self.clone() };
+        Ok(hex(&Sha256::digest(serde_json::to_vec(&unhashed)?)))
+    }
+}
+
+fn env(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+/// Rotated files from the oldest, followed by the current file, skipping the ones that don't exist
+fn log_files(path: &Path) -> Vec<PathBuf> {
+    let rotated = |i: usize| PathBuf::from(format!("{}.{i}", path.display()));
+    let mut files: Vec<_> = (1..).map(rotated).take_while(|file| file.exists()).collect();
+    files.reverse();
+    files.push(path.to_path_buf());
+    files.retain(|file| file.exists());
+    files
+}
+
+fn read_file(file: &Path) -> Result<LogFile> {
+    let mut log = LogFile { anchor: None, entries: vec![] };
+    let lines = fs::read_to_string(file)?;
+    let lines = lines.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    for (i, line) in lines {
+        let fail = |reason: String| Error::from(AuditError::Invalid { file: file.display().to_string(), line: i + 1, reason });
+        if let Ok(AnchorLine { anchor }) = serde_json::from_str(line) {
+            if log.anchor.is_some() || !log.entries.is_empty() {
+                return Err(fail("anchor is not at the start of the file".to_string()));
+            }
+            log.anchor = Some(anchor);
+            continue;
+        }
+        let entry = serde_json::from_str(line).map_err(|err| fail(err.to_string()))?;
+        log.entries.push((i + 1, entry));
+    }
+    Ok(log)
+}
+
+impl AuditLog {
+    /// Open the log at `path`, continuing the hash chain of existing entries
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let last = log_files(&path).iter().rev()
+            .map(|file| read_file(file))
+            .find_map(|log| log.map(|mut log| log.entries.pop()).transpose())
+            .transpose()?;
+        let (next_seq, last_hash) = last.map_or((0, String::new()), |(_, entry)| (entry.seq + 1, entry.hash));
+        Ok(Self { path, max_size: 10 * 1024 * 1024, max_files: 5, next_seq, last_hash })
+    }
+
+    /// Size after which the file is rotated, 10 MiB by default
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Number of rotated files kept, 5 by default
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn rotate(&self) -> Result<()> {
+        let rotated = |i: usize| PathBuf::from(format!("{}.{i}", self.path.display()));
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+        let oldest = rotated(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for i in (1..self.max_files).rev() {
+            if rotated(i).exists() {
+                fs::rename(rotated(i), rotated(i + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(1))?;
+        Ok(())
+    }
+
+    /// Append a record, returns the written entry
+    pub fn append(&mut self, record: AuditRecord) -> Result<AuditEntry> {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let mut entry = AuditEntry {
+            seq: self.next_seq,
+            timestamp_ms,
+            user: env(&["USERNAME", "USER"]),
+            machine: env(&["COMPUTERNAME", "HOSTNAME"]),
+            record,
+            prev_hash: self.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash()?;
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        let mut size = fs::metadata(&self.path).map_or(0, |meta| meta.len());
+        if size > 0 && size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+            size = 0;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        if size == 0 && self.next_seq > 0 {
+            let anchor = Anchor { seq: self.next_seq - 1, hash: self.last_hash.clone() };
+            let mut anchor = serde_json::to_vec(&AnchorLine { anchor })?;
+            anchor.push(b'\n');
+            file.write_all(&anchor)?;
+        }
+        file.write_all(&line)?;
+        file.sync_data()?;
+
+        self.next_seq += 1;
+        self.last_hash = entry.hash.clone();
+        Ok(entry)
+    }
+}
+
+/// Verify the hash chain of the log at `path` including its rotated files
+///
+/// Fails with [`AuditError::Invalid`] pointing at the first entry that has been modified, inserted or
+/// removed, also at the start of the oldest file, whose first entry must follow its anchor.
+///
+/// Some changes cannot be detected:
+/// * entries removed from the end of the current file,
+/// * whole files removed from the oldest ones, which looks the same as rotation,
+/// * consistent rewrites: the chain is not keyed, so anyone able to write the files can recompute
+///   all following hashes and anchors.
+pub fn verify_audit_log(path: impl AsRef<Path>) -> Result<AuditVerification> {
+    let mut verification = AuditVerification { entries: 0, first_seq: None, last_hash: None };
+    // Sequence number and hash of the previous entry
+    let mut prev: Option<Anchor> = None;
+    for file in log_files(path.as_ref()) {
+        let log = read_file(&file)?;
+        let fail = |line: usize, reason: &str| Error::from(AuditError::Invalid {
+            file: file.display().to_string(),
+            line,
+            reason: reason.to_string(),
+        });
+        match (&log.anchor, &prev) {
+            (Some(anchor), Some(prev)) if anchor != prev => return Err(fail(1, "anchor does not match the previous file")),
+            (Some(anchor), None) => prev = Some(anchor.clone()),
+            _ => {},
+        }
+        for (line, entry) in log.entries {
+            if entry.compute_hash()? != entry.hash {
+                return Err(fail(line, "entry does not match its hash"));
+            }
+            match &prev {
+                Some(prev) if entry.prev_hash != prev.hash => {
+                    return Err(fail(line, "previous hash does not match the previous entry"));
+                },
+                Some(prev) if entry.seq != prev.seq + 1 => return Err(fail(line, "sequence number is not continuous")),
+                Some(_) => {},
+                None if entry.seq != 0 => return Err(fail(line, "entry continues a rotated file but there is no anchor")),
+                None if !entry.prev_hash.is_empty() => return Err(fail(line, "first entry has a previous hash")),
+                None => {},
+            }
+            verification.entries += 1;
+            verification.first_seq.get_or_insert(entry.seq);
+            verification.last_hash = Some(entry.hash.clone());
+            prev = Some(Anchor { seq: entry.seq, hash: entry.hash });
+        }
+    }
+    Ok(verification)
+}
@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+use crate::enums::{Error, Result};
+
+/// Encoding used to decode strings returned by libwdi
+///
+/// libwdi converts strings to UTF-8, but some drivers report descriptions in the ANSI code page
+/// of the system, which may then require decoding them with a different code page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodePage {
+    #[default]
+    Utf8,
+    /// Active ANSI code page of the system (only on Windows)
+    Ansi,
+    /// Windows code page identifier, e.g. 1252. On platforms other than Windows only
+    /// 1252 (Western European), 28591 (Latin-1) and 65001 (UTF-8) are supported.
+    Id(u32),
+}
+
+/// String is not valid in the code page used to decode it
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("String is not valid in code page {code_page}")]
+pub struct DecodeError {
+    pub code_page: u32,
+}
+
+const CP_ACP: u32 = 0;
+const CP_UTF8: u32 = 65001;
+#[cfg(not(windows))]
+const CP_LATIN1: u32 = 28591;
+#[cfg(not(windows))]
+const CP_WESTERN: u32 = 1252;
+
+// Characters of code page 1252 in range 0x80..=0x9f, other bytes map to the same Unicode code points
+#[cfg(not(windows))]
+const CP1252_HIGH: [Option<char>; 32] = [
+    Some('\u{20ac}'), None, Some('\u{201a}'), Some('\u{0192}'), Some('\u{201e}'), Some('\u{2026}'), Some('\u{2020}'), Some('\u{2021}'),
+    Some('\u{02c6}'), Some('\u{2030}'), Some('\u{0160}'), Some('\u{2039}'), Some('\u{0152}'), None, Some('\u{017d}'), None,
+    None, Some('\u{2018}'), Some('\u{2019}'), Some('\u{201c}'), Some('\u{201d}'), Some('\u{2022}'), Some('\u{2013}'), Some('\u{2014}'),
+    Some('\u{02dc}'), Some('\u{2122}'), Some('\u{0161}'), Some('\u{203a}'), Some('\u{0153}'), None, Some('\u{017e}'), Some('\u{0178}'),
+];
+
+impl CodePage {
+    fn id(self) -> u32 {
+        match self {
+            CodePage::Utf8 => CP_UTF8,
+            CodePage::Ansi => CP_ACP,
+            CodePage::Id(id) => id,
+        }
+    }
+
+    /// Decode bytes, fails if they are not valid in this code page
+    pub fn decode(self, bytes: &[u8]) -> Result<Cow<'_, str>> {
+        match self.id() {
+            CP_UTF8 => Ok(Cow::Borrowed(std::str::from_utf8(bytes)?)),
+            // Pure ASCII is the same in all supported code pages
+            _ if bytes.is_ascii() => Ok(Cow::Borrowed(std::str::from_utf8(bytes)?)),
+            id => decode(id, bytes).map(Cow::Owned),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn decode(code_page: u32, bytes: &[u8]) -> Result<String> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn MultiByteToWideChar(
+            code_page: u32,
+            flags: u32,
+            multi_byte: *const u8,
+            multi_byte_len: i32,
+            wide: *mut u16,
+            wide_len: i32,
+        ) -> i32;
+    }
+    const MB_ERR_INVALID_CHARS: u32 = 0x8;
+
+    let len = i32::try_from(bytes.len()).map_err(|_| Error::Overflow)?;
+    // First call returns the required buffer size, 0 means an error (e.g. invalid code page or characters)
+    let wide_len = unsafe {
+        MultiByteToWideChar(code_page, MB_ERR_INVALID_CHARS, bytes.as_ptr(), len, std::ptr::null_mut(), 0)
+    };
+    if wide_len <= 0 {
+        return Err(DecodeError { code_page }.into());
+    }
+    let mut wide = vec![0u16; wide_len as usize];
+    let written = unsafe {
+        MultiByteToWideChar(code_page, MB_ERR_INVALID_CHARS, bytes.as_ptr(), len, wide.as_mut_ptr(), wide_len)
+    };
+    if written <= 0 {
+        return Err(DecodeError { code_page }.into());
+    }
+    wide.truncate(written as usize);
+    String::from_utf16(&wide).map_err(|_| DecodeError { code_page }.into())
+}
+
+#[cfg(not(windows))]
+fn decode(code_page: u32, bytes: &[u8]) -> Result<String> {
+    match code_page {
+        CP_LATIN1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+        CP_WESTERN => bytes.iter()
+            .map(|&b| match b {
+                0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+                _ => Some(b as char),
+            })
+            .collect::<Option<String>>()
+            .ok_or(DecodeError { code_page }.into()),
+        _ => Err(Error::NotSupported),
+    }
+}
@@ -0,0 +1,79 @@
+use std::ops::Deref;
+
+use crate::core::{DeviceInfo, DevicesList};
+
+/// Interfaces of a single composite device, listed by libwdi as separate devices
+///
+/// `D` is `&DeviceInfo` for reading or `&mut DeviceInfo` for preparing/installing drivers,
+/// but any type dereferencing to [`DeviceInfo`] (e.g. [`DeviceDescriptor`](crate::DeviceDescriptor)) can be grouped.
+#[derive(Debug)]
+pub struct CompositeDevice<D> {
+    pub vid: u16,
+    pub pid: u16,
+    /// Instance ID part shared by all interfaces of the device, if device IDs are available
+    pub parent: Option<String>,
+    /// Interfaces ordered by interface number
+    pub interfaces: Vec<D>,
+}
+
+impl<D: Deref<Target = DeviceInfo>> CompositeDevice<D> {
+    /// Interface with given number
+    pub fn interface(&self, mi: u8) -> Option<&D> {
+        self.interfaces.iter().find(|dev| dev.raw().mi == mi)
+    }
+
+    fn accepts(&self, dev: &DeviceInfo, parent: &Option<String>) -> bool {
+        // Interfaces with the same number belong to different devices with the same vid/pid
+        (self.vid, self.pid) == (dev.vid(), dev.pid())
+            && self.parent == *parent
+            && self.interface(dev.raw().mi).is_none()
+    }
+}
+
+/// Parent part of an interface instance ID, e.g. `6&2A1B3C4D&0` in `USB\VID_1234&PID_0001&MI_00\6&2A1B3C4D&0&0000`
+pub(crate) fn parent_instance(device_id: &str) -> Option<String> {
+    let (_, instance) = device_id.rsplit_once('\\')?;
+    let (parent, _) = instance.rsplit_once('&')?;
+    Some(parent.to_ascii_uppercase())
+}
+
+/// Group interfaces of composite devices by vid/pid and parent instance, ignoring other devices
+///
+/// Devices are returned in the order in which their first interface appears. Without device IDs
+/// two identical devices can only be told apart when they expose the same interface numbers.
+pub fn group_composite<D, I>(devices: I) -> Vec<CompositeDevice<D>>
+where
+    D: Deref<Target = DeviceInfo>,
+    I: IntoIterator<Item = D>,
+{
+    let mut groups: Vec<CompositeDevice<D>> = vec![];
+    for dev in devices.into_iter().filter(|dev| dev.is_composite()) {
+        let parent = dev.device_id().and_then(|id| parent_instance(&id));
+        match groups.iter_mut().find(|group| group.accepts(&dev, &parent)) {
+            Some(group) => group.interfaces.push(dev),
+            None => groups.push(CompositeDevice {
+                vid: dev.vid(),
+                pid: dev.pid(),
+                parent,
+                interfaces: vec![dev],
+            }),
+        }
+    }
+    for group in groups.iter_mut() {
+        group.interfaces.sort_by_key(|dev| dev.raw().mi);
+    }
+    groups
+}
+
+impl DevicesList {
+    /// Composite devices with their interfaces, see [`group_composite`]
+    pub fn composite_devices(&self) -> Vec<CompositeDevice<&DeviceInfo>> {
+        group_composite(self.iter())
+    }
+
+    /// Composite devices with interfaces that can be passed to libwdi, e.g. to install a driver
+    /// for all interfaces of a device
+    pub fn composite_devices_mut(&mut self) -> Vec<CompositeDevice<&mut DeviceInfo>> {
+        group_composite(self.iter_mut())
+    }
+}
@@ -1,37 +1,84 @@
-use std::{ffi, num::{NonZeroU8, NonZeroU64}, borrow::Cow, ptr};
+use std::{ffi, num::{NonZeroU8, NonZeroU64}, borrow::Cow, ptr, marker::PhantomData, os::raw::c_char};
 
-use libwdi_sys as wdi;
+use crate::ffi as wdi;
 
-use crate::enums::{check_error, Error, Result, DriverType};
+use crate::audit::{self, AuditOperation, AuditRecord};
+use crate::codepage::CodePage;
+use crate::descriptor::DeviceDescriptor;
+use crate::enums::{check_error, DriverError, Error, Result, DriverType, DriverOption};
+use crate::install_lock::{InstallLock, InstallLockOptions};
+use crate::lock::WdiLock;
+use crate::package::{DeviceIdentity, PackageOptions, PreparedPackage};
+use crate::progress::{self, Phase, ProgressObserver};
+use crate::window::WindowHandle;
 
 /// Builder of options for wdi_create_list
 #[derive(Clone)]
-pub struct CreateListOptions(wdi::wdi_options_create_list);
+pub struct CreateListOptions(pub(crate) wdi::wdi_options_create_list);
 
 /// List of information about USB devices optained from wdi_create_list
-pub struct DevicesList(*mut wdi::wdi_device_info);
+pub struct DevicesList {
+    head: *mut wdi::wdi_device_info,
+    options: CreateListOptions,
+    backing: Backing,
+}
+
+enum Backing {
+    Libwdi,
+    /// Devices allocated by the wrapper, e.g. when replaying a fixture
+    Owned(Vec<*mut DeviceDescriptor>),
+}
 
 /// Iterator over devices in the list
-pub struct DevicesIter<'a>(Option<DeviceInfo<'a>>);
+pub struct DevicesIter<'a> {
+    next: *const wdi::wdi_device_info,
+    _list: PhantomData<&'a DevicesList>,
+}
+
+/// Iterator over devices in the list allowing to modify them, e.g. to prepare/install a driver
+pub struct DevicesIterMut<'a> {
+    next: *mut wdi::wdi_device_info,
+    _list: PhantomData<&'a mut DevicesList>,
+}
 
 /// Information related to device installation
-pub struct DeviceInfo<'a>(&'a mut wdi::wdi_device_info);
+///
+/// This is a view of an element of [`DevicesList`], accessed through `&DeviceInfo` for reading
+/// or `&mut DeviceInfo` when passing it to libwdi functions. It is unsized, so the element itself
+/// cannot be moved out of the list, e.g. swapping two devices would corrupt the links of the list:
+/// ```compile_fail
+/// # let mut list = libwdi::CreateListOptions::new().create_list().unwrap();
+/// let mut devices = list.iter_mut();
+/// let (a, b) = (devices.next().unwrap(), devices.next().unwrap());
+/// std::mem::swap(a, b);
+/// ```
+#[repr(transparent)]
+pub struct DeviceInfo([wdi::wdi_device_info]);
 
 /// Builder of options for wdi_prepare_driver
 #[derive(Clone)]
 pub struct PrepareDriverOptions {
     opts: wdi::wdi_options_prepare_driver,
+    driver_type: DriverType,
     vendor_name: Option<ffi::CString>,
     device_guid: Option<ffi::CString>,
     cert_subject: Option<ffi::CString>,
+    install_lock: Option<InstallLockOptions>,
+    /// Disabled for intermediate steps of operations recorded as a whole
+    audit: bool,
 }
 
 /// Driver files prepared to be installed using wdi_install_driver
 pub struct PreparedDriver<'a> {
-    dev: DeviceInfo<'a>,
+    dev: &'a mut DeviceInfo,
     path: ffi::CString,
     inf_name: ffi::CString,
     options: wdi::wdi_options_install_driver,
+    prepared: PackageOptions,
+    install_lock: Option<InstallLockOptions>,
+    /// Acquired when preparing the driver, held until it is installed
+    held_lock: Option<InstallLock>,
+    audit: bool,
 }
 
 /// Builder of options for wdi_install_trusted_certificate
@@ -48,6 +95,26 @@ macro_rules! impl_builder_bool {
     };
 }
 
+macro_rules! impl_string_accessors {
+    ($( $field:ident, $bytes:ident, $try_field:ident );+ $(;)?) => {
+        $(
+            pub fn $field(&self) -> Option<Cow<'_, str>> {
+                self.opt_string(self.raw().$field)
+            }
+
+            /// Raw bytes of the string (without the terminating NUL)
+            pub fn $bytes(&self) -> Option<&[u8]> {
+                self.opt_bytes(self.raw().$field)
+            }
+
+            /// Fails if the string is not valid UTF-8
+            pub fn $try_field(&self) -> Result<Option<&str>> {
+                self.try_opt_str(self.raw().$field)
+            }
+         )+
+    };
+}
+
 impl Default for CreateListOptions {
     fn default() -> Self {
         Self::new()
@@ -74,23 +141,74 @@ impl CreateListOptions {
 impl DevicesList {
     fn new(mut options: CreateListOptions) -> Result<Self> {
         let mut list: *mut wdi::wdi_device_info = ptr::null_mut();
+        let _lock = WdiLock::acquire()?;
         unsafe {
             check_error(wdi::wdi_create_list(&mut list, &mut options.0))?;
         }
-        if list.is_null() {
+        // libwdi never returns an empty list, but the stub implementation always does
+        if list.is_null() && !wdi::STUB {
             return Err(Error::Internal)
         }
-        Ok(Self(list))
+        Ok(Self { head: list, options, backing: Backing::Libwdi })
     }
 
-    pub fn iter(&self) -> DevicesIter {
-        DevicesIter(if self.0.is_null() {
-            None
-        } else {
-            Some(unsafe {
-                DeviceInfo::new(&mut *self.0)
-            })
-        })
+    /// List of devices not obtained from libwdi, `options` describe how it has been created
+    pub(crate) fn from_descriptors(options: CreateListOptions, devices: Vec<DeviceDescriptor>) -> Self {
+        // Raw pointers keep the descriptors at fixed addresses without asserting unique access
+        let owned: Vec<_> = devices.into_iter().map(|dev| Box::into_raw(Box::new(dev))).collect();
+        for pair in owned.windows(2) {
+            // Safety: pointers come from Box::into_raw and are not aliased
+            unsafe { (*pair[0]).info.next = &mut (*pair[1]).info }
+        }
+        let head = owned.first().map_or(ptr::null_mut(), |&dev| unsafe { &mut (*dev).info as *mut _ });
+        Self { head, options, backing: Backing::Owned(owned) }
+    }
+
+    /// Options with which the list has been created
+    pub fn options(&self) -> &CreateListOptions {
+        &self.options
+    }
+
+    /// Iterate over devices for reading their information
+    pub fn iter(&self) -> DevicesIter<'_> {
+        DevicesIter {
+            next: self.head,
+            _list: PhantomData,
+        }
+    }
+
+    /// Iterate over devices with exclusive access, as required to prepare/install drivers.
+    ///
+    /// Devices cannot be read using [`DevicesList::iter`] while obtained from this iterator:
+    /// ```compile_fail
+    /// # let mut list = libwdi::CreateListOptions::new().create_list().unwrap();
+    /// let dev = list.iter_mut().next().unwrap();
+    /// let same = list.iter().next().unwrap();
+    /// libwdi::PrepareDriverOptions::new().prepare_driver(dev, "dir", "device.inf").ok();
+    /// ```
+    pub fn iter_mut(&mut self) -> DevicesIterMut<'_> {
+        DevicesIterMut {
+            next: self.head,
+            _list: PhantomData,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a DevicesList {
+    type Item = &'a DeviceInfo;
+    type IntoIter = DevicesIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut DevicesList {
+    type Item = &'a mut DeviceInfo;
+    type IntoIter = DevicesIterMut<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
     }
 }
 
@@ -100,110 +218,161 @@ unsafe impl Send for DevicesList {}
 
 impl Drop for DevicesList {
     fn drop(&mut self) {
-        unsafe {
-            check_error(wdi::wdi_destroy_list(self.0)).unwrap();
+        match &self.backing {
+            Backing::Libwdi => {
+                let _lock = WdiLock::acquire_blocking();
+                let result = unsafe { check_error(wdi::wdi_destroy_list(self.head)) };
+                if let Err(err) = result {
+                    log::error!("Failed to destroy devices list: {err}");
+                }
+            },
+            Backing::Owned(devices) => {
+                for &dev in devices {
+                    // Safety: pointers come from Box::into_raw and are freed only here
+                    drop(unsafe { Box::from_raw(dev) });
+                }
+            },
         }
     }
 }
 
 impl<'a> Iterator for DevicesIter<'a> {
-    type Item = DeviceInfo<'a>;
+    type Item = &'a DeviceInfo;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(mut info) = self.0.take() {
-            let next = info.next();
-            let item = Some(info);
-            self.0 = next;
-            item
-        } else {
-            None
+        if self.next.is_null() {
+            return None;
+        }
+        // Safety: the list lives for 'a and is only borrowed immutably
+        unsafe {
+            let info = DeviceInfo::from_ptr(self.next);
+            self.next = info.raw().next;
+            Some(info)
+        }
+    }
+}
+
+impl<'a> Iterator for DevicesIterMut<'a> {
+    type Item = &'a mut DeviceInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        // Safety: the list is borrowed mutably for 'a and each element is yielded only once.
+        // Pointer to the next element is read before creating the reference, so that the
+        // iterator never accesses an element that has already been handed out.
+        unsafe {
+            let current = self.next;
+            self.next = (*current).next;
+            Some(DeviceInfo::from_mut_ptr(current))
         }
     }
 }
 
-impl<'a> DeviceInfo<'a> {
-    fn new(info: &'a mut wdi::wdi_device_info) -> Self {
-        Self(info)
+impl DeviceInfo {
+    /// View of the device at `info`
+    ///
+    /// Safety: `info` must be valid for `'a` and must not be modified during that time
+    pub(crate) unsafe fn from_ptr<'a>(info: *const wdi::wdi_device_info) -> &'a Self {
+        &*(ptr::slice_from_raw_parts(info, 1) as *const Self)
+    }
+
+    /// Exclusive view of the device at `info`
+    ///
+    /// Safety: `info` must be valid for `'a` and must not be accessed otherwise during that time
+    pub(crate) unsafe fn from_mut_ptr<'a>(info: *mut wdi::wdi_device_info) -> &'a mut Self {
+        &mut *(ptr::slice_from_raw_parts_mut(info, 1) as *mut Self)
+    }
+
+    /// Fields of the device, which are never handed out mutably so that the list stays intact
+    pub(crate) fn raw(&self) -> &wdi::wdi_device_info {
+        &self.0[0]
+    }
+
+    /// Pointer passed to libwdi functions
+    fn as_mut_ptr(&mut self) -> *mut wdi::wdi_device_info {
+        self.0.as_mut_ptr()
     }
 
     pub fn vid(&self) -> u16 {
-        self.0.vid
+        self.raw().vid
     }
 
     pub fn pid(&self) -> u16 {
-        self.0.pid
+        self.raw().pid
     }
 
     pub fn is_composite(&self) -> bool {
-        self.0.is_composite != 0
+        self.raw().is_composite != 0
     }
 
     pub fn mi(&self) -> Option<NonZeroU8> {
-        NonZeroU8::new(self.0.mi)
+        NonZeroU8::new(self.raw().mi)
     }
 
     pub fn driver_version(&self) -> Option<NonZeroU64> {
-        NonZeroU64::new(self.0.driver_version)
+        NonZeroU64::new(self.raw().driver_version)
     }
 
-    fn opt_string(&self, s: *const std::os::raw::c_char) -> Option<Cow<'_, str>> {
+    fn opt_bytes(&self, s: *const c_char) -> Option<&[u8]> {
         if s.is_null() {
             None
         } else {
             Some(unsafe {
-                ffi::CStr::from_ptr(s).to_string_lossy()
+                ffi::CStr::from_ptr(s).to_bytes()
             })
         }
     }
 
-    pub fn desc(&self) -> Cow<'_, str> {
-        assert!(!self.0.desc.is_null());
-        unsafe {
-            ffi::CStr::from_ptr(self.0.desc).to_string_lossy()
-        }
+    fn opt_string(&self, s: *const c_char) -> Option<Cow<'_, str>> {
+        self.opt_bytes(s).map(String::from_utf8_lossy)
     }
 
-    pub fn driver(&self) -> Option<Cow<'_, str>> {
-        self.opt_string(self.0.driver)
+    fn try_opt_str(&self, s: *const c_char) -> Result<Option<&str>> {
+        Ok(self.opt_bytes(s).map(std::str::from_utf8).transpose()?)
     }
 
-    pub fn device_id(&self) -> Option<Cow<'_, str>> {
-        self.opt_string(self.0.device_id)
+    /// Device description, invalid UTF-8 is replaced and missing description is empty
+    pub fn desc(&self) -> Cow<'_, str> {
+        self.opt_string(self.raw().desc).unwrap_or_default()
     }
 
-    pub fn hardware_id(&self) -> Option<Cow<'_, str>> {
-        self.opt_string(self.0.hardware_id)
+    /// Raw bytes of the description, `None` only if libwdi broke its contract or for a replayed
+    /// [`ListFixture`](crate::ListFixture) of such a device
+    pub fn desc_bytes(&self) -> Option<&[u8]> {
+        self.opt_bytes(self.raw().desc)
     }
 
-    pub fn compatible_id(&self) -> Option<Cow<'_, str>> {
-        self.opt_string(self.0.compatible_id)
+    /// Fails if the description is missing or is not valid UTF-8
+    pub fn try_desc(&self) -> Result<&str> {
+        self.try_opt_str(self.raw().desc)?.ok_or(Error::Internal)
     }
 
-    pub fn upper_filter(&self) -> Option<Cow<'_, str>> {
-        self.opt_string(self.0.upper_filter)
+    /// Decode the description using given code page, e.g. [`CodePage::Ansi`]
+    /// for drivers that don't report their descriptions in UTF-8
+    pub fn desc_with(&self, code_page: CodePage) -> Result<Cow<'_, str>> {
+        code_page.decode(self.desc_bytes().ok_or(Error::Internal)?)
     }
-}
-
-impl<'a> Iterator for DeviceInfo<'a> {
-    type Item = DeviceInfo<'a>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.0.next.is_null() {
-            None
-        } else {
-            Some(unsafe {
-                Self::new(&mut *self.0.next)
-            })
-        }
-    }
+    impl_string_accessors!(
+        driver, driver_bytes, try_driver;
+        device_id, device_id_bytes, try_device_id;
+        hardware_id, hardware_id_bytes, try_hardware_id;
+        compatible_id, compatible_id_bytes, try_compatible_id;
+        upper_filter, upper_filter_bytes, try_upper_filter;
+    );
 }
 
 impl PrepareDriverOptions {
     pub fn new() -> Self {
         Self {
+            driver_type: DriverType::WinUsb,
             vendor_name: None,
             device_guid: None,
             cert_subject: None,
+            install_lock: None,
+            audit: true,
             opts: wdi::wdi_options_prepare_driver {
                 driver_type: wdi::wdi_driver_type::WDI_WINUSB,
                 vendor_name: ptr::null_mut(),
@@ -220,6 +389,7 @@ impl PrepareDriverOptions {
     impl_builder_bool!(opts: disable_cat, disable_signing, use_wcid_driver, external_inf);
 
     pub fn driver_type(mut self, typ: DriverType) -> Self {
+        self.driver_type = typ;
         self.opts.driver_type = typ.to_ffi();
         self
     }
@@ -239,37 +409,116 @@ impl PrepareDriverOptions {
         Ok(self)
     }
 
+    /// Hold the cross-process [`InstallLock`] from preparing the driver until it is installed
+    /// (or the [`PreparedDriver`] is dropped)
+    pub fn install_lock(mut self, options: InstallLockOptions) -> Self {
+        self.install_lock = Some(options);
+        self
+    }
+
+    pub(crate) fn audit(mut self, audit: bool) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Record failure of an operation that did not reach wdi_prepare_driver with these options,
+    /// e.g. invalid options or the install lock held by another process
+    pub(crate) fn audit_failure(&self, dev: &DeviceInfo, path: &str, inf_name: &str, err: Error) -> Error {
+        if self.audit {
+            let result = Err(err.clone());
+            audit::record(|| AuditRecord::driver(AuditOperation::PrepareDriver, dev, &self.package_options(), path, inf_name, &result));
+        }
+        err
+    }
+
+    fn package_options(&self) -> PackageOptions {
+        let string = |s: &Option<ffi::CString>| s.as_ref().map(|s| s.to_string_lossy().into_owned());
+        PackageOptions {
+            driver_type: self.driver_type,
+            vendor_name: string(&self.vendor_name),
+            device_guid: string(&self.device_guid),
+            cert_subject: string(&self.cert_subject),
+            disable_cat: self.opts.disable_cat != 0,
+            disable_signing: self.opts.disable_signing != 0,
+            use_wcid_driver: self.opts.use_wcid_driver != 0,
+            external_inf: self.opts.external_inf != 0,
+        }
+    }
+
+    /// Fails if the selected driver type is not embedded or if some options are not supported by it
+    pub fn validate(&self) -> Result<()> {
+        // The stub reports all driver operations as not supported on its own
+        if !self.driver_type.is_embedded() && !wdi::STUB {
+            return Err(DriverError::NotEmbedded(self.driver_type).into());
+        }
+        if self.device_guid.is_some() {
+            self.driver_type.check_option(DriverOption::DeviceGuid)?;
+        }
+        if self.opts.use_wcid_driver != 0 {
+            self.driver_type.check_option(DriverOption::UseWcidDriver)?;
+        }
+        Ok(())
+    }
+
     #[doc(alias = "wdi_prepare_driver")]
-    pub fn prepare_driver<'a>(mut self, dev: DeviceInfo<'a>, path: &str, inf_name: &str) -> Result<PreparedDriver<'a>> {
-        let path = ffi::CString::new(path)?;
-        let inf_name = ffi::CString::new(inf_name)?;
+    pub fn prepare_driver<'a>(self, dev: &'a mut DeviceInfo, path: &str, inf_name: &str) -> Result<PreparedDriver<'a>> {
+        let held_lock = match self.install_lock.as_ref().map(InstallLockOptions::acquire).transpose() {
+            Ok(held_lock) => held_lock,
+            Err(err) => return Err(self.audit_failure(dev, path, inf_name, err)),
+        };
+        self.prepare_driver_locked(dev, path, inf_name, held_lock)
+    }
+
+    /// Validate options and prepare the driver with the install lock already acquired by the caller
+    pub(crate) fn prepare_driver_locked<'a>(mut self, dev: &'a mut DeviceInfo, path: &str, inf_name: &str, held_lock: Option<InstallLock>) -> Result<PreparedDriver<'a>> {
+        let strings = self.validate().and_then(|()| Ok((ffi::CString::new(path)?, ffi::CString::new(inf_name)?)));
+        let (path, inf_name) = match strings {
+            Ok(strings) => strings,
+            Err(err) => return Err(self.audit_failure(dev, path, inf_name, err)),
+        };
 
         // If some optional strings have been provided, pass them in opts.
         // Safety: they should remain valid until after wdi_prepare_driver returns (until self is dropped),
         // if wdi_prepare_driver modifies these strings than we're screwed (but it shouldn't)
         if let Some(s) = &mut self.vendor_name {
-            self.opts.vendor_name = s.as_ptr() as *mut i8;
+            self.opts.vendor_name = s.as_ptr() as *mut _;
         }
         if let Some(s) = &mut self.device_guid {
-            self.opts.device_guid = s.as_ptr() as *mut i8;
+            self.opts.device_guid = s.as_ptr() as *mut _;
         }
         if let Some(s) = &mut self.cert_subject {
-            self.opts.cert_subject = s.as_ptr() as *mut i8;
+            self.opts.cert_subject = s.as_ptr() as *mut _;
         }
 
-        unsafe {
-            check_error(wdi::wdi_prepare_driver(&mut *dev.0, path.as_ptr(), inf_name.as_ptr(), &mut self.opts))?;
-        }
+        let result = WdiLock::acquire().and_then(|_lock| unsafe {
+            check_error(wdi::wdi_prepare_driver(dev.as_mut_ptr(), path.as_ptr(), inf_name.as_ptr(), &mut self.opts))
+        });
 
         // Make sure that self stays valid until now
+        let prepared = self.package_options();
+        let install_lock = self.install_lock.take();
+        let audited = self.audit;
         drop(self);
 
-        Ok(PreparedDriver {
-            dev,
-            path,
-            inf_name,
-            options: PreparedDriver::DEFAULT_OPTIONS
-        })
+        if audited {
+            audit::record(|| AuditRecord::driver(
+                AuditOperation::PrepareDriver, dev, &prepared, &path.to_string_lossy(), &inf_name.to_string_lossy(), &result,
+            ));
+        }
+        result?;
+        let mut driver = PreparedDriver::new(dev, path, inf_name, prepared);
+        driver.install_lock = install_lock;
+        driver.held_lock = held_lock;
+        driver.audit = audited;
+        Ok(driver)
+    }
+
+    /// Same as [`prepare_driver`](Self::prepare_driver) but reports progress to `observer`
+    pub fn prepare_driver_with_progress<'a, O>(self, dev: &'a mut DeviceInfo, path: &str, inf_name: &str, observer: &mut O) -> Result<PreparedDriver<'a>>
+    where
+        O: ProgressObserver + Send,
+    {
+        progress::run(Phase::ExtractingFiles, observer, || self.prepare_driver(dev, path, inf_name))
     }
 }
 
@@ -281,24 +530,97 @@ impl Default for PrepareDriverOptions {
 
 impl<'a> PreparedDriver<'a> {
     const DEFAULT_OPTIONS: wdi::wdi_options_install_driver = wdi::wdi_options_install_driver {
-        // TODO: try obtaining it using "windows" crate https://stackoverflow.com/a/2620522
         hWnd: ptr::null_mut(),
         install_filter_driver: false as wdi::BOOL,
         pending_install_timeout: 0,
     };
 
+    pub(crate) fn new(dev: &'a mut DeviceInfo, path: ffi::CString, inf_name: ffi::CString, prepared: PackageOptions) -> Self {
+        Self {
+            dev,
+            path,
+            inf_name,
+            options: Self::DEFAULT_OPTIONS,
+            prepared,
+            install_lock: None,
+            held_lock: None,
+            audit: true,
+        }
+    }
+
+    /// Hold the cross-process [`InstallLock`] while installing, unless it has already been
+    /// acquired by [`PrepareDriverOptions::install_lock`]
+    pub fn install_lock(mut self, options: InstallLockOptions) -> Self {
+        self.install_lock = Some(options);
+        self
+    }
+
+    /// Take the install lock acquired when preparing the driver
+    pub(crate) fn take_held_lock(&mut self) -> Option<InstallLock> {
+        self.held_lock.take()
+    }
+
     impl_builder_bool!(options: install_filter_driver);
 
+    /// Owned description of the prepared files, which can be saved and installed later
+    pub fn package(&self) -> PreparedPackage {
+        PreparedPackage::new(
+            self.path.to_string_lossy().into_owned(),
+            self.inf_name.to_string_lossy().into_owned(),
+            DeviceIdentity::of(self.dev),
+            self.prepared.clone(),
+        )
+    }
+
+    /// Parent window of dialogs shown during installation, e.g. [`WindowHandle::console`]
+    pub fn hwnd(mut self, hwnd: impl Into<WindowHandle>) -> Self {
+        self.options.hWnd = hwnd.into().as_raw();
+        self
+    }
+
     pub fn pending_install_timeout(mut self, timeout: u32) -> Self {
         self.options.pending_install_timeout = timeout;
         self
     }
 
+    /// Fails if some options are not supported by the driver type used to prepare the driver
+    pub fn validate(&self) -> Result<()> {
+        if self.options.install_filter_driver != 0 {
+            self.prepared.driver_type.check_option(DriverOption::InstallFilterDriver)?;
+        }
+        Ok(())
+    }
+
     #[doc(alias = "wdi_install_driver")]
     pub fn install_driver(mut self) -> Result<()> {
-        unsafe {
-            check_error(wdi::wdi_install_driver(self.dev.0, self.path.as_ptr(), self.inf_name.as_ptr(), &mut self.options))
+        let result = self.validate()
+            .and_then(|()| match self.held_lock.take() {
+                Some(lock) => Ok(Some(lock)),
+                None => self.install_lock.as_ref().map(InstallLockOptions::acquire).transpose(),
+            })
+            .and_then(|_install_lock| {
+                let _lock = WdiLock::acquire()?;
+                unsafe {
+                    check_error(wdi::wdi_install_driver(self.dev.as_mut_ptr(), self.path.as_ptr(), self.inf_name.as_ptr(), &mut self.options))
+                }
+            });
+
+        // Driver before the installation is recorded, libwdi doesn't update the device information
+        if self.audit {
+            audit::record(|| AuditRecord::driver(
+                AuditOperation::InstallDriver, self.dev, &self.prepared,
+                &self.path.to_string_lossy(), &self.inf_name.to_string_lossy(), &result,
+            ));
         }
+        result
+    }
+
+    /// Same as [`install_driver`](Self::install_driver) but reports progress to `observer`
+    pub fn install_driver_with_progress<O>(self, observer: &mut O) -> Result<()>
+    where
+        O: ProgressObserver + Send,
+    {
+        progress::run(Phase::Installing, observer, || self.install_driver())
     }
 }
 
@@ -312,16 +634,22 @@ impl InstallCertOptions {
 
     impl_builder_bool!(0: disable_warning);
 
-    pub fn hwnd(mut self, hwnd: wdi::HWND) -> Self {
-        self.0.hWnd = hwnd;
+    /// Parent window of the certificate prompt, e.g. [`WindowHandle::console`]
+    pub fn hwnd(mut self, hwnd: impl Into<WindowHandle>) -> Self {
+        self.0.hWnd = hwnd.into().as_raw();
         self
     }
 
     pub fn install_trusted_certificate(mut self, cert_name: &str) -> Result<()> {
-        let cert_name = ffi::CString::new(cert_name)?;
-        unsafe {
-            check_error(wdi::wdi_install_trusted_certificate(cert_name.as_ptr(), &mut self.0))
-        }
+        let result = ffi::CString::new(cert_name).map_err(Error::from).and_then(|c_cert_name| {
+            let _lock = WdiLock::acquire()?;
+            unsafe {
+                check_error(wdi::wdi_install_trusted_certificate(c_cert_name.as_ptr(), &mut self.0))
+            }
+        });
+
+        audit::record(|| AuditRecord::certificate(cert_name, &result));
+        result
     }
 }
 
@@ -0,0 +1,278 @@
+use std::{ffi, ops::{Deref, DerefMut}, os::raw::c_char, ptr};
+
+use crate::ffi as wdi;
+
+use crate::core::DeviceInfo;
+use crate::enums::Result;
+
+/// Device owning its information, created by the user or copied from a listed device
+///
+/// It can describe a device that is not connected, e.g. to pre-install a driver (like
+/// "Create New Device" in Zadig), or a listed device with modified fields, e.g. to set the
+/// device name written to the INF file. Dereferences to [`DeviceInfo`], so it can be passed to
+/// [`PrepareDriverOptions::prepare_driver`](crate::PrepareDriverOptions::prepare_driver).
+///
+/// The [`DeviceInfo`] points at strings owned by the descriptor, so it cannot be swapped with
+/// the one of another descriptor:
+/// ```compile_fail
+/// let mut a = libwdi::DeviceDescriptor::builder(0x1234, 0x0001).build().unwrap();
+/// let mut b = libwdi::DeviceDescriptor::builder(0x1234, 0x0002).build().unwrap();
+/// std::mem::swap(&mut *a, &mut *b);
+/// ```
+pub struct DeviceDescriptor {
+    pub(crate) info: wdi::wdi_device_info,
+    // Strings referenced by info, their buffers don't move when DeviceDescriptor is moved
+    desc: Option<ffi::CString>,
+    driver: Option<ffi::CString>,
+    device_id: Option<ffi::CString>,
+    hardware_id: Option<ffi::CString>,
+    compatible_id: Option<ffi::CString>,
+    upper_filter: Option<ffi::CString>,
+}
+
+/// Builder of [`DeviceDescriptor`]
+#[derive(Debug, Clone)]
+pub struct DeviceDescriptorBuilder {
+    vid: u16,
+    pid: u16,
+    mi: u8,
+    is_composite: bool,
+    desc: String,
+    hardware_id: Option<String>,
+    compatible_id: Option<String>,
+}
+
+fn opt_ptr(s: &Option<ffi::CString>) -> *mut c_char {
+    s.as_ref().map_or(ptr::null_mut(), |s| s.as_ptr() as *mut _)
+}
+
+fn opt_cstring(s: Option<&str>) -> Result<Option<ffi::CString>> {
+    Ok(s.map(ffi::CString::new).transpose()?)
+}
+
+impl DeviceDescriptor {
+    pub fn builder(vid: u16, pid: u16) -> DeviceDescriptorBuilder {
+        DeviceDescriptorBuilder {
+            vid,
+            pid,
+            mi: 0,
+            is_composite: false,
+            desc: "USB Device".to_string(),
+            hardware_id: None,
+            compatible_id: None,
+        }
+    }
+
+    // Point info at the owned strings, must be called after any of them changes
+    fn update_ptrs(&mut self) {
+        self.info.desc = opt_ptr(&self.desc);
+        self.info.driver = opt_ptr(&self.driver);
+        self.info.device_id = opt_ptr(&self.device_id);
+        self.info.hardware_id = opt_ptr(&self.hardware_id);
+        self.info.compatible_id = opt_ptr(&self.compatible_id);
+        self.info.upper_filter = opt_ptr(&self.upper_filter);
+    }
+
+    /// Set device name written to the INF file (shown in Device Manager)
+    pub fn set_desc(&mut self, desc: &str) -> Result<()> {
+        self.desc = Some(ffi::CString::new(desc)?);
+        self.update_ptrs();
+        Ok(())
+    }
+
+    /// Remove leading/trailing whitespace from the description, as done by `trim_whitespaces`
+    /// in [`CreateListOptions`](crate::CreateListOptions)
+    pub fn trim_desc(&mut self) {
+        let Some(desc) = &self.desc else { return };
+        let bytes = desc.as_bytes();
+        let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+        let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+        if (start, end) != (0, bytes.len()) {
+            // Sub-slice of a C string cannot contain NUL
+            self.desc = Some(ffi::CString::new(&bytes[start..end]).unwrap_or_default());
+            self.update_ptrs();
+        }
+    }
+
+    pub fn set_hardware_id(&mut self, id: Option<&str>) -> Result<()> {
+        self.hardware_id = opt_cstring(id)?;
+        self.update_ptrs();
+        Ok(())
+    }
+
+    pub fn set_compatible_id(&mut self, id: Option<&str>) -> Result<()> {
+        self.compatible_id = opt_cstring(id)?;
+        self.update_ptrs();
+        Ok(())
+    }
+
+    /// Device instance ID is used to find the device during installation,
+    /// without it the driver is installed for any device matching the hardware ID
+    pub fn set_device_id(&mut self, id: Option<&str>) -> Result<()> {
+        self.device_id = opt_cstring(id)?;
+        self.update_ptrs();
+        Ok(())
+    }
+}
+
+impl DeviceInfo {
+    /// Copy of the device information that can be modified
+    pub fn to_descriptor(&self) -> DeviceDescriptor {
+        DeviceDescriptor::from(self)
+    }
+}
+
+impl From<&DeviceInfo> for DeviceDescriptor {
+    fn from(dev: &DeviceInfo) -> Self {
+        // Strings are copied from C strings, so they contain no NUL and the conversion cannot fail
+        let string = |bytes: Option<&[u8]>| bytes.and_then(|bytes| ffi::CString::new(bytes).ok());
+        let mut descriptor = Self {
+            info: wdi::wdi_device_info {
+                next: ptr::null_mut(),
+                ..*dev.raw()
+            },
+            desc: string(dev.desc_bytes()),
+            driver: string(dev.driver_bytes()),
+            device_id: string(dev.device_id_bytes()),
+            hardware_id: string(dev.hardware_id_bytes()),
+            compatible_id: string(dev.compatible_id_bytes()),
+            upper_filter: string(dev.upper_filter_bytes()),
+        };
+        descriptor.update_ptrs();
+        descriptor
+    }
+}
+
+impl Clone for DeviceDescriptor {
+    fn clone(&self) -> Self {
+        DeviceDescriptor::from(&**self)
+    }
+}
+
+// Safety: same as for DevicesList, strings are owned by the descriptor
+unsafe impl Send for DeviceDescriptor {}
+
+impl Deref for DeviceDescriptor {
+    type Target = DeviceInfo;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: info is borrowed together with the descriptor
+        unsafe { DeviceInfo::from_ptr(&self.info) }
+    }
+}
+
+impl DerefMut for DeviceDescriptor {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: info is borrowed together with the descriptor
+        unsafe { DeviceInfo::from_mut_ptr(&mut self.info) }
+    }
+}
+
+impl DeviceDescriptorBuilder {
+    /// Mark device as interface `mi` of a composite device
+    pub fn interface(mut self, mi: u8) -> Self {
+        self.is_composite = true;
+        self.mi = mi;
+        self
+    }
+
+    pub fn composite(mut self, enabled: bool) -> Self {
+        self.is_composite = enabled;
+        self
+    }
+
+    /// Device name written to the INF file, "USB Device" by default
+    pub fn desc(mut self, desc: &str) -> Self {
+        self.desc = desc.to_string();
+        self
+    }
+
+    /// Defaults to `USB\VID_xxxx&PID_xxxx` (with `&MI_xx` for composite devices)
+    pub fn hardware_id(mut self, id: &str) -> Self {
+        self.hardware_id = Some(id.to_string());
+        self
+    }
+
+    pub fn compatible_id(mut self, id: &str) -> Self {
+        self.compatible_id = Some(id.to_string());
+        self
+    }
+
+    /// This may fail during string conversion
+    pub fn build(self) -> Result<DeviceDescriptor> {
+        let hardware_id = match self.hardware_id {
+            Some(id) => id,
+            None if self.is_composite => format!("USB\\VID_{:04X}&PID_{:04X}&MI_{:02X}", self.vid, self.pid, self.mi),
+            None => format!("USB\\VID_{:04X}&PID_{:04X}", self.vid, self.pid),
+        };
+        let mut descriptor = DeviceDescriptor {
+            info: wdi::wdi_device_info {
+                next: ptr::null_mut(),
+                vid: self.vid,
+                pid: self.pid,
+                is_composite: self.is_composite as wdi::BOOL,
+                mi: self.mi,
+                desc: ptr::null_mut(),
+                driver: ptr::null_mut(),
+                device_id: ptr::null_mut(),
+                hardware_id: ptr::null_mut(),
+                compatible_id: ptr::null_mut(),
+                upper_filter: ptr::null_mut(),
+                driver_version: 0,
+            },
+            desc: Some(ffi::CString::new(self.desc)?),
+            driver: None,
+            device_id: None,
+            hardware_id: Some(ffi::CString::new(hardware_id)?),
+            compatible_id: opt_cstring(self.compatible_id.as_deref())?,
+            upper_filter: None,
+        };
+        descriptor.update_ptrs();
+        Ok(descriptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::Error;
+
+    #[test]
+    fn builder_fills_hardware_id() {
+        let dev = DeviceDescriptor::builder(0xcafe, 0x4001)
+            .interface(1)
+            .desc("Our board")
+            .compatible_id("USB\\Class_ff")
+            .build()
+            .unwrap();
+        assert_eq!((dev.vid(), dev.pid(), dev.is_composite()), (0xcafe, 0x4001, true));
+        assert_eq!(dev.mi().map(|mi| mi.get()), Some(1));
+        assert_eq!(dev.desc(), "Our board");
+        assert_eq!(dev.hardware_id().as_deref(), Some("USB\\VID_CAFE&PID_4001&MI_01"));
+        assert_eq!(dev.device_id(), None);
+
+        let dev = DeviceDescriptor::builder(0xcafe, 0x4002).hardware_id("USB\\VID_CAFE&PID_4002&REV_0100").build().unwrap();
+        assert_eq!((dev.is_composite(), dev.mi()), (false, None));
+        assert_eq!(dev.desc(), "USB Device");
+        assert_eq!(dev.hardware_id().as_deref(), Some("USB\\VID_CAFE&PID_4002&REV_0100"));
+        assert!(matches!(DeviceDescriptor::builder(1, 2).desc("a\0b").build(), Err(Error::Nul(_))));
+    }
+
+    #[test]
+    fn edits_keep_pointers_valid() {
+        let mut dev = DeviceDescriptor::builder(0x2222, 0x3333).desc("  Device  ").build().unwrap();
+        dev.trim_desc();
+        assert_eq!(dev.desc(), "Device");
+        dev.set_device_id(Some("USB\\VID_2222&PID_3333\\0001")).unwrap();
+        dev.set_compatible_id(None).unwrap();
+        assert!(matches!(dev.set_desc("a\0b"), Err(Error::Nul(_))));
+
+        // Copy points at its own strings
+        let copy = dev.clone();
+        drop(dev);
+        assert_eq!(copy.desc(), "Device");
+        assert_eq!(copy.device_id().as_deref(), Some("USB\\VID_2222&PID_3333\\0001"));
+        assert_eq!(copy.compatible_id(), None);
+        assert_ne!(copy.raw().desc, ptr::null_mut());
+    }
+}
@@ -1,4 +1,6 @@
-use std::{str::Utf8Error, ffi};
+use std::{str::{FromStr, Utf8Error}, ffi, fmt, io, sync::Arc};
+
+use serde::{Deserialize, Serialize};
 
 use thiserror::Error;
 use libwdi_sys as wdi;
@@ -51,10 +53,57 @@ pub enum Error {
     Unexpected(wdi::wdi_error::Type),
     #[error("Internal error in the wrapper or library broken it's contract")]
     Internal,
+    #[error(transparent)]
+    Decode(#[from] crate::codepage::DecodeError),
     #[error("C to Rust string conversion error")]
     Utf8(#[from] Utf8Error),
     #[error("Rust to C string conversion error")]
     Nul(#[from] ffi::NulError),
+    #[error(transparent)]
+    Driver(#[from] DriverError),
+    #[error("Invalid MS OS descriptor: {0}")]
+    Descriptor(#[from] crate::msos::DescriptorError),
+    #[error(transparent)]
+    File(#[from] FileError),
+    #[error(transparent)]
+    InstallLock(#[from] crate::install_lock::InstallLockError),
+    #[error(transparent)]
+    Audit(#[from] crate::audit::AuditError),
+    #[error(transparent)]
+    ExternalPackage(#[from] crate::external::ExternalPackageError),
+}
+
+/// Reading or writing files of packages, fixtures, audit logs and install locks
+// io::Error and serde_json::Error are not Clone, so they are shared instead
+#[derive(Error, Debug, Clone)]
+pub enum FileError {
+    #[error("File error: {0}")]
+    Io(Arc<io::Error>),
+    #[error("JSON error: {0}")]
+    Json(Arc<serde_json::Error>),
+}
+
+/// Driver type that cannot be used as requested
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DriverError {
+    #[error("Unknown driver type \"{0}\"")]
+    UnknownType(String),
+    #[error("{0} driver is not embedded in libwdi (see crate features)")]
+    NotEmbedded(DriverType),
+    #[error("Option {option} cannot be used with {driver} driver")]
+    IncompatibleOption { driver: DriverType, option: DriverOption },
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::File(FileError::Io(Arc::new(err)))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::File(FileError::Json(Arc::new(err)))
+    }
 }
 
 pub fn check_error(code: wdi::wdi_error::Type) -> Result<()> {
@@ -85,7 +134,7 @@ pub fn check_error(code: wdi::wdi_error::Type) -> Result<()> {
 }
 
 /// Type of driver to install
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DriverType {
     WinUsb,
     LibUsb0,
@@ -95,7 +144,29 @@ pub enum DriverType {
 }
 
 impl DriverType {
-    pub(crate) fn to_ffi(self) -> wdi::wdi_driver_type::Type {
+    /// All driver types supported by libwdi
+    pub const ALL: [DriverType; 5] = [
+        DriverType::WinUsb,
+        DriverType::LibUsb0,
+        DriverType::LibUsbK,
+        DriverType::Cdc,
+        DriverType::User,
+    ];
+
+    /// Whether this driver has been embedded in libwdi at build time (see `libwdi-sys` documentation).
+    /// Always false where libwdi is not available (platforms other than Windows without the `fake`
+    /// feature), with `fake` it follows the crate features.
+    pub const fn is_embedded(self) -> bool {
+        !crate::ffi::STUB && match self {
+            DriverType::WinUsb => wdi::EMBEDDED_WINUSB,
+            DriverType::LibUsb0 => wdi::EMBEDDED_LIBUSB0,
+            DriverType::LibUsbK => wdi::EMBEDDED_LIBUSBK,
+            DriverType::Cdc => wdi::EMBEDDED_CDC,
+            DriverType::User => wdi::EMBEDDED_USER,
+        }
+    }
+
+    pub fn to_ffi(self) -> wdi::wdi_driver_type::Type {
         match self {
             DriverType::WinUsb => wdi::wdi_driver_type::WDI_WINUSB,
             DriverType::LibUsb0 => wdi::wdi_driver_type::WDI_LIBUSB0,
@@ -104,8 +175,150 @@ impl DriverType {
             DriverType::User => wdi::wdi_driver_type::WDI_USER,
         }
     }
+
+    pub const fn metadata(self) -> &'static DriverMetadata {
+        match self {
+            DriverType::WinUsb => &DriverMetadata {
+                display_name: "WinUSB",
+                service_name: Some("WinUSB"),
+                class: Some("USBDevice"),
+                class_guid: Some("{88BAE032-5A81-49F0-BC3D-A4FF138216D6}"),
+                options: &[DriverOption::DeviceGuid, DriverOption::UseWcidDriver],
+            },
+            DriverType::LibUsb0 => &DriverMetadata {
+                display_name: "libusb-win32",
+                service_name: Some("libusb0"),
+                class: Some("libusb-win32 devices"),
+                class_guid: Some("{EB781AAF-9C70-4523-A5DF-642A87ECA567}"),
+                options: &[DriverOption::DeviceGuid, DriverOption::InstallFilterDriver],
+            },
+            DriverType::LibUsbK => &DriverMetadata {
+                display_name: "libusbK",
+                service_name: Some("libusbK"),
+                class: Some("libusbK USB Devices"),
+                class_guid: Some("{ECFB0CFD-74C4-4F52-BBF7-343461CD72AC}"),
+                options: &[DriverOption::DeviceGuid],
+            },
+            DriverType::Cdc => &DriverMetadata {
+                display_name: "USB Serial (CDC)",
+                service_name: Some("usbser"),
+                class: Some("Ports"),
+                class_guid: Some("{4D36E978-E325-11CE-BFC1-08002BE10318}"),
+                options: &[],
+            },
+            // Defined by the user-provided INF template
+            DriverType::User => &DriverMetadata {
+                display_name: "Custom (User)",
+                service_name: None,
+                class: None,
+                class_guid: None,
+                options: &[DriverOption::DeviceGuid],
+            },
+        }
+    }
+
+    pub fn supports(self, option: DriverOption) -> bool {
+        self.metadata().options.contains(&option)
+    }
+
+    /// Whether the driver can be installed as a filter driver (on top of the current driver)
+    pub fn filter_install_possible(self) -> bool {
+        self.supports(DriverOption::InstallFilterDriver)
+    }
+
+    /// Fails if `option` is not supported by this driver type
+    pub fn check_option(self, option: DriverOption) -> Result<()> {
+        if self.supports(option) {
+            Ok(())
+        } else {
+            Err(DriverError::IncompatibleOption { driver: self, option }.into())
+        }
+    }
+}
+
+impl fmt::Display for DriverType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.metadata().display_name)
+    }
+}
+
+impl FromStr for DriverType {
+    type Err = Error;
+
+    /// Accepts variant names, display names and service names, case insensitive
+    fn from_str(s: &str) -> Result<Self> {
+        DriverType::ALL.into_iter()
+            .find(|typ| {
+                let meta = typ.metadata();
+                format!("{typ:?}").eq_ignore_ascii_case(s)
+                    || meta.display_name.eq_ignore_ascii_case(s)
+                    || meta.service_name.is_some_and(|name| name.eq_ignore_ascii_case(s))
+            })
+            .ok_or_else(|| DriverError::UnknownType(s.to_string()).into())
+    }
+}
+
+/// Information about a driver type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverMetadata {
+    pub display_name: &'static str,
+    /// Name of the Windows service of the driver
+    pub service_name: Option<&'static str>,
+    /// Device setup class used in the INF file
+    pub class: Option<&'static str>,
+    pub class_guid: Option<&'static str>,
+    /// Options that can be used with this driver type
+    pub options: &'static [DriverOption],
+}
+
+/// Driver-specific options of [`PrepareDriverOptions`](crate::PrepareDriverOptions)
+/// and [`PreparedDriver`](crate::PreparedDriver)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DriverOption {
+    DeviceGuid,
+    UseWcidDriver,
+    InstallFilterDriver,
+}
+
+impl fmt::Display for DriverOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DriverOption::DeviceGuid => "device_guid",
+            DriverOption::UseWcidDriver => "use_wcid_driver",
+            DriverOption::InstallFilterDriver => "install_filter_driver",
+        })
+    }
 }
 
+// Embedded drivers are moved to the front, the length is the number of embedded drivers
+const EMBEDDED: ([DriverType; 5], usize) = {
+    let mut drivers = DriverType::ALL;
+    let mut n = 0;
+    let mut i = 0;
+    while i < DriverType::ALL.len() {
+        if DriverType::ALL[i].is_embedded() {
+            drivers[n] = DriverType::ALL[i];
+            n += 1;
+        }
+        i += 1;
+    }
+    (drivers, n)
+};
+
+/// Driver types that have been embedded in libwdi at build time, see [`DriverType::is_embedded`].
+/// [`PrepareDriverOptions::prepare_driver`](crate::PrepareDriverOptions::prepare_driver) fails with
+/// [`DriverError::NotEmbedded`] for driver types not on this list.
+pub const EMBEDDED_DRIVERS: &[DriverType] = EMBEDDED.0.split_at(EMBEDDED.1).0;
+
+/// Subdirectory of embedded driver files and INF decoration for the current architecture
+pub(crate) const ARCH_DIR: &str = if cfg!(target_arch = "x86_64") {
+    "amd64"
+} else if cfg!(target_arch = "aarch64") {
+    "arm64"
+} else {
+    "x86"
+};
+
 #[derive(Debug, Clone, Copy)]
 pub enum LogLevel {
     Debug,
@@ -126,3 +339,24 @@ impl LogLevel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn driver_type_names() {
+        for typ in DriverType::ALL {
+            assert_eq!(typ.to_string().parse::<DriverType>().unwrap(), typ);
+            assert_eq!(format!("{typ:?}").to_lowercase().parse::<DriverType>().unwrap(), typ);
+        }
+        assert_eq!("libusb0".parse::<DriverType>().unwrap(), DriverType::LibUsb0);
+        assert_eq!("usbser".parse::<DriverType>().unwrap(), DriverType::Cdc);
+        assert!(matches!("winusb2".parse::<DriverType>(), Err(Error::Driver(DriverError::UnknownType(_)))));
+
+        assert!(DriverType::LibUsb0.filter_install_possible());
+        assert!(!DriverType::WinUsb.filter_install_possible());
+        assert_eq!(DriverType::Cdc.metadata().class, Some("Ports"));
+        assert_eq!(DriverType::WinUsb.metadata().service_name, Some("WinUSB"));
+    }
+}
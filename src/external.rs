@@ -0,0 +1,238 @@
+use std::{fs, ops::Range, path::{Component, Path, PathBuf}};
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::core::{DeviceInfo, PrepareDriverOptions, PreparedDriver};
+use crate::enums::{Error, Result};
+use crate::inf::{device_hardware_ids, InfFile};
+
+/// Driver package supplied by the application (INF, signed catalog and driver files) instead of
+/// the one generated by libwdi
+///
+/// The package is checked before it is copied to the install path, so that a wrong or tampered
+/// package is reported before Windows rejects it during installation.
+#[derive(Debug, Clone)]
+pub struct ExternalPackage {
+    dir: PathBuf,
+    inf_name: String,
+    inf: InfFile,
+}
+
+/// [`ExternalPackage`] that does not match its INF file or the device
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExternalPackageError {
+    #[error("Invalid driver package, {file}: {reason}")]
+    Invalid { file: String, reason: String },
+}
+
+fn invalid(file: &Path, reason: impl Into<String>) -> Error {
+    ExternalPackageError::Invalid { file: file.display().to_string(), reason: reason.into() }.into()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as usize)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+/// Parts of a PE image covered by its Authenticode hash, `None` if `data` is not a PE image
+///
+/// The checksum, the certificate table entry and the certificates themselves are excluded, so the
+/// hash does not change when the file is signed.
+fn authenticode_ranges(data: &[u8]) -> Option<Vec<Range<usize>>> {
+    if !data.starts_with(b"MZ") {
+        return None;
+    }
+    let pe = read_u32(data, 0x3c)?;
+    if data.get(pe..pe + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let optional = pe + 24;
+    let checksum = optional + 64;
+    let directories = optional + match read_u16(data, optional)? {
+        0x10b => 96,
+        0x20b => 112,
+        _ => return None,
+    };
+    if read_u32(data, directories - 4)? <= 4 {
+        // No certificate table entry
+        return Some(vec![0..checksum, checksum + 4..data.len()]);
+    }
+    let cert_entry = directories + 4 * 8;
+    let (cert_offset, cert_size) = (read_u32(data, cert_entry)?, read_u32(data, cert_entry + 4)?);
+    let end = if cert_size > 0 && cert_offset <= data.len() { cert_offset } else { data.len() };
+    Some(vec![0..checksum, checksum + 4..cert_entry, cert_entry + 8..end.max(cert_entry + 8)])
+}
+
+fn digest<D: Digest>(data: &[u8], ranges: &[Range<usize>]) -> Vec<u8> {
+    let mut hasher = D::new();
+    for range in ranges {
+        hasher.update(&data[range.clone()]);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Hashes under which a catalog may list the file: SHA-1 and SHA-256 of the whole file and, for
+/// PE images, their Authenticode hashes
+fn member_hashes(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut hashes = vec![Sha1::digest(data).to_vec(), Sha256::digest(data).to_vec()];
+    if let Some(ranges) = authenticode_ranges(data) {
+        hashes.push(digest::<Sha1>(data, &ranges));
+        hashes.push(digest::<Sha256>(data, &ranges));
+    }
+    hashes
+}
+
+/// Fails unless `file` stays inside the package directory, so that a crafted INF cannot make
+/// [`ExternalPackage::copy_to`] write elsewhere
+fn check_relative(inf: &Path, file: &Path) -> Result<()> {
+    let inside = file.components().all(|part| match part {
+        Component::Normal(name) => !name.to_string_lossy().contains([':', '\\', '/']),
+        Component::CurDir => true,
+        Component::ParentDir | Component::RootDir | Component::Prefix(_) => false,
+    });
+    if !inside || file.as_os_str().is_empty() {
+        return Err(invalid(inf, format!("path {} is not inside the package", file.display())));
+    }
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from, to)?;
+    Ok(())
+}
+
+impl ExternalPackage {
+    /// Package in directory `dir` described by INF file `inf_name`
+    ///
+    /// Fails with [`ExternalPackageError::Invalid`] if the INF references files outside of `dir`
+    /// (absolute paths or `..` components).
+    pub fn open(dir: impl Into<PathBuf>, inf_name: &str) -> Result<Self> {
+        let dir = dir.into();
+        let inf_path = dir.join(inf_name);
+        check_relative(&inf_path, Path::new(inf_name))?;
+        let inf = InfFile::load(&inf_path)?;
+        if let Some(catalog) = inf.catalog_file() {
+            // Absolute in any path syntax, not only the one of the current platform
+            if catalog.starts_with(['\\', '/']) {
+                return Err(invalid(&inf_path, format!("path {catalog} is not inside the package")));
+            }
+        }
+        let package = Self { dir, inf_name: inf_name.to_string(), inf };
+        for file in package.files() {
+            check_relative(&inf_path, &file)?;
+        }
+        Ok(package)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn inf_name(&self) -> &str {
+        &self.inf_name
+    }
+
+    pub fn inf(&self) -> &InfFile {
+        &self.inf
+    }
+
+    /// Files of the package relative to its directory: the INF, the catalog and the files listed in the INF
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from(&self.inf_name)];
+        files.extend(self.inf.catalog_file().map(|catalog| PathBuf::from_iter(catalog.split(['\\', '/']))));
+        files.extend(self.inf.source_files());
+        files
+    }
+
+    /// Fails if the INF has no model for any of the hardware IDs of `dev`
+    pub fn check_device(&self, dev: &DeviceInfo) -> Result<()> {
+        if !self.inf.targets(dev) {
+            let ids = device_hardware_ids(dev).join(", ");
+            return Err(invalid(&self.dir.join(&self.inf_name), format!("no model for hardware ID {ids}")));
+        }
+        Ok(())
+    }
+
+    /// Fails if a file of the package is missing
+    pub fn check_files(&self) -> Result<()> {
+        for file in self.files() {
+            if !self.dir.join(&file).is_file() {
+                return Err(invalid(&self.dir.join(file), "file referenced by the INF is missing"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the INF has no catalog or if a file of the package is not listed in it
+    ///
+    /// This is only a heuristic to report obviously wrong packages early: the catalog is not parsed,
+    /// a file is considered listed if one of its hashes occurs anywhere in the catalog as a DER
+    /// octet string. Neither the member entries nor the signature of the catalog are verified,
+    /// Windows does that during installation.
+    pub fn check_catalog(&self) -> Result<()> {
+        let inf = self.dir.join(&self.inf_name);
+        let catalog_name = self.inf.catalog_file().ok_or_else(|| invalid(&inf, "no CatalogFile in [Version]"))?;
+        let catalog = fs::read(self.dir.join(catalog_name))?;
+        let listed = |hash: &Vec<u8>| {
+            // OCTET STRING tag and short form length precede the hash
+            let encoded = [&[0x04, hash.len() as u8], hash.as_slice()].concat();
+            catalog.windows(encoded.len()).any(|window| window == encoded)
+        };
+        let members = std::iter::once(PathBuf::from(&self.inf_name)).chain(self.inf.source_files());
+        for file in members {
+            let path = self.dir.join(file);
+            if !member_hashes(&fs::read(&path)?).iter().any(listed) {
+                return Err(invalid(&path, format!("does not match catalog {catalog_name}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run all checks for installing the package for `dev`
+    ///
+    /// The INF must have a model for the hardware ID of the device, all files listed in
+    /// `[SourceDisksFiles]` must exist and the INF and driver files must appear in the catalog, see
+    /// [`check_catalog`](Self::check_catalog).
+    pub fn validate(&self, dev: &DeviceInfo) -> Result<()> {
+        self.check_device(dev)?;
+        self.check_files()?;
+        self.check_catalog()
+    }
+
+    /// Copy all files of the package to `path`, keeping their relative paths
+    pub fn copy_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        for file in self.files() {
+            copy_file(&self.dir.join(&file), &path.as_ref().join(&file))?;
+        }
+        Ok(())
+    }
+
+    /// Validate the package, copy it to `path` and prepare it for `dev` with `options`
+    ///
+    /// libwdi is used with `external_inf` and `disable_cat`, so that the INF and the catalog of the
+    /// package are kept. It still extracts the installer and the files of the selected driver type,
+    /// which are then replaced by the files of the package.
+    pub fn prepare<'a>(&self, options: PrepareDriverOptions, dev: &'a mut DeviceInfo, path: &str) -> Result<PreparedDriver<'a>> {
+        self.validate(dev)?;
+        copy_file(&self.dir.join(&self.inf_name), &Path::new(path).join(&self.inf_name))?;
+        let driver = options
+            .external_inf(true)
+            .disable_cat(true)
+            .prepare_driver(dev, path, &self.inf_name)?;
+        self.copy_to(path)?;
+        Ok(driver)
+    }
+
+    /// [`prepare`](Self::prepare) the package and install it
+    pub fn install(&self, options: PrepareDriverOptions, dev: &mut DeviceInfo, path: &str) -> Result<()> {
+        self.prepare(options, dev, path)?.install_driver()
+    }
+}
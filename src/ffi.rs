@@ -0,0 +1,105 @@
+//! libwdi functions used by the wrappers
+//!
+//! On Windows (or with the "fake" feature) these are just the functions from libwdi-sys. On other
+//! platforms libwdi is not available, so a stub implementation is used instead: listing finds no
+//! devices and all driver operations fail with `WDI_ERROR_NOT_SUPPORTED`.
+
+pub use libwdi_sys::*;
+
+#[cfg(not(any(windows, feature = "fake")))]
+pub use self::stub::{
+    wdi_create_list,
+    wdi_destroy_list,
+    wdi_prepare_driver,
+    wdi_install_driver,
+    wdi_install_trusted_certificate,
+    wdi_is_driver_supported,
+    wdi_is_file_embedded,
+    wdi_get_vendor_name,
+    wdi_get_wdf_version,
+    wdi_set_log_level,
+    wdi_register_logger,
+    wdi_unregister_logger,
+    wdi_read_logger,
+};
+
+/// Whether the stub implementation is used instead of libwdi
+pub const STUB: bool = cfg!(not(any(windows, feature = "fake")));
+
+// Functions keep the signatures of libwdi-sys, including `unsafe`, so that the call sites are the same.
+#[cfg(not(any(windows, feature = "fake")))]
+mod stub {
+    use std::os::raw::{c_char, c_int, c_ushort};
+    use std::ptr;
+
+    use libwdi_sys::*;
+    use libwdi_sys::wdi_error::{WDI_SUCCESS, WDI_ERROR_NOT_SUPPORTED};
+
+    /// Returns an empty list (null), which is then handled by `DevicesList`
+    pub unsafe fn wdi_create_list(list: *mut *mut wdi_device_info, _options: *mut wdi_options_create_list) -> c_int {
+        *list = ptr::null_mut();
+        WDI_SUCCESS
+    }
+
+    pub unsafe fn wdi_destroy_list(list: *mut wdi_device_info) -> c_int {
+        debug_assert!(list.is_null(), "stub never creates any devices");
+        WDI_SUCCESS
+    }
+
+    pub unsafe fn wdi_prepare_driver(
+        _device_info: *mut wdi_device_info,
+        _path: *const c_char,
+        _inf_name: *const c_char,
+        _options: *mut wdi_options_prepare_driver,
+    ) -> c_int {
+        WDI_ERROR_NOT_SUPPORTED
+    }
+
+    pub unsafe fn wdi_install_driver(
+        _device_info: *mut wdi_device_info,
+        _path: *const c_char,
+        _inf_name: *const c_char,
+        _options: *mut wdi_options_install_driver,
+    ) -> c_int {
+        WDI_ERROR_NOT_SUPPORTED
+    }
+
+    pub unsafe fn wdi_install_trusted_certificate(_cert_name: *const c_char, _options: *mut wdi_options_install_cert) -> c_int {
+        WDI_ERROR_NOT_SUPPORTED
+    }
+
+    pub unsafe fn wdi_is_driver_supported(_driver_type: c_int, _driver_info: *mut VS_FIXEDFILEINFO) -> BOOL {
+        false as BOOL
+    }
+
+    pub unsafe fn wdi_is_file_embedded(_path: *const c_char, _name: *const c_char) -> BOOL {
+        false as BOOL
+    }
+
+    pub unsafe fn wdi_get_vendor_name(_vid: c_ushort) -> *const c_char {
+        ptr::null()
+    }
+
+    /// Same as libwdi built without WDF support
+    pub unsafe fn wdi_get_wdf_version() -> c_int {
+        -1
+    }
+
+    /// There is nothing to log, so log level can be set without problems
+    pub unsafe fn wdi_set_log_level(_level: c_int) -> c_int {
+        WDI_SUCCESS
+    }
+
+    pub unsafe fn wdi_register_logger(_hwnd: HWND, _message: UINT, _buffsize: DWORD) -> c_int {
+        WDI_ERROR_NOT_SUPPORTED
+    }
+
+    pub unsafe fn wdi_unregister_logger(_hwnd: HWND) -> c_int {
+        WDI_ERROR_NOT_SUPPORTED
+    }
+
+    pub unsafe fn wdi_read_logger(_buffer: *mut c_char, _buffer_size: DWORD, message_size: *mut DWORD) -> c_int {
+        *message_size = 0;
+        WDI_ERROR_NOT_SUPPORTED
+    }
+}
@@ -0,0 +1,213 @@
+use std::{ffi, fs, path::Path, ptr};
+
+use serde::{de::Error as _, Deserialize, Serialize};
+
+use crate::ffi as wdi;
+
+use crate::core::{CreateListOptions, DeviceInfo, DevicesList};
+use crate::descriptor::DeviceDescriptor;
+use crate::enums::Result;
+
+/// Snapshot of a [`DevicesList`] that can be saved as JSON and replayed later, e.g. to reproduce
+/// a problem reported by a user in tests on any platform
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListFixture {
+    version: u32,
+    pub options: ListOptionsRecord,
+    pub devices: Vec<DeviceRecord>,
+}
+
+/// Options with which the recorded list has been created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListOptionsRecord {
+    pub list_all: bool,
+    pub list_hubs: bool,
+    pub trim_whitespaces: bool,
+}
+
+/// All fields of a recorded [`DeviceInfo`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceRecord {
+    pub vid: u16,
+    pub pid: u16,
+    pub is_composite: bool,
+    pub mi: u8,
+    pub desc: Option<RecordedString>,
+    pub driver: Option<RecordedString>,
+    pub device_id: Option<RecordedString>,
+    pub hardware_id: Option<RecordedString>,
+    pub compatible_id: Option<RecordedString>,
+    pub upper_filter: Option<RecordedString>,
+    pub driver_version: u64,
+}
+
+/// String as returned by libwdi, stored as bytes if it is not valid UTF-8 (e.g. in ANSI code page)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RecordedString {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl RecordedString {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Self::Text(s.to_string()),
+            Err(_) => Self::Bytes(bytes.to_vec()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(s) => s.as_bytes(),
+            Self::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+impl From<&str> for RecordedString {
+    fn from(s: &str) -> Self {
+        Self::Text(s.to_string())
+    }
+}
+
+impl From<&CreateListOptions> for ListOptionsRecord {
+    fn from(options: &CreateListOptions) -> Self {
+        Self {
+            list_all: options.0.list_all != 0,
+            list_hubs: options.0.list_hubs != 0,
+            trim_whitespaces: options.0.trim_whitespaces != 0,
+        }
+    }
+}
+
+impl From<ListOptionsRecord> for CreateListOptions {
+    fn from(options: ListOptionsRecord) -> Self {
+        CreateListOptions::new()
+            .list_all(options.list_all)
+            .list_hubs(options.list_hubs)
+            .trim_whitespaces(options.trim_whitespaces)
+    }
+}
+
+impl DeviceRecord {
+    pub fn of(dev: &DeviceInfo) -> Self {
+        let string = |bytes: Option<&[u8]>| bytes.map(RecordedString::from_bytes);
+        Self {
+            vid: dev.vid(),
+            pid: dev.pid(),
+            is_composite: dev.is_composite(),
+            mi: dev.raw().mi,
+            desc: string(dev.desc_bytes()),
+            driver: string(dev.driver_bytes()),
+            device_id: string(dev.device_id_bytes()),
+            hardware_id: string(dev.hardware_id_bytes()),
+            compatible_id: string(dev.compatible_id_bytes()),
+            upper_filter: string(dev.upper_filter_bytes()),
+            driver_version: dev.driver_version().map_or(0, |v| v.get()),
+        }
+    }
+
+    /// Device owning a copy of the recorded information, fails if a string contains NUL
+    pub fn to_descriptor(&self) -> Result<DeviceDescriptor> {
+        let string = |s: &Option<RecordedString>| s.as_ref().map(|s| ffi::CString::new(s.as_bytes())).transpose();
+        let strings = [
+            string(&self.desc)?,
+            string(&self.driver)?,
+            string(&self.device_id)?,
+            string(&self.hardware_id)?,
+            string(&self.compatible_id)?,
+            string(&self.upper_filter)?,
+        ];
+        let [desc, driver, device_id, hardware_id, compatible_id, upper_filter] = strings.each_ref()
+            .map(|s| s.as_ref().map_or(ptr::null_mut(), |s| s.as_ptr() as *mut _));
+        // Temporary view of the strings above, which are copied by the descriptor
+        let info = wdi::wdi_device_info {
+            next: ptr::null_mut(),
+            vid: self.vid,
+            pid: self.pid,
+            is_composite: self.is_composite as wdi::BOOL,
+            mi: self.mi,
+            desc,
+            driver,
+            device_id,
+            hardware_id,
+            compatible_id,
+            upper_filter,
+            driver_version: self.driver_version,
+        };
+        // Safety: info is a local that is not modified while the view exists
+        Ok(DeviceDescriptor::from(unsafe { DeviceInfo::from_ptr(&info) }))
+    }
+}
+
+impl ListFixture {
+    const VERSION: u32 = 1;
+
+    pub fn new(options: ListOptionsRecord, devices: Vec<DeviceRecord>) -> Self {
+        Self { version: Self::VERSION, options, devices }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Fails with [`FileError::Json`](crate::FileError::Json) for fixtures written in a format version that is not supported
+    pub fn from_json(json: &str) -> Result<Self> {
+        let fixture: Self = serde_json::from_str(json)?;
+        if fixture.version != Self::VERSION {
+            let msg = format!("unsupported fixture format version {}, expected {}", fixture.version, Self::VERSION);
+            return Err(serde_json::Error::custom(msg).into());
+        }
+        Ok(fixture)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// List with the recorded devices, behaving as if returned by wdi_create_list
+    pub fn replay(&self) -> Result<DevicesList> {
+        let devices = self.devices.iter()
+            .map(DeviceRecord::to_descriptor)
+            .collect::<Result<_>>()?;
+        Ok(DevicesList::from_descriptors(self.options.into(), devices))
+    }
+}
+
+impl DevicesList {
+    /// Record all devices and the options used to create the list
+    pub fn to_fixture(&self) -> ListFixture {
+        ListFixture::new(self.options().into(), self.iter().map(DeviceRecord::of).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::{Error, FileError};
+
+    #[test]
+    fn fixture_is_read_from_json() {
+        let customer = r#"{
+            "version": 1,
+            "options": { "list_all": false, "list_hubs": false, "trim_whitespaces": false },
+            "devices": [{
+                "vid": 4660, "pid": 1, "is_composite": false, "mi": 0, "desc": "Board",
+                "driver": null, "device_id": null, "hardware_id": "USB\\VID_1234&PID_0001",
+                "compatible_id": null, "upper_filter": null, "driver_version": 0
+            }]
+        }"#;
+        let list = ListFixture::from_json(customer).unwrap().replay().unwrap();
+        let found: Vec<_> = list.iter().map(|dev| (dev.vid(), dev.desc().into_owned(), dev.device_id())).collect();
+        assert_eq!(found, [(0x1234, "Board".to_string(), None)]);
+
+        let future = customer.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(ListFixture::from_json(&future), Err(Error::File(FileError::Json(_)))));
+    }
+}
@@ -0,0 +1,292 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::core::DeviceInfo;
+use crate::enums::{Result, ARCH_DIR};
+use crate::models::DeviceModel;
+
+/// INF file parsed as far as needed to check a driver package
+///
+/// Section names and keys are case-insensitive, `%strings%` are substituted from the `[Strings]`
+/// sections and quotes are removed from values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfFile {
+    sections: Vec<InfSection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfSection {
+    pub name: String,
+    pub lines: Vec<InfLine>,
+}
+
+/// `key = value, value` line, lines without `=` only have values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfLine {
+    pub key: Option<String>,
+    pub values: Vec<String>,
+}
+
+/// Position of the first `c` outside of quotes
+pub(crate) fn find_unquoted(s: &str, c: char) -> Option<usize> {
+    let mut quoted = false;
+    s.char_indices().find(|&(_, ch)| {
+        if ch == '"' {
+            quoted = !quoted;
+        }
+        ch == c && !quoted
+    }).map(|(i, _)| i)
+}
+
+pub(crate) fn split_unquoted(mut s: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    while let Some(i) = find_unquoted(s, separator) {
+        parts.push(&s[..i]);
+        s = &s[i + 1..];
+    }
+    parts.push(s);
+    parts
+}
+
+/// Remove quotes, `""` inside quotes is a quote
+fn unquote(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.trim().chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                out.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn is_strings_section(name: &str) -> bool {
+    name.eq_ignore_ascii_case("Strings") || name.to_ascii_lowercase().starts_with("strings.")
+}
+
+/// Encoding of an INF file, kept when the file is rewritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InfEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+}
+
+pub(crate) fn decode(data: &[u8]) -> (String, InfEncoding) {
+    match data {
+        [0xff, 0xfe, rest @ ..] => {
+            let units: Vec<u16> = rest.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            (String::from_utf16_lossy(&units), InfEncoding::Utf16Le)
+        },
+        [0xef, 0xbb, 0xbf, rest @ ..] => (String::from_utf8_lossy(rest).into_owned(), InfEncoding::Utf8Bom),
+        _ => (String::from_utf8_lossy(data).into_owned(), InfEncoding::Utf8),
+    }
+}
+
+pub(crate) fn encode(text: &str, encoding: InfEncoding) -> Vec<u8> {
+    match encoding {
+        InfEncoding::Utf8 => text.as_bytes().to_vec(),
+        InfEncoding::Utf8Bom => [&[0xef, 0xbb, 0xbf], text.as_bytes()].concat(),
+        InfEncoding::Utf16Le => [0xff, 0xfe].into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+    }
+}
+
+/// Hardware IDs under which Windows lists the device, from the most specific
+pub(crate) fn device_hardware_ids(dev: &DeviceInfo) -> Vec<String> {
+    let mut ids = vec![];
+    if let Some(id) = dev.hardware_id() {
+        ids.push(id.to_ascii_uppercase());
+    }
+    let id = DeviceModel::of(dev).hardware_id();
+    if !ids.contains(&id) {
+        ids.push(id);
+    }
+    ids
+}
+
+impl InfFile {
+    pub fn parse(text: &str) -> Self {
+        let mut sections: Vec<InfSection> = vec![];
+        let mut logical = String::new();
+        for line in text.lines() {
+            let line = find_unquoted(line, ';').map_or(line, |comment| &line[..comment]).trim_end();
+            // Backslash at the end continues the line
+            if let Some(continued) = line.strip_suffix('\\') {
+                logical.push_str(continued);
+                continue;
+            }
+            logical.push_str(line);
+            let line = std::mem::take(&mut logical);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.split_once(']')).map(|(name, _)| name) {
+                sections.push(InfSection { name: name.trim().to_string(), lines: vec![] });
+                continue;
+            }
+            let Some(section) = sections.last_mut() else { continue };
+            let (key, values) = match find_unquoted(line, '=') {
+                Some(i) => (Some(unquote(&line[..i])), &line[i + 1..]),
+                None => (None, line),
+            };
+            let values = split_unquoted(values, ',').into_iter().map(unquote).collect();
+            section.lines.push(InfLine { key, values });
+        }
+
+        let mut inf = Self { sections };
+        inf.substitute_strings();
+        inf
+    }
+
+    /// Load INF file in UTF-8, UTF-16 (with BOM) or ASCII
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::parse(&decode(&fs::read(path)?).0))
+    }
+
+    fn substitute_strings(&mut self) {
+        // Undecorated [Strings] take precedence over localized ones
+        let mut strings: Vec<(String, String)> = vec![];
+        let mut string_sections: Vec<_> = self.sections.iter()
+            .filter(|s| is_strings_section(&s.name))
+            .collect();
+        string_sections.sort_by_key(|s| !s.name.eq_ignore_ascii_case("Strings"));
+        for section in string_sections {
+            for line in &section.lines {
+                if let (Some(key), Some(value)) = (&line.key, line.values.first()) {
+                    if !strings.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                        strings.push((key.clone(), value.clone()));
+                    }
+                }
+            }
+        }
+        let substitute = |s: &mut String| {
+            let mut out = String::new();
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find('%') {
+                out.push_str(&rest[..start]);
+                let after = &rest[start + 1..];
+                let Some(end) = after.find('%') else {
+                    out.push_str(&rest[start..]);
+                    rest = "";
+                    break;
+                };
+                let name = &after[..end];
+                match strings.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)) {
+                    Some((_, value)) => out.push_str(value),
+                    None if name.is_empty() => out.push('%'),
+                    None => out.push_str(&rest[start..start + end + 2]),
+                }
+                rest = &after[end + 1..];
+            }
+            out.push_str(rest);
+            *s = out;
+        };
+        for section in &mut self.sections {
+            if is_strings_section(&section.name) {
+                continue;
+            }
+            for line in &mut section.lines {
+                line.key.iter_mut().for_each(substitute);
+                line.values.iter_mut().for_each(substitute);
+            }
+        }
+    }
+
+    pub fn sections(&self) -> &[InfSection] {
+        &self.sections
+    }
+
+    /// Lines of all sections named `name`, which may appear several times
+    pub fn section(&self, name: &str) -> impl Iterator<Item = &InfLine> {
+        let name = name.to_string();
+        self.sections.iter().filter(move |s| s.name.eq_ignore_ascii_case(&name)).flat_map(|s| &s.lines)
+    }
+
+    /// First value of `key` in section `section`
+    pub fn value(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)
+            .find(|line| line.key.as_deref().is_some_and(|k| k.eq_ignore_ascii_case(key)))
+            .and_then(|line| line.values.first())
+            .map(String::as_str)
+    }
+
+    /// Lines of the section and of its decoration for the current platform, e.g. `SourceDisksFiles.amd64`
+    fn platform_section(&self, name: &str) -> impl Iterator<Item = &InfLine> {
+        self.section(name).chain(self.section(&format!("{name}.{ARCH_DIR}")))
+    }
+
+    /// Names of the models sections listed in `[Manufacturer]`, including all platform decorations
+    pub fn models_sections(&self) -> Vec<String> {
+        let mut names = vec![];
+        for line in self.section("Manufacturer") {
+            let Some((models, decorations)) = line.values.split_first() else { continue };
+            names.push(models.clone());
+            names.extend(decorations.iter().filter(|d| !d.is_empty()).map(|d| format!("{models}.{d}")));
+        }
+        names
+    }
+
+    /// Hardware IDs of all models, upper case
+    pub fn hardware_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = vec![];
+        for models in self.models_sections() {
+            for line in self.section(&models) {
+                for id in line.values.iter().skip(1).filter(|id| !id.is_empty()) {
+                    let id = id.to_ascii_uppercase();
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /// Whether the INF has a model for one of the hardware IDs of `dev`
+    pub fn targets(&self, dev: &DeviceInfo) -> bool {
+        let ids = self.hardware_ids();
+        device_hardware_ids(dev).iter().any(|id| ids.contains(id))
+    }
+
+    /// Catalog file for the current platform from `[Version]`
+    pub fn catalog_file(&self) -> Option<&str> {
+        [format!("CatalogFile.NT{ARCH_DIR}"), "CatalogFile.NT".to_string(), "CatalogFile".to_string()]
+            .iter()
+            .find_map(|key| self.value("Version", key))
+            .filter(|file| !file.is_empty())
+    }
+
+    /// Files listed in `[SourceDisksFiles]` for the current platform, relative to the INF
+    ///
+    /// Paths are taken from the INF as they are and may contain `..` components.
+    pub fn source_files(&self) -> Vec<PathBuf> {
+        let disk_path = |disk: &str| {
+            self.platform_section("SourceDisksNames")
+                .find(|line| line.key.as_deref() == Some(disk))
+                .and_then(|line| line.values.get(3))
+                .map_or("", String::as_str)
+        };
+        let relative = |path: &str| PathBuf::from_iter(path.split(['\\', '/']).filter(|part| !part.is_empty()));
+        let mut files = vec![];
+        for line in self.platform_section("SourceDisksFiles") {
+            let Some(name) = line.key.as_deref().or(line.values.first().map(String::as_str)) else { continue };
+            let (disk, subdir) = match &line.key {
+                Some(_) => (line.values.first().map_or("", String::as_str), line.values.get(1).map_or("", String::as_str)),
+                None => ("", ""),
+            };
+            let file = relative(disk_path(disk)).join(relative(subdir)).join(name);
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        files
+    }
+}
@@ -0,0 +1,461 @@
+use std::{fmt, fs, io, marker::PhantomData, path::{Path, PathBuf}, thread};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::enums::Result;
+use crate::lock::LockMode;
+
+/// Process holding an [`InstallLock`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    /// Name of the tool given in [`InstallLockOptions::tool`]
+    pub tool: String,
+    /// Seconds since the Unix epoch
+    pub started: u64,
+}
+
+/// [`InstallLock`] that could not be acquired in the [`LockMode`] of its options
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum InstallLockError {
+    #[error("Install lock is held by {}", .0.as_ref().map_or("another process".to_string(), |owner| owner.to_string()))]
+    Held(Option<LockOwner>),
+}
+
+/// Mechanism used for mutual exclusion, owner information is always stored in a file
+///
+/// Only the lock itself decides whether it is held, the owner file is used for reporting, so all tools
+/// using a lock with the same name must use the same backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockBackend {
+    /// Lock on a file in the lock directory, released by the system when the owner exits
+    #[default]
+    File,
+    /// Named mutex in the global namespace, shared by all sessions
+    #[cfg(windows)]
+    NamedMutex,
+}
+
+/// Builder of [`InstallLock`]
+#[derive(Debug, Clone)]
+pub struct InstallLockOptions {
+    name: String,
+    tool: String,
+    dir: PathBuf,
+    mode: LockMode,
+    backend: LockBackend,
+}
+
+/// Lock held across processes while preparing/installing drivers, released on drop
+///
+/// Named mutexes must be released by the thread that acquired them, so the lock cannot be sent
+/// to another thread.
+#[derive(Debug)]
+pub struct InstallLock {
+    owner_path: PathBuf,
+    stale_owner: Option<LockOwner>,
+    guard: Guard,
+    _thread: PhantomData<*const ()>,
+}
+
+#[derive(Debug)]
+enum Guard {
+    File(fs::File),
+    #[cfg(windows)]
+    NamedMutex(mutex::NamedMutex),
+}
+
+enum Acquired {
+    No,
+    Yes,
+    /// Previous owner exited without releasing the lock
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Abandoned,
+}
+
+impl Acquired {
+    fn is_acquired(&self) -> bool {
+        !matches!(self, Acquired::No)
+    }
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl LockOwner {
+    fn current(tool: &str) -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        Self { pid: std::process::id(), tool: tool.to_string(), started }
+    }
+
+    fn read(path: &Path) -> Option<Self> {
+        // Missing or being replaced by another process
+        serde_json::from_slice(&fs::read(path).ok()?).ok()
+    }
+}
+
+impl fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {}, started at {})", self.tool, self.pid, self.started)
+    }
+}
+
+impl InstallLockOptions {
+    /// Lock directory, the system temporary directory by default
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Name of the tool reported to processes waiting for the lock, "libwdi" by default
+    pub fn tool(mut self, tool: &str) -> Self {
+        self.tool = tool.to_string();
+        self
+    }
+
+    /// How to wait for the lock held by another process, fails with [`InstallLockError::Held`] if it cannot be acquired
+    pub fn mode(mut self, mode: LockMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn backend(mut self, backend: LockBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        let name: String = self.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("libwdi-{name}.{extension}"))
+    }
+
+    pub fn acquire(&self) -> Result<InstallLock> {
+        self.acquire_with(|_| {})
+    }
+
+    /// Acquire the lock calling `on_wait` with the current owner (if known) when it is held by another process
+    pub fn acquire_with(&self, mut on_wait: impl FnMut(Option<&LockOwner>)) -> Result<InstallLock> {
+        let owner_path = self.path("owner.json");
+        let mut guard = match self.backend {
+            LockBackend::File => {
+                let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(self.path("lock"))?;
+                Guard::File(file)
+            },
+            #[cfg(windows)]
+            LockBackend::NamedMutex => Guard::NamedMutex(mutex::NamedMutex::new(&self.name)?),
+        };
+        let deadline = match self.mode {
+            LockMode::Block => None,
+            LockMode::TryLock => Some(Instant::now()),
+            LockMode::Timeout(timeout) => Some(Instant::now() + timeout),
+        };
+
+        let mut waiting = false;
+        let acquired = loop {
+            // First attempt does not wait, so that the owner can be reported before waiting
+            let timeout = match deadline {
+                _ if !waiting => Some(Duration::ZERO),
+                Some(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+                None => None,
+            };
+            let acquired = guard.wait(timeout)?;
+            if acquired.is_acquired() {
+                break acquired;
+            }
+            let owner = LockOwner::read(&owner_path);
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(InstallLockError::Held(owner).into());
+            }
+            if !waiting {
+                on_wait(owner.as_ref());
+                waiting = true;
+            }
+        };
+        // The owner file is removed before the lock is released, so it has been left by a process
+        // that exited while holding the lock (its PID may already be reused, so it is not checked)
+        let stale_owner = LockOwner::read(&owner_path);
+
+        if let Some(owner) = &stale_owner {
+            log::warn!("Taking over install lock \"{}\" left by {owner}", self.name);
+        } else if matches!(acquired, Acquired::Abandoned) {
+            log::warn!("Taking over abandoned install lock \"{}\"", self.name);
+        }
+
+        let temp = self.path("owner.json.tmp");
+        fs::write(&temp, serde_json::to_vec(&LockOwner::current(&self.tool))?)?;
+        fs::rename(&temp, &owner_path)?;
+        Ok(InstallLock { owner_path, stale_owner, guard, _thread: PhantomData })
+    }
+
+    /// Run `f` while holding the lock
+    ///
+    /// To hold the lock while preparing and installing a driver use
+    /// [`PrepareDriverOptions::install_lock`](crate::PrepareDriverOptions::install_lock) instead.
+    pub fn run<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let _lock = self.acquire()?;
+        f()
+    }
+}
+
+impl Guard {
+    fn wait(&mut self, timeout: Option<Duration>) -> Result<Acquired> {
+        match self {
+            Guard::File(file) => match timeout {
+                Some(timeout) => try_lock_file(file, timeout),
+                None => {
+                    file_lock::lock(file)?;
+                    Ok(Acquired::Yes)
+                },
+            },
+            #[cfg(windows)]
+            Guard::NamedMutex(mutex) => mutex.wait(timeout),
+        }
+    }
+
+    fn release(&mut self) {
+        match self {
+            Guard::File(file) => {
+                if let Err(err) = file_lock::unlock(file) {
+                    log::error!("Failed to release install lock: {err}");
+                }
+            },
+            #[cfg(windows)]
+            Guard::NamedMutex(mutex) => mutex.release(),
+        }
+    }
+}
+
+fn try_lock_file(file: &fs::File, timeout: Duration) -> Result<Acquired> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if file_lock::try_lock(file)? {
+            return Ok(Acquired::Yes);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(Acquired::No);
+        }
+        thread::sleep(remaining.min(POLL_INTERVAL));
+    }
+}
+
+impl InstallLock {
+    /// Lock named `name`, which should be the same for all tools that may install drivers for the same devices
+    pub fn options(name: &str) -> InstallLockOptions {
+        InstallLockOptions {
+            name: name.to_string(),
+            tool: "libwdi".to_string(),
+            dir: std::env::temp_dir(),
+            mode: LockMode::Block,
+            backend: LockBackend::File,
+        }
+    }
+
+    /// Owner of the lock that exited without releasing it, if the lock has been taken over
+    pub fn stale_owner(&self) -> Option<&LockOwner> {
+        self.stale_owner.as_ref()
+    }
+
+    /// Current owner of the lock `name` in `dir`, if any process holds it
+    pub fn owner(name: &str, dir: impl Into<PathBuf>) -> Option<LockOwner> {
+        LockOwner::read(&Self::options(name).dir(dir).path("owner.json"))
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        // Removed before unlocking, so that the next owner does not consider it stale
+        if let Err(err) = fs::remove_file(&self.owner_path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::error!("Failed to remove install lock owner file: {err}");
+            }
+        }
+        self.guard.release();
+    }
+}
+
+/// Exclusive lock of a whole file, released by the system when the process exits
+#[cfg(unix)]
+mod file_lock {
+    use std::{fs, io, os::raw::c_int, os::unix::io::AsRawFd};
+
+    const LOCK_EX: c_int = 2;
+    const LOCK_NB: c_int = 4;
+    const LOCK_UN: c_int = 8;
+
+    extern "C" {
+        fn flock(fd: c_int, operation: c_int) -> c_int;
+    }
+
+    fn flock_file(file: &fs::File, operation: c_int) -> io::Result<()> {
+        if unsafe { flock(file.as_raw_fd(), operation) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn lock(file: &fs::File) -> io::Result<()> {
+        flock_file(file, LOCK_EX)
+    }
+
+    /// Returns false if the file is locked by someone else
+    pub fn try_lock(file: &fs::File) -> io::Result<bool> {
+        match flock_file(file, LOCK_EX | LOCK_NB) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn unlock(file: &fs::File) -> io::Result<()> {
+        flock_file(file, LOCK_UN)
+    }
+}
+
+#[cfg(windows)]
+mod file_lock {
+    use std::{fs, io, os::raw::c_void, os::windows::io::AsRawHandle};
+
+    const LOCKFILE_FAIL_IMMEDIATELY: u32 = 1;
+    const LOCKFILE_EXCLUSIVE_LOCK: u32 = 2;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    #[repr(C)]
+    struct Overlapped {
+        internal: usize,
+        internal_high: usize,
+        offset: u32,
+        offset_high: u32,
+        event: *mut c_void,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LockFileEx(file: *mut c_void, flags: u32, reserved: u32, len_low: u32, len_high: u32, overlapped: *mut Overlapped) -> i32;
+        fn UnlockFile(file: *mut c_void, offset_low: u32, offset_high: u32, len_low: u32, len_high: u32) -> i32;
+    }
+
+    fn lock_file(file: &fs::File, flags: u32) -> io::Result<()> {
+        let mut overlapped = Overlapped { internal: 0, internal_high: 0, offset: 0, offset_high: 0, event: std::ptr::null_mut() };
+        let ret = unsafe { LockFileEx(file.as_raw_handle(), flags, 0, u32::MAX, u32::MAX, &mut overlapped) };
+        if ret != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn lock(file: &fs::File) -> io::Result<()> {
+        lock_file(file, LOCKFILE_EXCLUSIVE_LOCK)
+    }
+
+    /// Returns false if the file is locked by someone else
+    pub fn try_lock(file: &fs::File) -> io::Result<bool> {
+        match lock_file(file, LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY) {
+            Ok(()) => Ok(true),
+            Err(err) if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn unlock(file: &fs::File) -> io::Result<()> {
+        if unsafe { UnlockFile(file.as_raw_handle(), 0, 0, u32::MAX, u32::MAX) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod file_lock {
+    use std::{fs, io};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "file locking is not supported on this platform")
+    }
+
+    pub fn lock(_file: &fs::File) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn try_lock(_file: &fs::File) -> io::Result<bool> {
+        Err(unsupported())
+    }
+
+    pub fn unlock(_file: &fs::File) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(windows)]
+mod mutex {
+    use std::{io, os::raw::c_void, ptr, time::Duration};
+
+    use super::Acquired;
+    use crate::enums::Result;
+
+    const WAIT_OBJECT_0: u32 = 0;
+    const WAIT_ABANDONED: u32 = 0x80;
+    const WAIT_TIMEOUT: u32 = 0x102;
+    const INFINITE: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateMutexW(attributes: *const c_void, initial_owner: i32, name: *const u16) -> *mut c_void;
+        fn WaitForSingleObject(handle: *mut c_void, milliseconds: u32) -> u32;
+        fn ReleaseMutex(handle: *mut c_void) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    #[derive(Debug)]
+    pub struct NamedMutex {
+        handle: *mut c_void,
+        owned: bool,
+    }
+
+    impl NamedMutex {
+        pub fn new(name: &str) -> Result<Self> {
+            // Backslash is the only character not allowed in object names
+            let name: Vec<u16> = format!("Global\\libwdi-install-{}", name.replace('\\', "_"))
+                .encode_utf16()
+                .chain([0])
+                .collect();
+            let handle = unsafe { CreateMutexW(ptr::null(), 0, name.as_ptr()) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(Self { handle, owned: false })
+        }
+
+        pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Acquired> {
+            let ms = timeout.map_or(INFINITE, |t| t.as_millis().min(INFINITE as u128 - 1) as u32);
+            let acquired = match unsafe { WaitForSingleObject(self.handle, ms) } {
+                WAIT_OBJECT_0 => Acquired::Yes,
+                WAIT_ABANDONED => Acquired::Abandoned,
+                WAIT_TIMEOUT => Acquired::No,
+                _ => return Err(io::Error::last_os_error().into()),
+            };
+            self.owned = !matches!(acquired, Acquired::No);
+            Ok(acquired)
+        }
+
+        pub fn release(&mut self) {
+            if self.owned && unsafe { ReleaseMutex(self.handle) } == 0 {
+                log::error!("Failed to release install lock: {}", io::Error::last_os_error());
+            }
+            self.owned = false;
+        }
+    }
+
+    impl Drop for NamedMutex {
+        fn drop(&mut self) {
+            self.release();
+            unsafe { CloseHandle(self.handle) };
+        }
+    }
+}
@@ -1,7 +1,75 @@
+//! Idiomatic wrappers of [libwdi](https://github.com/pbatard/libwdi), a Windows Driver Installation
+//! library for USB devices
+//!
+//! See the [libwdi wiki](https://github.com/pbatard/libwdi/wiki/Usage) for how libwdi works. Installing
+//! a driver for every device without one:
+//! ```no_run
+//! # const DEFAULT_DIR: &str = "usb_driver";
+//! # const INF_NAME: &str = "usb_device.inf";
+//! if let Ok(mut devices) = libwdi::CreateListOptions::new().create_list() {
+//!     for dev in devices.iter_mut() {
+//!         println!("Installing driver for USB device: \"{}\" ({:04x}:{:04x})",
+//!             dev.desc(), dev.vid(), dev.pid());
+//!         libwdi::PrepareDriverOptions::new()
+//!             .prepare_driver(dev, DEFAULT_DIR, INF_NAME)
+//!             .and_then(|driver| driver.install_driver())
+//!             .ok();
+//!     }
+//! }
+//! ```
+//!
+//! Drivers can also be prepared for a [`DeviceDescriptor`] (a device that is not connected or a
+//! modified copy of a listed one), for a whole product family
+//! ([`PrepareDriverOptions::prepare_driver_for_models`]) or installed from a vendor package
+//! ([`ExternalPackage`]). A [`PreparedPackage`] can be saved and installed later, e.g. by an elevated
+//! process.
+//!
+//! Calls into libwdi are serialized within the process ([`set_lock_mode`]) and, with an
+//! [`InstallLock`], across processes. [`set_audit_log`] records all driver changes to a hash-chained log.
+//!
+//! The crate builds on all platforms with the same API, so that cross-platform applications don't need
+//! to `cfg`-gate its usage. Outside of Windows listing finds no devices and driver operations fail with
+//! [`Error::NotSupported`]. With the `fake` feature libwdi is replaced by `libwdi_sys::fake`, which runs
+//! the wrappers on any platform (also under Miri) with configurable devices and results.
+
+mod audit;
+mod codepage;
+mod composite;
 mod core;
+mod descriptor;
 mod enums;
+mod external;
+mod ffi;
+mod fixture;
+mod inf;
+mod install_lock;
+mod lock;
 mod misc;
+mod models;
+pub mod msos;
+mod package;
+mod progress;
+mod recommend;
+mod report;
+mod topology;
+mod window;
 
-pub use enums::{Error, Result, LogLevel, DriverType};
+pub use audit::{set_audit_log, verify_audit_log, AuditEntry, AuditError, AuditLog, AuditOperation, AuditRecord, AuditVerification};
+pub use codepage::{CodePage, DecodeError};
+pub use composite::{group_composite, CompositeDevice};
+pub use enums::{Error, DriverError, FileError, Result, LogLevel, DriverType, DriverMetadata, DriverOption, EMBEDDED_DRIVERS};
 pub use misc::*;
+pub use models::{add_inf_models, DeviceModel};
 pub use crate::core::*;
+pub use descriptor::{DeviceDescriptor, DeviceDescriptorBuilder};
+pub use external::{ExternalPackage, ExternalPackageError};
+pub use fixture::{DeviceRecord, ListFixture, ListOptionsRecord, RecordedString};
+pub use inf::{InfFile, InfLine, InfSection};
+pub use install_lock::{InstallLock, InstallLockError, InstallLockOptions, LockBackend, LockOwner};
+pub use lock::{lock_mode, set_lock_mode, LockMode};
+pub use package::{DeviceIdentity, PackageOptions, PreparedPackage};
+pub use progress::{Phase, ProgressEvent, ProgressKind, ProgressObserver};
+pub use recommend::{recommend_driver, Recommendation, Suggestion, UsbClass};
+pub use report::{Check, DriverSupport, EmbeddedFile, OsVersion, SystemReport, Verdict};
+pub use topology::{DeviceTree, NodeKind, SystemTree, Topology, TopologyNode};
+pub use window::WindowHandle;
@@ -0,0 +1,82 @@
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::enums::{Error, Result};
+
+/// How calls into libwdi wait for a call running on another thread
+///
+/// libwdi keeps global state, so the wrapper serializes calls into it process-wide. Only dropping
+/// a [`DevicesList`](crate::DevicesList), which cannot report errors, always blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Wait until the other call finishes
+    #[default]
+    Block,
+    /// Fail with [`Error::Busy`] immediately
+    TryLock,
+    /// Wait at most the given time, then fail with [`Error::Busy`]
+    Timeout(Duration),
+}
+
+static MODE: Mutex<LockMode> = Mutex::new(LockMode::Block);
+static LOCKED: Mutex<bool> = Mutex::new(false);
+static RELEASED: Condvar = Condvar::new();
+
+// Nothing can panic while these are held, but poisoning must not disable libwdi for the process
+fn locked() -> MutexGuard<'static, bool> {
+    LOCKED.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Set how calls into libwdi wait for each other, for all threads
+pub fn set_lock_mode(mode: LockMode) {
+    *MODE.lock().unwrap_or_else(|err| err.into_inner()) = mode;
+}
+
+pub fn lock_mode() -> LockMode {
+    *MODE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Exclusive access to libwdi, released on drop
+pub(crate) struct WdiLock(());
+
+impl WdiLock {
+    /// Acquire the lock according to the current [`LockMode`]
+    pub(crate) fn acquire() -> Result<Self> {
+        Self::acquire_with(lock_mode())
+    }
+
+    pub(crate) fn acquire_blocking() -> Self {
+        let mut locked = locked();
+        while *locked {
+            locked = RELEASED.wait(locked).unwrap_or_else(|err| err.into_inner());
+        }
+        *locked = true;
+        Self(())
+    }
+
+    fn acquire_with(mode: LockMode) -> Result<Self> {
+        let timeout = match mode {
+            LockMode::Block => return Ok(Self::acquire_blocking()),
+            LockMode::TryLock => Duration::ZERO,
+            LockMode::Timeout(timeout) => timeout,
+        };
+        let deadline = Instant::now() + timeout;
+        let mut locked = locked();
+        while *locked {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Busy);
+            }
+            locked = RELEASED.wait_timeout(locked, remaining).unwrap_or_else(|err| err.into_inner()).0;
+        }
+        *locked = true;
+        Ok(Self(()))
+    }
+}
+
+impl Drop for WdiLock {
+    fn drop(&mut self) {
+        *locked() = false;
+        RELEASED.notify_all();
+    }
+}
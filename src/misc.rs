@@ -1,39 +1,63 @@
 use std::{ffi, ptr};
 
-use libwdi_sys as wdi;
+use crate::ffi as wdi;
 
 use crate::enums::{LogLevel, check_error, Result, DriverType};
+use crate::lock::WdiLock;
+use crate::window::WindowHandle;
 
 pub struct DriverInfo(pub wdi::tagVS_FIXEDFILEINFO);
 
+impl DriverInfo {
+    /// File version of the driver, e.g. `[6, 1, 7600, 16385]`
+    pub fn file_version(&self) -> [u16; 4] {
+        let (ms, ls) = (self.0.dwFileVersionMS, self.0.dwFileVersionLS);
+        [(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16]
+    }
+}
+
+/// Returns `None` if the vendor is unknown or its name is not valid UTF-8
 pub fn get_vendor_name(vid: u16) -> Option<&'static str> {
+    try_get_vendor_name(vid).ok().flatten()
+}
+
+/// Fails if the vendor name is not valid UTF-8
+pub fn try_get_vendor_name(vid: u16) -> Result<Option<&'static str>> {
+    Ok(get_vendor_name_bytes(vid).map(std::str::from_utf8).transpose()?)
+}
+
+/// Raw bytes of the vendor name (without the terminating NUL)
+pub fn get_vendor_name_bytes(vid: u16) -> Option<&'static [u8]> {
+    // Lookup in a static table, which needs no serialization
     let name = unsafe { wdi::wdi_get_vendor_name(vid) };
     if name.is_null() {
         None
     } else {
-        let name = unsafe { ffi::CStr::from_ptr(name) };
-        // libwdi guarantees UTF-8 strings
-        Some(name.to_str().unwrap())
+        // Vendor names are static strings in libwdi
+        Some(unsafe { ffi::CStr::from_ptr(name) }.to_bytes())
     }
 }
 
-pub fn get_wdf_version() -> std::os::raw::c_int {
-    unsafe {
+pub fn get_wdf_version() -> Result<std::os::raw::c_int> {
+    let _lock = WdiLock::acquire()?;
+    Ok(unsafe {
         wdi::wdi_get_wdf_version()
-    }
+    })
 }
 
 /// Returns `Some` if driver is supported. For WinUsb/LibUsb0/LibUsbK the info structure is filled,
 /// otherwise it is zeroed.
-pub fn is_driver_supported(driver_type: DriverType) -> Option<DriverInfo> {
+pub fn is_driver_supported(driver_type: DriverType) -> Result<Option<DriverInfo>> {
     let mut info: wdi::tagVS_FIXEDFILEINFO = unsafe { std::mem::zeroed() };
+    let lock = WdiLock::acquire()?;
     let is_supported = unsafe {
         wdi::wdi_is_driver_supported(driver_type.to_ffi(), &mut info as *mut wdi::tagVS_FIXEDFILEINFO) != 0
     };
+    drop(lock);
     if is_supported {
-        Some(DriverInfo(info))
+        Ok(Some(DriverInfo(info)))
     } else {
-        None
+        Ok(None)
     }
 }
 
@@ -41,31 +65,43 @@ pub fn is_driver_supported(driver_type: DriverType) -> Option<DriverInfo> {
 pub fn is_file_embedded(path: Option<&str>, name: &str) -> Result<bool> {
     let path = path.map(ffi::CString::new).transpose()?;
     let name = ffi::CString::new(name)?;
-    let path = path.map_or(ptr::null(), |s| s.as_ptr());
+    let path_ptr = path.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+    let _lock = WdiLock::acquire()?;
     let result = unsafe {
-        wdi::wdi_is_file_embedded(path as *const i8, name.as_ptr() as *const i8)
+        wdi::wdi_is_file_embedded(path_ptr, name.as_ptr())
     };
     Ok(result != 0)
 }
 
 pub fn set_log_level(level: LogLevel) -> Result<()> {
+    let _lock = WdiLock::acquire()?;
     unsafe {
         check_error(wdi::wdi_set_log_level(level.to_ffi()))
     }
 }
 
-pub unsafe fn register_logger(hwnd: wdi::HWND, message_id: wdi::UINT, buff_size: wdi::DWORD) -> Result<()> {
-    check_error(wdi::wdi_register_logger(hwnd, message_id, buff_size))
+/// libwdi will post `message_id` to the window whenever a log message is available to [`read_logger`]
+pub fn register_logger(hwnd: WindowHandle, message_id: u32, buff_size: u32) -> Result<()> {
+    let _lock = WdiLock::acquire()?;
+    unsafe {
+        check_error(wdi::wdi_register_logger(hwnd.as_raw(), message_id, buff_size as wdi::DWORD))
+    }
 }
 
-pub unsafe fn unregister_logger(hwnd: wdi::HWND) -> Result<()> {
-    check_error(wdi::wdi_unregister_logger(hwnd))
+/// `hwnd` must be the window previously passed to [`register_logger`]
+pub fn unregister_logger(hwnd: WindowHandle) -> Result<()> {
+    let _lock = WdiLock::acquire()?;
+    unsafe {
+        check_error(wdi::wdi_unregister_logger(hwnd.as_raw()))
+    }
 }
 
+/// Unlike other functions this does not wait for a running call, so that logs can be read while a
+/// driver is being installed
 pub fn read_logger(buf: &mut [u8]) -> Result<usize> {
     let mut size = 0;
     unsafe {
-        check_error(wdi::wdi_read_logger(buf.as_mut_ptr() as *mut i8, buf.len() as u32, &mut size))?
+        check_error(wdi::wdi_read_logger(buf.as_mut_ptr() as *mut _, buf.len() as wdi::DWORD, &mut size))?
     }
     Ok(size as usize)
 }
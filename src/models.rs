@@ -0,0 +1,179 @@
+use std::{fs, path::Path};
+
+use crate::core::{DeviceInfo, PrepareDriverOptions, PreparedDriver};
+use crate::enums::{Error, Result};
+use crate::inf::{decode, encode, find_unquoted, split_unquoted, InfFile};
+
+/// Device listed in the INF of a package prepared with [`PrepareDriverOptions::prepare_driver_for_models`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceModel {
+    pub vid: u16,
+    pub pid: u16,
+    /// Interface number of a composite device
+    pub mi: Option<u8>,
+    /// Device name shown in Device Manager
+    pub desc: String,
+}
+
+impl DeviceModel {
+    pub fn new(vid: u16, pid: u16, desc: &str) -> Self {
+        Self { vid, pid, mi: None, desc: desc.to_string() }
+    }
+
+    pub fn interface(mut self, mi: u8) -> Self {
+        self.mi = Some(mi);
+        self
+    }
+
+    pub fn of(dev: &DeviceInfo) -> Self {
+        Self {
+            vid: dev.vid(),
+            pid: dev.pid(),
+            mi: dev.is_composite().then_some(dev.raw().mi),
+            desc: dev.desc().into_owned(),
+        }
+    }
+
+    /// Hardware ID matched by the INF, e.g. `USB\VID_1234&PID_0001&MI_02`
+    pub fn hardware_id(&self) -> String {
+        let mut id = format!("USB\\VID_{:04X}&PID_{:04X}", self.vid, self.pid);
+        if let Some(mi) = self.mi {
+            id.push_str(&format!("&MI_{mi:02X}"));
+        }
+        id
+    }
+
+    /// Description as a quoted INF string
+    fn quoted_desc(&self) -> Result<String> {
+        if self.desc.chars().any(char::is_control) {
+            return Err(Error::InvalidParam);
+        }
+        Ok(format!("\"{}\"", self.desc.replace('"', "\"\"").replace('%', "%%")))
+    }
+}
+
+/// Add `models` to every models section of an INF file, next to its first model
+///
+/// New models use the same install section as the existing one, models already listed in the INF are
+/// skipped. The encoding of the file is kept. The catalog of the package must be generated again,
+/// e.g. using [`PrepareDriverOptions::external_inf`].
+pub fn add_inf_models(path: impl AsRef<Path>, models: &[DeviceModel]) -> Result<()> {
+    let path = path.as_ref();
+    let (text, encoding) = decode(&fs::read(path)?);
+    let out = add_models(&text, models)?;
+    fs::write(path, encode(&out, encoding))?;
+    Ok(())
+}
+
+fn add_models(text: &str, models: &[DeviceModel]) -> Result<String> {
+    let inf = InfFile::parse(text);
+    let existing = inf.hardware_ids();
+    let models_sections = inf.models_sections();
+    let new_models: Vec<_> = models.iter()
+        .filter(|model| !existing.contains(&model.hardware_id()))
+        .collect();
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let mut out = String::with_capacity(text.len());
+    let mut section = String::new();
+    let mut added = false;
+    let mut logical = String::new();
+    for line in text.split_inclusive('\n') {
+        out.push_str(line);
+        let content = line.trim_end_matches(['\r', '\n']);
+        let content = find_unquoted(content, ';').map_or(content, |comment| &content[..comment]).trim_end();
+        if let Some(continued) = content.strip_suffix('\\') {
+            logical.push_str(continued);
+            continue;
+        }
+        logical.push_str(content);
+        let content = std::mem::take(&mut logical);
+        let content = content.trim();
+        if let Some(name) = content.strip_prefix('[').and_then(|l| l.split_once(']')).map(|(name, _)| name) {
+            section = name.trim().to_string();
+            added = false;
+            continue;
+        }
+        if added || !models_sections.iter().any(|name| name.eq_ignore_ascii_case(&section)) {
+            continue;
+        }
+        let Some(install) = find_unquoted(content, '=')
+            .and_then(|i| split_unquoted(&content[i + 1..], ',').first().map(|s| s.trim().to_string()))
+        else {
+            continue;
+        };
+        if !line.ends_with('\n') {
+            out.push_str(newline);
+        }
+        for model in &new_models {
+            out.push_str(&format!("{} = {install}, {}{newline}", model.quoted_desc()?, model.hardware_id()));
+        }
+        added = true;
+    }
+    Ok(out)
+}
+
+impl PrepareDriverOptions {
+    /// Prepare a driver for `dev` whose INF also lists `models`, so that one package can be signed and
+    /// installed for a whole product family
+    ///
+    /// libwdi generates the INF for `dev`, then the models are added to it and libwdi is called again
+    /// with [`external_inf`](Self::external_inf) to generate (and sign) the catalog of the final INF.
+    /// The audit log gets a single entry with the options of the final package.
+    pub fn prepare_driver_for_models<'a>(self, dev: &'a mut DeviceInfo, models: &[DeviceModel], path: &str, inf_name: &str) -> Result<PreparedDriver<'a>> {
+        // The catalog would not cover the added models
+        let generated = self.clone()
+            .disable_cat(true)
+            .external_inf(false)
+            .audit(false)
+            .prepare_driver(dev, path, inf_name)
+            .map(|mut driver| driver.take_held_lock())
+            .and_then(|held_lock| add_inf_models(Path::new(path).join(inf_name), models).map(|()| held_lock));
+        let options = self.external_inf(true);
+        match generated {
+            Ok(held_lock) => options.prepare_driver_locked(dev, path, inf_name, held_lock),
+            Err(err) => Err(options.audit_failure(dev, path, inf_name, err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn models() -> [DeviceModel; 2] {
+        [DeviceModel::new(0xcafe, 0x4002, "Board 2"), DeviceModel::new(0xcafe, 0x4003, "Board 3").interface(1)]
+    }
+
+    #[test]
+    fn models_are_added_to_each_platform() {
+        let inf = "[Manufacturer]\n%Vendor% = Boards, NTx86, NTamd64\n\n\
+            [Boards.NTx86]\n%Board% = Install_x86, USB\\VID_CAFE&PID_4001\n\n\
+            [Boards.NTamd64]\n%Board% = Install_amd64, USB\\VID_CAFE&PID_4001\n\
+            %Other% = Install_amd64, USB\\VID_CAFE&PID_4100\n";
+        let out = add_models(inf, &models()).unwrap();
+        assert_eq!(out, "[Manufacturer]\n%Vendor% = Boards, NTx86, NTamd64\n\n\
+            [Boards.NTx86]\n%Board% = Install_x86, USB\\VID_CAFE&PID_4001\n\
+            \"Board 2\" = Install_x86, USB\\VID_CAFE&PID_4002\n\
+            \"Board 3\" = Install_x86, USB\\VID_CAFE&PID_4003&MI_01\n\n\
+            [Boards.NTamd64]\n%Board% = Install_amd64, USB\\VID_CAFE&PID_4001\n\
+            \"Board 2\" = Install_amd64, USB\\VID_CAFE&PID_4002\n\
+            \"Board 3\" = Install_amd64, USB\\VID_CAFE&PID_4003&MI_01\n\
+            %Other% = Install_amd64, USB\\VID_CAFE&PID_4100\n");
+        // Models already listed are not duplicated
+        assert_eq!(add_models(&out, &models()).unwrap(), out);
+    }
+
+    #[test]
+    fn models_follow_continued_lines() {
+        let inf = "[Manufacturer]\r\n%Vendor% = Boards, \\\r\n    NTamd64\r\n\r\n\
+            [Boards.NTamd64]\r\n%Board% = \\ ; install section on the next line\r\n    Install, \\\r\n    USB\\VID_CAFE&PID_4001\r\n\
+            [Strings]\r\nBoard = \"Board 1\"";
+        let out = add_models(inf, &models()).unwrap();
+        let added = "    USB\\VID_CAFE&PID_4001\r\n\
+            \"Board 2\" = Install, USB\\VID_CAFE&PID_4002\r\n\
+            \"Board 3\" = Install, USB\\VID_CAFE&PID_4003&MI_01\r\n[Strings]";
+        assert!(out.contains(added), "{out}");
+        assert_eq!(InfFile::parse(&out).hardware_ids().len(), 3);
+    }
+}
@@ -0,0 +1,1010 @@
+//! Microsoft OS descriptors, which make Windows bind a driver (e.g. WinUSB) to a device automatically
+//!
+//! MS OS 1.0 descriptors consist of the OS string descriptor (string index 0xEE) and the extended
+//! compat ID/extended properties descriptors requested with the vendor code. MS OS 2.0 descriptors
+//! are a descriptor set advertised by a platform capability in the BOS descriptor. All descriptors
+//! can be parsed (validating their structure) and built from their description.
+
+use thiserror::Error;
+
+/// String descriptor index of the MS OS 1.0 string descriptor
+pub const OS_STRING_INDEX: u8 = 0xee;
+/// wIndex of the control request for the extended compat ID descriptor
+pub const EXTENDED_COMPAT_ID_INDEX: u16 = 0x0004;
+/// wIndex of the control request for the extended properties descriptor
+pub const EXTENDED_PROPERTIES_INDEX: u16 = 0x0005;
+/// wIndex of the control request for the MS OS 2.0 descriptor set
+pub const DESCRIPTOR_SET_INDEX: u16 = 0x0007;
+/// Minimal Windows version supporting MS OS 2.0 descriptors (Windows 8.1)
+pub const WINDOWS_8_1: u32 = 0x0603_0000;
+
+const OS_STRING_SIGNATURE: &str = "MSFT100";
+const MS_OS_10_VERSION: u16 = 0x0100;
+const PLATFORM_CAPABILITY_UUID: [u8; 16] = [
+    0xdf, 0x60, 0xdd, 0xd8, 0x89, 0x45, 0xc7, 0x4c, 0x9c, 0xd2, 0x65, 0x9d, 0x9e, 0x64, 0x8a, 0x9f,
+];
+
+// MS OS 2.0 descriptor types
+const SET_HEADER_DESCRIPTOR: u16 = 0x00;
+const SUBSET_HEADER_CONFIGURATION: u16 = 0x01;
+const SUBSET_HEADER_FUNCTION: u16 = 0x02;
+const FEATURE_COMPATIBLE_ID: u16 = 0x03;
+const FEATURE_REG_PROPERTY: u16 = 0x04;
+const FEATURE_MIN_RESUME_TIME: u16 = 0x05;
+const FEATURE_MODEL_ID: u16 = 0x06;
+const FEATURE_CCGP_DEVICE: u16 = 0x07;
+const FEATURE_VENDOR_REVISION: u16 = 0x08;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorError {
+    #[error("Descriptor truncated: {expected} bytes needed, {actual} available")]
+    Truncated { expected: usize, actual: usize },
+    #[error("Invalid length {found}, expected {expected}")]
+    InvalidLength { expected: usize, found: usize },
+    #[error("Unexpected descriptor type {found:#04x}, expected {expected:#04x}")]
+    UnexpectedType { expected: u16, found: u16 },
+    #[error("Unknown descriptor type {0:#04x}")]
+    UnknownType(u16),
+    #[error("Invalid MS OS string descriptor signature")]
+    InvalidSignature,
+    #[error("Unsupported version {0:#x}")]
+    UnsupportedVersion(u32),
+    #[error("Count is {expected} but there are {found} entries")]
+    CountMismatch { expected: usize, found: usize },
+    #[error("{0} unexpected bytes after the descriptor")]
+    TrailingBytes(usize),
+    #[error("Descriptor is too long ({0} bytes)")]
+    TooLong(usize),
+    #[error("Invalid compatible ID {0:?} (up to 8 characters: A-Z, 0-9, _)")]
+    InvalidCompatibleId(String),
+    #[error("Invalid property name {0:?}")]
+    InvalidPropertyName(String),
+    #[error("Unknown property data type {0}")]
+    UnknownPropertyType(u32),
+    #[error("Invalid data of property {0:?}")]
+    InvalidPropertyData(String),
+    #[error("Invalid device interface GUID {0:?}")]
+    InvalidGuid(String),
+    #[error("Feature descriptor {0:#04x} is not allowed in a function subset")]
+    MisplacedFeature(u16),
+    #[error("Function subset for interface {0} is defined more than once")]
+    DuplicateFunction(u8),
+}
+
+pub type Result<T> = std::result::Result<T, DescriptorError>;
+
+/// MS OS 1.0 string descriptor, returned for string index [`OS_STRING_INDEX`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OsStringDescriptor {
+    /// bRequest of the vendor requests for the other MS OS 1.0 descriptors
+    pub vendor_code: u8,
+}
+
+/// Compatible ID and sub-compatible ID, e.g. "WINUSB"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibleId {
+    pub id: String,
+    pub sub_id: String,
+}
+
+/// Function (group of interfaces) of the MS OS 1.0 extended compat ID descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatIdFunction {
+    pub first_interface: u8,
+    pub compatible_id: CompatibleId,
+}
+
+/// MS OS 1.0 extended compat ID descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedCompatId {
+    pub functions: Vec<CompatIdFunction>,
+}
+
+/// MS OS 1.0 extended properties descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedProperties {
+    pub properties: Vec<RegistryProperty>,
+}
+
+/// Registry value stored in the device (or interface) registry key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryProperty {
+    pub name: String,
+    pub value: PropertyValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyValue {
+    /// REG_SZ
+    String(String),
+    /// REG_EXPAND_SZ
+    ExpandString(String),
+    /// REG_BINARY
+    Binary(Vec<u8>),
+    /// REG_DWORD_LITTLE_ENDIAN
+    Dword(u32),
+    /// REG_DWORD_BIG_ENDIAN
+    DwordBigEndian(u32),
+    /// REG_LINK
+    Link(String),
+    /// REG_MULTI_SZ
+    MultiString(Vec<String>),
+}
+
+/// Platform capability of the BOS descriptor announcing the MS OS 2.0 descriptor set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlatformCapability {
+    pub windows_version: u32,
+    /// Total length of the descriptor set, see [`DescriptorSet::total_length`]
+    pub descriptor_set_length: u16,
+    /// bRequest of the vendor request for the descriptor set
+    pub vendor_code: u8,
+    /// Non-zero if the device supports alternate enumeration
+    pub alt_enum_code: u8,
+}
+
+/// MS OS 2.0 descriptor set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorSet {
+    pub windows_version: u32,
+    /// Features applying to the whole device
+    pub features: Vec<Feature>,
+    /// Only needed for composite devices
+    pub configurations: Vec<ConfigurationSubset>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigurationSubset {
+    /// Index of the configuration (despite being called bConfigurationValue in the specification)
+    pub configuration: u8,
+    pub features: Vec<Feature>,
+    pub functions: Vec<FunctionSubset>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSubset {
+    pub first_interface: u8,
+    pub features: Vec<Feature>,
+}
+
+/// MS OS 2.0 feature descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Feature {
+    CompatibleId(CompatibleId),
+    RegistryProperty(RegistryProperty),
+    /// Resume recovery time and resume signaling time in milliseconds
+    MinResumeTime { recovery_time: u8, signaling_time: u8 },
+    ModelId([u8; 16]),
+    CcgpDevice,
+    VendorRevision(u16),
+}
+
+/// Little endian reader that reports truncated data
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.remaining() < n {
+            return Err(DescriptorError::Truncated { expected: self.pos + n, actual: self.data.len() });
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn finish(&self) -> Result<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            n => Err(DescriptorError::TrailingBytes(n)),
+        }
+    }
+}
+
+fn check_length(expected: usize, found: usize) -> Result<()> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(DescriptorError::InvalidLength { expected, found })
+    }
+}
+
+fn check_type(expected: u16, found: u16) -> Result<()> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(DescriptorError::UnexpectedType { expected, found })
+    }
+}
+
+fn length_u16(len: usize) -> Result<u16> {
+    u16::try_from(len).map_err(|_| DescriptorError::TooLong(len))
+}
+
+fn utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// NUL-terminated UTF-16LE string
+fn utf16z(s: &str) -> Vec<u8> {
+    let mut bytes = utf16(s);
+    bytes.extend_from_slice(&[0, 0]);
+    bytes
+}
+
+fn decode_utf16(bytes: &[u8]) -> Option<String> {
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]]));
+    char::decode_utf16(units).collect::<std::result::Result<String, _>>().ok()
+}
+
+/// Decode NUL-terminated UTF-16LE string that contains no other NULs
+fn decode_utf16z(bytes: &[u8]) -> Option<String> {
+    let s = decode_utf16(bytes)?;
+    let s = s.strip_suffix('\0')?;
+    (!s.contains('\0')).then(|| s.to_string())
+}
+
+/// Whether `s` is a GUID in registry format: {xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}
+pub fn is_valid_guid(s: &str) -> bool {
+    let Some(inner) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) else {
+        return false;
+    };
+    let groups: Vec<_> = inner.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+            group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+impl OsStringDescriptor {
+    const LENGTH: usize = 18;
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(data);
+        check_length(Self::LENGTH, r.u8()? as usize)?;
+        check_type(0x03, r.u8()? as u16)?;
+        if r.bytes(14)? != utf16(OS_STRING_SIGNATURE) {
+            return Err(DescriptorError::InvalidSignature);
+        }
+        let vendor_code = r.u8()?;
+        let _pad = r.u8()?;
+        r.finish()?;
+        Ok(Self { vendor_code })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![Self::LENGTH as u8, 0x03];
+        bytes.extend(utf16(OS_STRING_SIGNATURE));
+        bytes.extend([self.vendor_code, 0]);
+        bytes
+    }
+}
+
+impl CompatibleId {
+    pub fn new(id: &str, sub_id: &str) -> Self {
+        Self { id: id.to_string(), sub_id: sub_id.to_string() }
+    }
+
+    pub fn winusb() -> Self {
+        Self::new("WINUSB", "")
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for id in [&self.id, &self.sub_id] {
+            let valid = id.len() <= 8 && id.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            if !valid {
+                return Err(DescriptorError::InvalidCompatibleId(id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse(r: &mut Reader) -> Result<Self> {
+        let mut id = || -> Result<String> {
+            let bytes = r.bytes(8)?;
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            // Padding must consist of NULs only
+            let valid = bytes[end..].iter().all(|&b| b == 0) && bytes[..end].is_ascii();
+            let id = String::from_utf8_lossy(&bytes[..end]).into_owned();
+            if valid { Ok(id) } else { Err(DescriptorError::InvalidCompatibleId(id)) }
+        };
+        let compatible_id = Self { id: id()?, sub_id: id()? };
+        compatible_id.validate()?;
+        Ok(compatible_id)
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        for id in [&self.id, &self.sub_id] {
+            let mut padded = [0; 8];
+            padded[..id.len()].copy_from_slice(id.as_bytes());
+            bytes.extend(padded);
+        }
+    }
+}
+
+impl ExtendedCompatId {
+    const HEADER_LENGTH: usize = 16;
+    const FUNCTION_LENGTH: usize = 24;
+
+    /// Descriptor for a non-composite device using WinUSB
+    pub fn winusb() -> Self {
+        Self {
+            functions: vec![CompatIdFunction { first_interface: 0, compatible_id: CompatibleId::winusb() }],
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.functions.len() > u8::MAX as usize {
+            return Err(DescriptorError::TooLong(self.functions.len()));
+        }
+        self.functions.iter().try_for_each(|f| f.compatible_id.validate())
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(data);
+        let length = r.u32()? as usize;
+        check_length(data.len(), length)?;
+        let version = r.u16()?;
+        if version != MS_OS_10_VERSION {
+            return Err(DescriptorError::UnsupportedVersion(version as u32));
+        }
+        check_type(EXTENDED_COMPAT_ID_INDEX, r.u16()?)?;
+        let count = r.u8()? as usize;
+        r.bytes(7)?;
+        let found = r.remaining() / Self::FUNCTION_LENGTH;
+        if r.remaining() != count * Self::FUNCTION_LENGTH {
+            return Err(DescriptorError::CountMismatch { expected: count, found });
+        }
+        let functions = (0..count)
+            .map(|_| {
+                let first_interface = r.u8()?;
+                r.u8()?; // reserved, should be 1
+                let compatible_id = CompatibleId::parse(&mut r)?;
+                r.bytes(6)?;
+                Ok(CompatIdFunction { first_interface, compatible_id })
+            })
+            .collect::<Result<_>>()?;
+        r.finish()?;
+        Ok(Self { functions })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let length = Self::HEADER_LENGTH + self.functions.len() * Self::FUNCTION_LENGTH;
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend((length as u32).to_le_bytes());
+        bytes.extend(MS_OS_10_VERSION.to_le_bytes());
+        bytes.extend(EXTENDED_COMPAT_ID_INDEX.to_le_bytes());
+        bytes.push(self.functions.len() as u8);
+        bytes.extend([0; 7]);
+        for function in &self.functions {
+            bytes.extend([function.first_interface, 0x01]);
+            function.compatible_id.write(&mut bytes);
+            bytes.extend([0; 6]);
+        }
+        Ok(bytes)
+    }
+}
+
+impl PropertyValue {
+    fn data_type(&self) -> u32 {
+        match self {
+            PropertyValue::String(_) => 1,
+            PropertyValue::ExpandString(_) => 2,
+            PropertyValue::Binary(_) => 3,
+            PropertyValue::Dword(_) => 4,
+            PropertyValue::DwordBigEndian(_) => 5,
+            PropertyValue::Link(_) => 6,
+            PropertyValue::MultiString(_) => 7,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            PropertyValue::String(s) | PropertyValue::ExpandString(s) | PropertyValue::Link(s) => utf16z(s),
+            PropertyValue::Binary(data) => data.clone(),
+            PropertyValue::Dword(value) => value.to_le_bytes().to_vec(),
+            PropertyValue::DwordBigEndian(value) => value.to_be_bytes().to_vec(),
+            PropertyValue::MultiString(strings) => {
+                let mut bytes: Vec<u8> = strings.iter().flat_map(|s| utf16z(s)).collect();
+                bytes.extend([0, 0]);
+                bytes
+            },
+        }
+    }
+
+    fn parse(data_type: u32, data: &[u8]) -> Option<Self> {
+        let dword = || data.try_into().ok();
+        Some(match data_type {
+            1 => PropertyValue::String(decode_utf16z(data)?),
+            2 => PropertyValue::ExpandString(decode_utf16z(data)?),
+            3 => PropertyValue::Binary(data.to_vec()),
+            4 => PropertyValue::Dword(u32::from_le_bytes(dword()?)),
+            5 => PropertyValue::DwordBigEndian(u32::from_be_bytes(dword()?)),
+            6 => PropertyValue::Link(decode_utf16z(data)?),
+            7 => {
+                let s = decode_utf16(data)?;
+                let s = s.strip_suffix("\0\0").or_else(|| (s == "\0").then_some(""))?;
+                let strings = if s.is_empty() { vec![] } else { s.split('\0').map(String::from).collect() };
+                PropertyValue::MultiString(strings)
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl RegistryProperty {
+    pub fn new(name: &str, value: PropertyValue) -> Self {
+        Self { name: name.to_string(), value }
+    }
+
+    /// `DeviceInterfaceGUID` (REG_SZ), used with MS OS 1.0 descriptors
+    pub fn device_interface_guid(guid: &str) -> Self {
+        Self::new("DeviceInterfaceGUID", PropertyValue::String(guid.to_string()))
+    }
+
+    /// `DeviceInterfaceGUIDs` (REG_MULTI_SZ), used with MS OS 2.0 descriptors
+    pub fn device_interface_guids(guids: &[&str]) -> Self {
+        Self::new("DeviceInterfaceGUIDs", PropertyValue::MultiString(guids.iter().map(|s| s.to_string()).collect()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.contains('\0') {
+            return Err(DescriptorError::InvalidPropertyName(self.name.clone()));
+        }
+        let strings: Vec<&String> = match &self.value {
+            PropertyValue::String(s) | PropertyValue::ExpandString(s) | PropertyValue::Link(s) => vec![s],
+            PropertyValue::MultiString(strings) => strings.iter().collect(),
+            _ => vec![],
+        };
+        let multi = matches!(self.value, PropertyValue::MultiString(_));
+        if strings.iter().any(|s| s.contains('\0') || (multi && s.is_empty())) {
+            return Err(DescriptorError::InvalidPropertyData(self.name.clone()));
+        }
+        if self.name.eq_ignore_ascii_case("DeviceInterfaceGUID") || self.name.eq_ignore_ascii_case("DeviceInterfaceGUIDs") {
+            if let Some(guid) = strings.iter().find(|s| !is_valid_guid(s)) {
+                return Err(DescriptorError::InvalidGuid(guid.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_value(name: String, data_type: u32, data: &[u8]) -> Result<Self> {
+        if !(1..=7).contains(&data_type) {
+            return Err(DescriptorError::UnknownPropertyType(data_type));
+        }
+        match PropertyValue::parse(data_type, data) {
+            Some(value) => {
+                let property = Self { name, value };
+                property.validate()?;
+                Ok(property)
+            },
+            None => Err(DescriptorError::InvalidPropertyData(name)),
+        }
+    }
+
+    fn parse_name(bytes: &[u8]) -> Result<String> {
+        decode_utf16z(bytes).ok_or_else(|| DescriptorError::InvalidPropertyName(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+impl ExtendedProperties {
+    const HEADER_LENGTH: usize = 10;
+
+    pub fn validate(&self) -> Result<()> {
+        if self.properties.len() > u16::MAX as usize {
+            return Err(DescriptorError::TooLong(self.properties.len()));
+        }
+        self.properties.iter().try_for_each(RegistryProperty::validate)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(data);
+        let length = r.u32()? as usize;
+        check_length(data.len(), length)?;
+        let version = r.u16()?;
+        if version != MS_OS_10_VERSION {
+            return Err(DescriptorError::UnsupportedVersion(version as u32));
+        }
+        check_type(EXTENDED_PROPERTIES_INDEX, r.u16()?)?;
+        let count = r.u16()? as usize;
+        let mut properties = Vec::with_capacity(count);
+        while r.remaining() > 0 {
+            let start = r.pos;
+            let size = r.u32()? as usize;
+            let data_type = r.u32()?;
+            let name_length = r.u16()? as usize;
+            let name = RegistryProperty::parse_name(r.bytes(name_length)?)?;
+            let data_length = r.u32()? as usize;
+            let data = r.bytes(data_length)?;
+            check_length(r.pos - start, size)?;
+            properties.push(RegistryProperty::parse_value(name, data_type, data)?);
+        }
+        if properties.len() != count {
+            return Err(DescriptorError::CountMismatch { expected: count, found: properties.len() });
+        }
+        Ok(Self { properties })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let mut body = Vec::new();
+        for property in &self.properties {
+            let name = utf16z(&property.name);
+            let data = property.value.to_bytes();
+            let size = 4 + 4 + 2 + name.len() + 4 + data.len();
+            body.extend((size as u32).to_le_bytes());
+            body.extend(property.value.data_type().to_le_bytes());
+            body.extend(length_u16(name.len())?.to_le_bytes());
+            body.extend(name);
+            body.extend((data.len() as u32).to_le_bytes());
+            body.extend(data);
+        }
+        let length = Self::HEADER_LENGTH + body.len();
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend((length as u32).to_le_bytes());
+        bytes.extend(MS_OS_10_VERSION.to_le_bytes());
+        bytes.extend(EXTENDED_PROPERTIES_INDEX.to_le_bytes());
+        bytes.extend((self.properties.len() as u16).to_le_bytes());
+        bytes.extend(body);
+        Ok(bytes)
+    }
+}
+
+impl PlatformCapability {
+    const LENGTH: usize = 28;
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(data);
+        check_length(Self::LENGTH, r.u8()? as usize)?;
+        check_type(0x10, r.u8()? as u16)?; // DEVICE CAPABILITY
+        check_type(0x05, r.u8()? as u16)?; // PLATFORM
+        r.u8()?;
+        if r.bytes(16)? != PLATFORM_CAPABILITY_UUID {
+            return Err(DescriptorError::InvalidSignature);
+        }
+        let capability = Self {
+            windows_version: r.u32()?,
+            descriptor_set_length: r.u16()?,
+            vendor_code: r.u8()?,
+            alt_enum_code: r.u8()?,
+        };
+        r.finish()?;
+        Ok(capability)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![Self::LENGTH as u8, 0x10, 0x05, 0x00];
+        bytes.extend(PLATFORM_CAPABILITY_UUID);
+        bytes.extend(self.windows_version.to_le_bytes());
+        bytes.extend(self.descriptor_set_length.to_le_bytes());
+        bytes.extend([self.vendor_code, self.alt_enum_code]);
+        bytes
+    }
+}
+
+impl Feature {
+    fn descriptor_type(&self) -> u16 {
+        match self {
+            Feature::CompatibleId(_) => FEATURE_COMPATIBLE_ID,
+            Feature::RegistryProperty(_) => FEATURE_REG_PROPERTY,
+            Feature::MinResumeTime { .. } => FEATURE_MIN_RESUME_TIME,
+            Feature::ModelId(_) => FEATURE_MODEL_ID,
+            Feature::CcgpDevice => FEATURE_CCGP_DEVICE,
+            Feature::VendorRevision(_) => FEATURE_VENDOR_REVISION,
+        }
+    }
+
+    /// Whether the feature applies to a function, otherwise it can only describe the whole device
+    fn allowed_in_function(&self) -> bool {
+        matches!(self, Feature::CompatibleId(_) | Feature::RegistryProperty(_) | Feature::VendorRevision(_))
+    }
+
+    fn validate(&self, in_function: bool) -> Result<()> {
+        if in_function && !self.allowed_in_function() {
+            return Err(DescriptorError::MisplacedFeature(self.descriptor_type()));
+        }
+        match self {
+            Feature::CompatibleId(id) => id.validate(),
+            Feature::RegistryProperty(property) => property.validate(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse the feature which header (length and type) has already been read
+    fn parse(length: usize, descriptor_type: u16, r: &mut Reader) -> Result<Self> {
+        let fixed = |expected: usize| check_length(expected, length);
+        Ok(match descriptor_type {
+            FEATURE_COMPATIBLE_ID => {
+                fixed(20)?;
+                Feature::CompatibleId(CompatibleId::parse(r)?)
+            },
+            FEATURE_REG_PROPERTY => {
+                let start = r.pos - 4;
+                let data_type = r.u16()? as u32;
+                let name_length = r.u16()? as usize;
+                let name = RegistryProperty::parse_name(r.bytes(name_length)?)?;
+                let data_length = r.u16()? as usize;
+                let data = r.bytes(data_length)?;
+                check_length(r.pos - start, length)?;
+                Feature::RegistryProperty(RegistryProperty::parse_value(name, data_type, data)?)
+            },
+            FEATURE_MIN_RESUME_TIME => {
+                fixed(6)?;
+                Feature::MinResumeTime { recovery_time: r.u8()?, signaling_time: r.u8()? }
+            },
+            FEATURE_MODEL_ID => {
+                fixed(20)?;
+                let mut id = [0; 16];
+                id.copy_from_slice(r.bytes(16)?);
+                Feature::ModelId(id)
+            },
+            FEATURE_CCGP_DEVICE => {
+                fixed(4)?;
+                Feature::CcgpDevice
+            },
+            FEATURE_VENDOR_REVISION => {
+                fixed(6)?;
+                Feature::VendorRevision(r.u16()?)
+            },
+            other => return Err(DescriptorError::UnknownType(other)),
+        })
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        let mut body = Vec::new();
+        match self {
+            Feature::CompatibleId(id) => id.write(&mut body),
+            Feature::RegistryProperty(property) => {
+                let name = utf16z(&property.name);
+                let data = property.value.to_bytes();
+                body.extend((property.value.data_type() as u16).to_le_bytes());
+                body.extend(length_u16(name.len())?.to_le_bytes());
+                body.extend(name);
+                body.extend(length_u16(data.len())?.to_le_bytes());
+                body.extend(data);
+            },
+            Feature::MinResumeTime { recovery_time, signaling_time } => body.extend([*recovery_time, *signaling_time]),
+            Feature::ModelId(id) => body.extend(id),
+            Feature::CcgpDevice => {},
+            Feature::VendorRevision(revision) => body.extend(revision.to_le_bytes()),
+        }
+        bytes.extend(length_u16(4 + body.len())?.to_le_bytes());
+        bytes.extend(self.descriptor_type().to_le_bytes());
+        bytes.extend(body);
+        Ok(())
+    }
+}
+
+/// Read features until `end` or until a subset header
+fn parse_features(r: &mut Reader, end: usize, in_function: bool) -> Result<Vec<Feature>> {
+    let mut features = vec![];
+    while r.pos < end {
+        let start = r.pos;
+        let length = r.u16()? as usize;
+        let descriptor_type = r.u16()?;
+        if matches!(descriptor_type, SUBSET_HEADER_CONFIGURATION | SUBSET_HEADER_FUNCTION) {
+            r.pos = start;
+            break;
+        }
+        let feature = Feature::parse(length, descriptor_type, r)?;
+        feature.validate(in_function)?;
+        features.push(feature);
+    }
+    Ok(features)
+}
+
+impl DescriptorSet {
+    const HEADER_LENGTH: usize = 10;
+
+    /// Descriptor set for a non-composite device using WinUSB with given device interface GUID
+    pub fn winusb(guid: &str) -> Self {
+        Self {
+            windows_version: WINDOWS_8_1,
+            features: vec![
+                Feature::CompatibleId(CompatibleId::winusb()),
+                Feature::RegistryProperty(RegistryProperty::device_interface_guids(&[guid])),
+            ],
+            configurations: vec![],
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.windows_version < WINDOWS_8_1 {
+            return Err(DescriptorError::UnsupportedVersion(self.windows_version));
+        }
+        self.features.iter().try_for_each(|f| f.validate(false))?;
+        for configuration in &self.configurations {
+            configuration.features.iter().try_for_each(|f| f.validate(false))?;
+            let mut interfaces = std::collections::HashSet::new();
+            for function in &configuration.functions {
+                if !interfaces.insert(function.first_interface) {
+                    return Err(DescriptorError::DuplicateFunction(function.first_interface));
+                }
+                function.features.iter().try_for_each(|f| f.validate(true))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Value for [`PlatformCapability::descriptor_set_length`]
+    pub fn total_length(&self) -> Result<u16> {
+        length_u16(self.to_bytes()?.len())
+    }
+
+    /// Platform capability announcing this descriptor set
+    pub fn platform_capability(&self, vendor_code: u8) -> Result<PlatformCapability> {
+        Ok(PlatformCapability {
+            windows_version: self.windows_version,
+            descriptor_set_length: self.total_length()?,
+            vendor_code,
+            alt_enum_code: 0,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(data);
+        check_length(Self::HEADER_LENGTH, r.u16()? as usize)?;
+        check_type(SET_HEADER_DESCRIPTOR, r.u16()?)?;
+        let windows_version = r.u32()?;
+        let total_length = r.u16()? as usize;
+        check_length(data.len(), total_length)?;
+
+        let features = parse_features(&mut r, total_length, false)?;
+        let mut configurations = vec![];
+        while r.remaining() > 0 {
+            let start = r.pos;
+            check_length(8, r.u16()? as usize)?;
+            check_type(SUBSET_HEADER_CONFIGURATION, r.u16()?)?;
+            let configuration = r.u8()?;
+            r.u8()?;
+            let end = start + r.u16()? as usize;
+            if end > data.len() {
+                return Err(DescriptorError::Truncated { expected: end, actual: data.len() });
+            }
+            let config_features = parse_features(&mut r, end, false)?;
+            let mut functions = vec![];
+            while r.pos < end {
+                let start = r.pos;
+                check_length(8, r.u16()? as usize)?;
+                check_type(SUBSET_HEADER_FUNCTION, r.u16()?)?;
+                let first_interface = r.u8()?;
+                r.u8()?;
+                let function_end = start + r.u16()? as usize;
+                if function_end > end {
+                    return Err(DescriptorError::Truncated { expected: function_end, actual: end });
+                }
+                let features = parse_features(&mut r, function_end, true)?;
+                check_length(function_end - start, r.pos - start)?;
+                functions.push(FunctionSubset { first_interface, features });
+            }
+            check_length(end - start, r.pos - start)?;
+            configurations.push(ConfigurationSubset { configuration, features: config_features, functions });
+        }
+        r.finish()?;
+
+        let set = Self { windows_version, features, configurations };
+        set.validate()?;
+        Ok(set)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let mut body = Vec::new();
+        for feature in &self.features {
+            feature.write(&mut body)?;
+        }
+        for configuration in &self.configurations {
+            let mut subset = Vec::new();
+            for feature in &configuration.features {
+                feature.write(&mut subset)?;
+            }
+            for function in &configuration.functions {
+                let mut features = Vec::new();
+                for feature in &function.features {
+                    feature.write(&mut features)?;
+                }
+                subset.extend(8u16.to_le_bytes());
+                subset.extend(SUBSET_HEADER_FUNCTION.to_le_bytes());
+                subset.extend([function.first_interface, 0]);
+                subset.extend(length_u16(8 + features.len())?.to_le_bytes());
+                subset.extend(features);
+            }
+            body.extend(8u16.to_le_bytes());
+            body.extend(SUBSET_HEADER_CONFIGURATION.to_le_bytes());
+            body.extend([configuration.configuration, 0]);
+            body.extend(length_u16(8 + subset.len())?.to_le_bytes());
+            body.extend(subset);
+        }
+        let total_length = length_u16(Self::HEADER_LENGTH + body.len())?;
+        let mut bytes = Vec::with_capacity(total_length as usize);
+        bytes.extend((Self::HEADER_LENGTH as u16).to_le_bytes());
+        bytes.extend(SET_HEADER_DESCRIPTOR.to_le_bytes());
+        bytes.extend(self.windows_version.to_le_bytes());
+        bytes.extend(total_length.to_le_bytes());
+        bytes.extend(body);
+        Ok(bytes)
+    }
+}
+
+/// Byte layouts from Microsoft documentation
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "{CDB3B5AD-293B-4663-AA36-1AAE46463776}";
+
+    #[test]
+    fn os_string_descriptor() {
+        let bytes = [
+            0x12, 0x03, b'M', 0, b'S', 0, b'F', 0, b'T', 0, b'1', 0, b'0', 0, b'0', 0, 0x20, 0x00,
+        ];
+        let descriptor = OsStringDescriptor::parse(&bytes).unwrap();
+        assert_eq!(descriptor.vendor_code, 0x20);
+        assert_eq!(descriptor.to_bytes(), bytes);
+
+        let mut bad = bytes;
+        bad[2] = b'X';
+        assert_eq!(OsStringDescriptor::parse(&bad), Err(DescriptorError::InvalidSignature));
+        assert!(matches!(OsStringDescriptor::parse(&bytes[..10]), Err(DescriptorError::Truncated { .. })));
+    }
+
+    #[test]
+    fn extended_compat_id() {
+        let mut bytes = vec![
+            0x28, 0x00, 0x00, 0x00, // dwLength
+            0x00, 0x01, // bcdVersion
+            0x04, 0x00, // wIndex
+            0x01, // bCount
+            0, 0, 0, 0, 0, 0, 0, // reserved
+            0x00, 0x01, // bFirstInterfaceNumber, reserved
+        ];
+        bytes.extend(b"WINUSB\0\0");
+        bytes.extend([0; 8 + 6]);
+
+        let descriptor = ExtendedCompatId::parse(&bytes).unwrap();
+        assert_eq!(descriptor, ExtendedCompatId::winusb());
+        assert_eq!(descriptor.to_bytes().unwrap(), bytes);
+
+        bytes[8] = 2;
+        assert!(matches!(ExtendedCompatId::parse(&bytes), Err(DescriptorError::CountMismatch { expected: 2, found: 1 })));
+
+        let invalid = ExtendedCompatId {
+            functions: vec![CompatIdFunction { first_interface: 0, compatible_id: CompatibleId::new("winusb", "") }],
+        };
+        assert_eq!(invalid.to_bytes(), Err(DescriptorError::InvalidCompatibleId("winusb".to_string())));
+    }
+
+    #[test]
+    fn extended_properties() {
+        let name = utf16z("DeviceInterfaceGUID");
+        let data = utf16z(GUID);
+        let size = 4 + 4 + 2 + name.len() + 4 + data.len();
+        let mut bytes = vec![];
+        bytes.extend(((10 + size) as u32).to_le_bytes());
+        bytes.extend([0x00, 0x01, 0x05, 0x00, 0x01, 0x00]);
+        bytes.extend((size as u32).to_le_bytes());
+        bytes.extend(1u32.to_le_bytes()); // REG_SZ
+        bytes.extend((name.len() as u16).to_le_bytes());
+        bytes.extend(&name);
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(&data);
+        assert_eq!(bytes.len(), 0x8e);
+
+        let descriptor = ExtendedProperties::parse(&bytes).unwrap();
+        assert_eq!(descriptor.properties, [RegistryProperty::device_interface_guid(GUID)]);
+        assert_eq!(descriptor.to_bytes().unwrap(), bytes);
+
+        let invalid = ExtendedProperties { properties: vec![RegistryProperty::device_interface_guid("not-a-guid")] };
+        assert_eq!(invalid.to_bytes(), Err(DescriptorError::InvalidGuid("not-a-guid".to_string())));
+    }
+
+    #[test]
+    fn descriptor_set_for_winusb_device() {
+        // Example from "Microsoft OS 2.0 Descriptors Specification"
+        let name = utf16z("DeviceInterfaceGUIDs");
+        let mut data = utf16z(GUID);
+        data.extend([0, 0]);
+        let mut bytes = vec![
+            0x0a, 0x00, 0x00, 0x00, // header
+            0x00, 0x00, 0x03, 0x06, // dwWindowsVersion
+            0xa2, 0x00, // wTotalLength
+            0x14, 0x00, 0x03, 0x00, // compatible ID
+        ];
+        bytes.extend(b"WINUSB\0\0");
+        bytes.extend([0; 8]);
+        bytes.extend([0x84, 0x00, 0x04, 0x00, 0x07, 0x00]);
+        bytes.extend((name.len() as u16).to_le_bytes());
+        bytes.extend(&name);
+        bytes.extend((data.len() as u16).to_le_bytes());
+        bytes.extend(&data);
+        assert_eq!(bytes.len(), 0xa2);
+
+        let set = DescriptorSet::parse(&bytes).unwrap();
+        assert_eq!(set, DescriptorSet::winusb(GUID));
+        assert_eq!(set.to_bytes().unwrap(), bytes);
+        assert_eq!(set.total_length().unwrap(), 0xa2);
+
+        let capability = set.platform_capability(0x21).unwrap();
+        let capability_bytes = capability.to_bytes();
+        assert_eq!(capability_bytes[..4], [0x1c, 0x10, 0x05, 0x00]);
+        assert_eq!(capability_bytes[20..], [0x00, 0x00, 0x03, 0x06, 0xa2, 0x00, 0x21, 0x00]);
+        assert_eq!(PlatformCapability::parse(&capability_bytes).unwrap(), capability);
+
+        bytes[8] = 0xa0;
+        assert!(matches!(DescriptorSet::parse(&bytes), Err(DescriptorError::InvalidLength { .. })));
+    }
+
+    #[test]
+    fn descriptor_set_for_composite_device() {
+        let set = DescriptorSet {
+            windows_version: WINDOWS_8_1,
+            features: vec![Feature::MinResumeTime { recovery_time: 10, signaling_time: 20 }],
+            configurations: vec![ConfigurationSubset {
+                configuration: 0,
+                features: vec![Feature::CcgpDevice],
+                functions: vec![
+                    FunctionSubset {
+                        first_interface: 0,
+                        features: vec![
+                            Feature::CompatibleId(CompatibleId::winusb()),
+                            Feature::RegistryProperty(RegistryProperty::device_interface_guids(&[GUID])),
+                        ],
+                    },
+                    FunctionSubset {
+                        first_interface: 2,
+                        features: vec![
+                            Feature::RegistryProperty(RegistryProperty::new("Enabled", PropertyValue::Dword(1))),
+                            Feature::VendorRevision(3),
+                        ],
+                    },
+                ],
+            }],
+        };
+        let bytes = set.to_bytes().unwrap();
+        assert_eq!(DescriptorSet::parse(&bytes).unwrap(), set);
+
+        let mut misplaced = set.clone();
+        misplaced.configurations[0].functions[1].features.push(Feature::CcgpDevice);
+        assert_eq!(misplaced.to_bytes(), Err(DescriptorError::MisplacedFeature(0x07)));
+
+        let mut duplicated = set.clone();
+        duplicated.configurations[0].functions[1].first_interface = 0;
+        assert_eq!(duplicated.to_bytes(), Err(DescriptorError::DuplicateFunction(0)));
+
+        let mut old = set;
+        old.windows_version = 0x0602_0000;
+        assert_eq!(old.to_bytes(), Err(DescriptorError::UnsupportedVersion(0x0602_0000)));
+
+        for len in 0..bytes.len() {
+            assert!(DescriptorSet::parse(&bytes[..len]).is_err());
+        }
+    }
+}
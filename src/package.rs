@@ -0,0 +1,138 @@
+use std::{borrow::Cow, ffi, fs, path::{Path, PathBuf}};
+
+use serde::{de::Error as _, Deserialize, Serialize};
+
+use crate::core::{DeviceInfo, DevicesList, PreparedDriver};
+use crate::enums::{DriverType, Error, Result};
+
+/// Identity of a device, used to find it again after the devices have been re-enumerated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    pub is_composite: bool,
+    pub mi: u8,
+    pub desc: String,
+    pub hardware_id: Option<String>,
+    /// Contains instance ID of the device, which may change when it is plugged into another port
+    pub device_id: Option<String>,
+}
+
+/// Options with which driver files have been prepared
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageOptions {
+    pub driver_type: DriverType,
+    pub vendor_name: Option<String>,
+    pub device_guid: Option<String>,
+    pub cert_subject: Option<String>,
+    pub disable_cat: bool,
+    pub disable_signing: bool,
+    pub use_wcid_driver: bool,
+    pub external_inf: bool,
+}
+
+/// Driver package prepared using wdi_prepare_driver, independent of the [`DevicesList`]
+///
+/// It can be saved as a manifest next to the driver files and loaded later, e.g. in an elevated
+/// process, to install the driver for a matching device from a freshly created list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreparedPackage {
+    version: u32,
+    pub path: String,
+    pub inf_name: String,
+    pub device: DeviceIdentity,
+    pub options: PackageOptions,
+}
+
+impl DeviceIdentity {
+    pub fn of(dev: &DeviceInfo) -> Self {
+        Self {
+            vid: dev.vid(),
+            pid: dev.pid(),
+            is_composite: dev.is_composite(),
+            mi: dev.mi().map_or(0, |mi| mi.get()),
+            desc: dev.desc().into_owned(),
+            hardware_id: dev.hardware_id().map(Cow::into_owned),
+            device_id: dev.device_id().map(Cow::into_owned),
+        }
+    }
+
+    /// Whether `dev` is the same kind of device: VID/PID, interface and hardware ID (if known) match
+    pub fn matches(&self, dev: &DeviceInfo) -> bool {
+        let hardware_id_matches = match (&self.hardware_id, dev.hardware_id()) {
+            (Some(id), Some(other)) => id.eq_ignore_ascii_case(&other),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        self.vid == dev.vid()
+            && self.pid == dev.pid()
+            && self.is_composite == dev.is_composite()
+            && self.mi == dev.mi().map_or(0, |mi| mi.get())
+            && hardware_id_matches
+    }
+
+    /// Whether `dev` is exactly this device instance
+    pub fn is_same_instance(&self, dev: &DeviceInfo) -> bool {
+        self.matches(dev) && self.device_id.is_some() && self.device_id.as_deref() == dev.device_id().as_deref()
+    }
+}
+
+impl PreparedPackage {
+    /// Name of the manifest file stored in the package directory
+    pub const MANIFEST_NAME: &'static str = "libwdi-package.json";
+    const VERSION: u32 = 1;
+
+    pub(crate) fn new(path: String, inf_name: String, device: DeviceIdentity, options: PackageOptions) -> Self {
+        Self { version: Self::VERSION, path, inf_name, device, options }
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        Path::new(&self.path).join(Self::MANIFEST_NAME)
+    }
+
+    /// Write the manifest to the package directory, returns its path
+    pub fn save(&self) -> Result<PathBuf> {
+        let manifest = self.manifest_path();
+        fs::write(&manifest, serde_json::to_vec_pretty(self)?)?;
+        Ok(manifest)
+    }
+
+    /// Load the manifest from package directory `dir`
+    ///
+    /// Package path is set to `dir`, so packages can be moved after they have been saved.
+    /// Fails with [`FileError::Json`](crate::FileError::Json) for manifests written in a format version that is not supported.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let data = fs::read(dir.join(Self::MANIFEST_NAME))?;
+        let mut package: Self = serde_json::from_slice(&data)?;
+        if package.version != Self::VERSION {
+            let msg = format!("unsupported package format version {}, expected {}", package.version, Self::VERSION);
+            return Err(serde_json::Error::custom(msg).into());
+        }
+        package.path = dir.to_str().ok_or(Error::InvalidParam)?.to_string();
+        Ok(package)
+    }
+
+    /// Find the device for which the package has been prepared, preferring the same device instance
+    pub fn find_device<'a>(&self, list: &'a mut DevicesList) -> Option<&'a mut DeviceInfo> {
+        let mut candidates: Vec<_> = list.iter_mut().filter(|dev| self.device.matches(dev)).collect();
+        let same = candidates.iter().position(|dev| self.device.is_same_instance(dev)).unwrap_or(0);
+        (same < candidates.len()).then(|| candidates.swap_remove(same))
+    }
+
+    /// Use the package to install a driver for `dev`, which must match the original device
+    pub fn bind<'a>(&self, dev: &'a mut DeviceInfo) -> Result<PreparedDriver<'a>> {
+        if !self.device.matches(dev) {
+            return Err(Error::NoDevice);
+        }
+        let path = ffi::CString::new(self.path.as_str())?;
+        let inf_name = ffi::CString::new(self.inf_name.as_str())?;
+        Ok(PreparedDriver::new(dev, path, inf_name, self.options.clone()))
+    }
+
+    /// Find the matching device in `list` and [`bind`](Self::bind) to it
+    pub fn bind_in<'a>(&self, list: &'a mut DevicesList) -> Result<PreparedDriver<'a>> {
+        let dev = self.find_device(list).ok_or(Error::NoDevice)?;
+        self.bind(dev)
+    }
+}
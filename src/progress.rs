@@ -0,0 +1,268 @@
+use std::sync::{mpsc, Mutex, atomic::{AtomicBool, Ordering}};
+use std::time::{Duration, Instant, SystemTime};
+use std::thread;
+
+use crate::enums::{Error, Result};
+use crate::misc::{read_logger, register_logger, unregister_logger};
+use crate::window::WindowHandle;
+
+/// Phase of driver preparation or installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    ExtractingFiles,
+    GeneratingInf,
+    /// Generating and signing the catalog file
+    GeneratingCatalog,
+    InstallingCertificate,
+    WaitingForPendingInstall,
+    Installing,
+    Verifying,
+}
+
+/// What happened during preparation or installation
+#[derive(Debug, Clone)]
+pub enum ProgressKind {
+    /// New phase started, libwdi log message from which it has been detected (if any)
+    Phase(Phase, Option<String>),
+    /// Message logged by libwdi
+    Log(String),
+    /// The operation finished with given result
+    Finished(std::result::Result<(), Error>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ProgressEvent {
+    pub kind: ProgressKind,
+    pub time: SystemTime,
+    /// Time since the start of the operation
+    pub elapsed: Duration,
+}
+
+/// Receives progress of prepare/install operations, implemented for closures
+///
+/// Phases are detected from libwdi log messages, which are read in a background thread while the
+/// operation runs, so the observer may be called from that thread (hence the `Send` bound). libwdi
+/// supports a single logger: if the application has registered its own with
+/// [`register_logger`](crate::register_logger), or the logger cannot be registered, only the initial
+/// phase and the result are reported, so that no log messages are taken from the application.
+pub trait ProgressObserver {
+    fn on_event(&mut self, event: &ProgressEvent);
+}
+
+impl<F: FnMut(&ProgressEvent)> ProgressObserver for F {
+    fn on_event(&mut self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+impl Phase {
+    /// Guess the phase from a libwdi log message
+    ///
+    /// This is best-effort: libwdi log messages are free-form text, so keywords are matched and
+    /// messages of other or future libwdi versions may be missed or attributed to the wrong phase.
+    pub fn from_log_line(line: &str) -> Option<Phase> {
+        let line = line.to_ascii_lowercase();
+        let has = |s: &str| line.contains(s);
+        if has("pending") {
+            Some(Phase::WaitingForPendingInstall)
+        } else if has("certificate") && (has("store") || has("trusted") || has("install")) {
+            Some(Phase::InstallingCertificate)
+        } else if has("extract") {
+            Some(Phase::ExtractingFiles)
+        } else if has(".cat") || has("catalog") || has("sign") {
+            Some(Phase::GeneratingCatalog)
+        } else if has(".inf") || has("inf file") {
+            Some(Phase::GeneratingInf)
+        } else if has("verif") {
+            Some(Phase::Verifying)
+        } else if has("install") {
+            Some(Phase::Installing)
+        } else {
+            None
+        }
+    }
+}
+
+// WM_APP, posted by libwdi to the log window for every message, which is then read with read_logger
+const LOG_MESSAGE: u32 = 0x8000;
+const LOG_BUFFER_SIZE: u32 = 64 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+struct Tracker<'o, O> {
+    observer: &'o mut O,
+    phase: Option<Phase>,
+    start: Instant,
+}
+
+impl<'o, O: ProgressObserver> Tracker<'o, O> {
+    fn emit(&mut self, kind: ProgressKind) {
+        let event = ProgressEvent { kind, time: SystemTime::now(), elapsed: self.start.elapsed() };
+        self.observer.on_event(&event);
+    }
+
+    fn phase(&mut self, phase: Phase, line: Option<&str>) {
+        if self.phase != Some(phase) {
+            self.phase = Some(phase);
+            self.emit(ProgressKind::Phase(phase, line.map(String::from)));
+        }
+    }
+
+    fn log(&mut self, line: &str) {
+        if let Some(phase) = Phase::from_log_line(line) {
+            self.phase(phase, Some(line));
+        }
+        self.emit(ProgressKind::Log(line.to_string()));
+    }
+}
+
+/// Read all available log messages, returns false if the logger cannot be read
+fn read_log<O: ProgressObserver>(tracker: &Mutex<Tracker<O>>, buf: &mut [u8]) -> bool {
+    loop {
+        match read_logger(buf) {
+            Ok(0) => return true,
+            Ok(n) => {
+                let line = String::from_utf8_lossy(&buf[..n]);
+                let line = line.trim_end_matches(['\0', '\r', '\n']);
+                // Observer panics are propagated when the thread is joined
+                if let Ok(mut tracker) = tracker.lock() {
+                    tracker.log(line);
+                }
+            },
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Read log messages until `done` is set, the logger is registered with a window owned by this thread
+fn poll_log<O: ProgressObserver>(tracker: &Mutex<Tracker<O>>, done: &AtomicBool, ready: mpsc::Sender<()>) {
+    let Some(window) = LogWindow::new() else {
+        log::warn!("Failed to create window for libwdi log, its messages are not reported");
+        return;
+    };
+    let registered = register_logger(window.handle(), LOG_MESSAGE, LOG_BUFFER_SIZE);
+    drop(ready);
+    match registered {
+        Ok(()) => {},
+        // Reading the messages would take them from the logger of the application
+        Err(Error::Exists) => {
+            log::debug!("libwdi logger is registered by the application, progress is reported without log messages");
+            return;
+        },
+        Err(err) => {
+            log::warn!("Failed to register libwdi logger ({err}), progress is reported without log messages");
+            return;
+        },
+    }
+
+    let mut buf = vec![0; LOG_BUFFER_SIZE as usize];
+    while !done.load(Ordering::Acquire) && read_log(tracker, &mut buf) {
+        window.discard_messages();
+        thread::sleep(POLL_INTERVAL);
+    }
+    // Messages logged after the last poll
+    read_log(tracker, &mut buf);
+    if let Err(err) = unregister_logger(window.handle()) {
+        log::warn!("Failed to unregister libwdi logger: {err}");
+    }
+}
+
+/// Run `f` reporting its progress to `observer` based on libwdi log messages read in a background thread
+pub(crate) fn run<T, O>(initial: Phase, observer: &mut O, f: impl FnOnce() -> Result<T>) -> Result<T>
+where
+    O: ProgressObserver + Send,
+{
+    let mut tracker = Tracker { observer, phase: None, start: Instant::now() };
+    tracker.phase(initial, None);
+    let tracker = Mutex::new(tracker);
+
+    let done = AtomicBool::new(false);
+    let result = thread::scope(|scope| {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        scope.spawn(|| poll_log(&tracker, &done, ready_tx));
+        // Wait until the logger is registered, so that no message of the operation is missed
+        ready_rx.recv().ok();
+        let result = f();
+        done.store(true, Ordering::Release);
+        result
+    });
+
+    let mut tracker = tracker.into_inner().unwrap_or_else(|err| err.into_inner());
+    tracker.emit(ProgressKind::Finished(result.as_ref().map(|_| ()).map_err(Clone::clone)));
+    result
+}
+
+/// Window receiving the notifications that libwdi posts for every log message
+///
+/// With no window libwdi would post them to the message queue of the thread running the operation,
+/// flooding GUI applications with spurious messages. On Windows this is a message-only window, which
+/// must be used and destroyed by the thread that created it.
+struct LogWindow(WindowHandle);
+
+#[cfg(windows)]
+impl LogWindow {
+    fn new() -> Option<Self> {
+        let hwnd = user32::message_only_window();
+        (!hwnd.is_null()).then(|| Self(WindowHandle::from(hwnd)))
+    }
+
+    /// Remove the notifications from the message queue, messages themselves are read with read_logger
+    fn discard_messages(&self) {
+        user32::discard_messages(self.0.as_raw());
+    }
+}
+
+#[cfg(windows)]
+impl Drop for LogWindow {
+    fn drop(&mut self) {
+        user32::destroy_window(self.0.as_raw());
+    }
+}
+
+// Without windows there are no message queues to flood
+#[cfg(not(windows))]
+impl LogWindow {
+    fn new() -> Option<Self> {
+        Some(Self(WindowHandle::NONE))
+    }
+
+    fn discard_messages(&self) {}
+}
+
+impl LogWindow {
+    fn handle(&self) -> WindowHandle {
+        self.0
+    }
+}
+
+#[cfg(windows)]
+mod user32 {
+    use windows::Win32::Foundation::{HINSTANCE, HWND};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyWindow, PeekMessageW, HMENU, HWND_MESSAGE, MSG, PM_REMOVE, WINDOW_EX_STYLE, WINDOW_STYLE,
+    };
+
+    use crate::ffi as wdi;
+
+    /// Null if the window cannot be created
+    pub fn message_only_window() -> wdi::HWND {
+        // "Message" is the system class for message-only windows
+        let hwnd = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE(0), windows::w!("Message"), None, WINDOW_STYLE(0), 0, 0, 0, 0,
+                HWND_MESSAGE, HMENU(0), HINSTANCE(0), None,
+            )
+        };
+        hwnd.0 as wdi::HWND
+    }
+
+    pub fn discard_messages(hwnd: wdi::HWND) {
+        let mut msg = MSG::default();
+        while unsafe { PeekMessageW(&mut msg, HWND(hwnd as isize), 0, 0, PM_REMOVE) }.as_bool() {}
+    }
+
+    pub fn destroy_window(hwnd: wdi::HWND) {
+        if !unsafe { DestroyWindow(HWND(hwnd as isize)) }.as_bool() {
+            log::error!("Failed to destroy log window: {}", std::io::Error::last_os_error());
+        }
+    }
+}
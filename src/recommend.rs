@@ -0,0 +1,209 @@
+use std::fmt;
+
+use crate::core::DeviceInfo;
+use crate::enums::DriverType;
+use crate::misc::get_vendor_name;
+
+/// USB class triple as reported in the compatible ID, e.g. `USB\Class_02&SubClass_02&Prot_01`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UsbClass {
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+}
+
+/// Driver suggested for a device together with the reason for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub driver: DriverType,
+    pub reason: String,
+}
+
+/// Result of [`recommend_driver`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recommendation {
+    /// Class parsed from the compatible ID, if any
+    pub class: Option<UsbClass>,
+    /// Driver currently installed, if it is one of the drivers supported by libwdi
+    pub current: Option<DriverType>,
+    /// Suggested drivers, most suitable first; empty if the device should keep its driver
+    pub suggestions: Vec<Suggestion>,
+    /// Why the current driver should not be replaced
+    pub leave_alone: Option<String>,
+}
+
+impl UsbClass {
+    pub const AUDIO: u8 = 0x01;
+    pub const CDC: u8 = 0x02;
+    pub const HID: u8 = 0x03;
+    pub const PHYSICAL: u8 = 0x05;
+    pub const IMAGE: u8 = 0x06;
+    pub const PRINTER: u8 = 0x07;
+    pub const MASS_STORAGE: u8 = 0x08;
+    pub const HUB: u8 = 0x09;
+    pub const CDC_DATA: u8 = 0x0a;
+    pub const SMART_CARD: u8 = 0x0b;
+    pub const VIDEO: u8 = 0x0e;
+    pub const AUDIO_VIDEO: u8 = 0x10;
+    pub const DIAGNOSTIC: u8 = 0xdc;
+    pub const WIRELESS: u8 = 0xe0;
+    pub const MISC: u8 = 0xef;
+    pub const APPLICATION: u8 = 0xfe;
+    pub const VENDOR: u8 = 0xff;
+
+    pub const fn new(class: u8, subclass: u8, protocol: u8) -> Self {
+        Self { class, subclass, protocol }
+    }
+
+    /// Parse the class from a compatible ID, case insensitive
+    pub fn from_compatible_id(id: &str) -> Option<Self> {
+        let id = id.to_ascii_uppercase();
+        let rest = id.strip_prefix("USB\\")?;
+        let mut class = None;
+        let mut subclass = None;
+        let mut protocol = None;
+        for part in rest.split('&') {
+            let (key, value) = part.split_once('_')?;
+            let value = u8::from_str_radix(value, 16).ok()?;
+            match key {
+                "CLASS" => class = Some(value),
+                "SUBCLASS" => subclass = Some(value),
+                "PROT" => protocol = Some(value),
+                _ => return None,
+            }
+        }
+        Some(Self::new(class?, subclass.unwrap_or(0), protocol.unwrap_or(0)))
+    }
+
+    /// Name of the class as defined by USB-IF
+    pub fn name(self) -> &'static str {
+        match self.class {
+            0x00 => "Defined by interface",
+            Self::AUDIO => "Audio",
+            Self::CDC => "Communications (CDC)",
+            Self::HID => "HID",
+            Self::PHYSICAL => "Physical",
+            Self::IMAGE => "Still Image",
+            Self::PRINTER => "Printer",
+            Self::MASS_STORAGE => "Mass Storage",
+            Self::HUB => "Hub",
+            Self::CDC_DATA => "CDC Data",
+            Self::SMART_CARD => "Smart Card",
+            0x0d => "Content Security",
+            Self::VIDEO => "Video",
+            0x0f => "Personal Healthcare",
+            Self::AUDIO_VIDEO => "Audio/Video",
+            0x11 => "Billboard",
+            Self::DIAGNOSTIC => "Diagnostic",
+            Self::WIRELESS => "Wireless Controller",
+            Self::MISC => "Miscellaneous",
+            Self::APPLICATION => "Application Specific",
+            Self::VENDOR => "Vendor Specific",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for UsbClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:02x}/{:02x}/{:02x})", self.name(), self.class, self.subclass, self.protocol)
+    }
+}
+
+impl Recommendation {
+    /// Most suitable driver, `None` if the device should keep its driver
+    pub fn best(&self) -> Option<DriverType> {
+        self.suggestions.first().map(|s| s.driver)
+    }
+
+    /// Whether the best driver is already installed
+    pub fn is_satisfied(&self) -> bool {
+        self.current.is_some() && self.current == self.best()
+    }
+}
+
+/// Class served by a Windows in-box driver that applications use through other APIs
+fn system_class(class: UsbClass) -> Option<&'static str> {
+    match (class.class, class.subclass, class.protocol) {
+        (UsbClass::AUDIO, ..) => Some("handled by the Windows audio stack"),
+        (UsbClass::HID, ..) => Some("HID devices are accessed through the HID API"),
+        (UsbClass::IMAGE, ..) => Some("cameras and scanners are accessed through WIA/WPD"),
+        (UsbClass::PRINTER, ..) => Some("handled by the Windows printing stack"),
+        (UsbClass::MASS_STORAGE, ..) => Some("replacing the driver makes the storage inaccessible"),
+        (UsbClass::HUB, ..) => Some("replacing a hub driver disconnects all downstream devices"),
+        (UsbClass::SMART_CARD, ..) => Some("smart cards are accessed through WinSCard"),
+        (UsbClass::VIDEO | UsbClass::AUDIO_VIDEO, ..) => Some("handled by the Windows media stack"),
+        (UsbClass::CDC, sub, _) if sub != 0x00 && sub != 0x02 => Some("network and modem functions use Windows networking drivers"),
+        (UsbClass::WIRELESS, 0x01, 0x01) => Some("Bluetooth controllers are used by the Windows Bluetooth stack"),
+        (UsbClass::WIRELESS, 0x01, 0x03) => Some("RNDIS devices use the Windows network driver"),
+        (UsbClass::MISC, 0x02, 0x01) => Some("composite parent is handled by the USB generic parent driver"),
+        _ => None,
+    }
+}
+
+fn generic(reason: &str) -> Vec<Suggestion> {
+    vec![
+        Suggestion { driver: DriverType::WinUsb, reason: format!("{reason}, WinUSB is included in Windows") },
+        Suggestion { driver: DriverType::LibUsbK, reason: "supports isochronous transfers and the libusbK API".to_string() },
+        Suggestion { driver: DriverType::LibUsb0, reason: "for applications using the legacy libusb-win32 API".to_string() },
+    ]
+}
+
+fn suggest(class: Option<UsbClass>, vendor: Option<&str>) -> Vec<Suggestion> {
+    let Some(class) = class else {
+        return generic("no class information");
+    };
+    match (class.class, class.subclass) {
+        (UsbClass::CDC, 0x00 | 0x02) => vec![
+            Suggestion { driver: DriverType::Cdc, reason: "CDC ACM control interface is a virtual COM port".to_string() },
+        ],
+        (UsbClass::CDC_DATA, _) => vec![
+            Suggestion { driver: DriverType::Cdc, reason: "CDC data interface belongs to a virtual COM port".to_string() },
+        ],
+        (UsbClass::APPLICATION, 0x01) => generic("DFU interface is accessed by flashing tools through libusb"),
+        (UsbClass::APPLICATION, 0x03) => generic("USBTMC instruments are accessed through libusb"),
+        (UsbClass::DIAGNOSTIC, _) => generic("diagnostic interface is accessed through libusb"),
+        (UsbClass::VENDOR, _) => match vendor {
+            Some(vendor) => generic(&format!("vendor specific interface of {vendor}")),
+            None => generic("vendor specific interface"),
+        },
+        _ => generic(&format!("no Windows driver for class {class}")),
+    }
+}
+
+/// Suggest drivers for a device based on its class codes and currently installed driver
+///
+/// This is a heuristic: devices exposing a class interface (e.g. HID) in addition to their own
+/// protocol may still need a generic driver if they are accessed through libusb.
+pub fn recommend_driver(dev: &DeviceInfo) -> Recommendation {
+    let class = dev.compatible_id().and_then(|id| UsbClass::from_compatible_id(&id));
+    let current = dev.driver().and_then(|driver| driver.parse().ok());
+
+    if let Some(reason) = class.and_then(system_class) {
+        return Recommendation { class, current, suggestions: vec![], leave_alone: Some(reason.to_string()) };
+    }
+
+    let mut suggestions = suggest(class, get_vendor_name(dev.vid()));
+    if dev.is_composite() {
+        let mi = dev.mi().map_or(0, |mi| mi.get());
+        for s in suggestions.iter_mut() {
+            s.reason = format!("{}, installed for interface {mi} only", s.reason);
+        }
+    }
+    if let Some(current) = current {
+        // Keep a working generic driver over a marginally better one
+        if let Some(i) = suggestions.iter().position(|s| s.driver == current) {
+            let mut kept = suggestions.remove(i);
+            kept.reason = format!("already installed, {}", kept.reason);
+            suggestions.insert(0, kept);
+        }
+    }
+    Recommendation { class, current, suggestions, leave_alone: None }
+}
+
+impl DeviceInfo {
+    /// See [`recommend_driver`]
+    pub fn recommend_driver(&self) -> Recommendation {
+        recommend_driver(self)
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::enums::{DriverType, Result, ARCH_DIR, EMBEDDED_DRIVERS};
+use crate::misc::{get_wdf_version, is_driver_supported, is_file_embedded};
+
+/// Result of a single check of a [`SystemReport`], ordered from the best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Pass,
+    /// Installation may work, but something needs attention
+    Warn,
+    /// Installation will fail
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Check {
+    pub name: String,
+    pub verdict: Verdict,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+/// Support of a driver type by libwdi on this system
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DriverSupport {
+    pub driver: DriverType,
+    /// Whether the driver has been embedded at build time, see [`EMBEDDED_DRIVERS`]
+    pub embedded: bool,
+    /// Result of [`is_driver_supported`]
+    pub supported: bool,
+    /// File version of the driver, e.g. "6.1.7600.16385"
+    pub file_version: Option<String>,
+    /// Driver file for the current architecture, if the driver has one
+    pub file: Option<EmbeddedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedFile {
+    pub path: String,
+    pub name: String,
+    /// Result of [`is_file_embedded`]
+    pub embedded: bool,
+}
+
+/// Whether this machine is ready to install drivers, collected with [`SystemReport::collect`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemReport {
+    /// Operating system the process runs on, e.g. "windows"
+    pub os: String,
+    /// Windows version, `None` on other systems
+    pub os_version: Option<OsVersion>,
+    /// Whether the process runs with administrator rights, `None` if unknown
+    pub elevated: Option<bool>,
+    /// Result of [`get_wdf_version`], `None` if WDF is not available
+    pub wdf_version: Option<i32>,
+    pub drivers: Vec<DriverSupport>,
+    /// Whether another driver installation is in progress, `None` if unknown
+    pub pending_installation: Option<bool>,
+    pub checks: Vec<Check>,
+}
+
+/// Main file of each driver type among the resources embedded in libwdi
+fn driver_file(driver: DriverType) -> Option<&'static str> {
+    match driver {
+        DriverType::WinUsb => Some("winusbcoinstaller2.dll"),
+        DriverType::LibUsb0 => Some("libusb0.sys"),
+        DriverType::LibUsbK => Some("libusbK.sys"),
+        DriverType::Cdc | DriverType::User => None,
+    }
+}
+
+fn version_string(version: [u16; 4]) -> String {
+    let [a, b, c, d] = version;
+    format!("{a}.{b}.{c}.{d}")
+}
+
+impl DriverSupport {
+    fn collect(driver: DriverType) -> Self {
+        let info = is_driver_supported(driver).unwrap_or_else(|err| {
+            log::warn!("Could not check support of {driver} driver: {err}");
+            None
+        });
+        let file = driver_file(driver).map(|name| EmbeddedFile {
+            path: ARCH_DIR.to_string(),
+            name: name.to_string(),
+            embedded: is_file_embedded(Some(ARCH_DIR), name).unwrap_or(false),
+        });
+        Self {
+            driver,
+            embedded: EMBEDDED_DRIVERS.contains(&driver),
+            supported: info.is_some(),
+            file_version: info.map(|info| info.file_version())
+                .filter(|version| *version != [0; 4])
+                .map(version_string),
+            file,
+        }
+    }
+
+    fn check(&self) -> Check {
+        let (verdict, message) = match (self.embedded, self.supported) {
+            (_, true) => match &self.file_version {
+                Some(version) => (Verdict::Pass, format!("supported, version {version}")),
+                None => (Verdict::Pass, "supported".to_string()),
+            },
+            (true, false) => (Verdict::Fail, "embedded but not supported on this system".to_string()),
+            (false, false) => (Verdict::Warn, "not embedded in this build".to_string()),
+        };
+        let (verdict, message) = match &self.file {
+            Some(file) if self.embedded && !file.embedded => {
+                (Verdict::Fail, format!("{message}, {}\\{} is missing", file.path, file.name))
+            },
+            _ => (verdict, message),
+        };
+        let name = format!("driver_{:?}", self.driver).to_ascii_lowercase();
+        Check { name, verdict, message: format!("{}: {message}", self.driver) }
+    }
+}
+
+impl SystemReport {
+    /// Run all checks, never fails since every problem is reported as a failed check
+    ///
+    /// Checks calling libwdi follow the [`LockMode`](crate::LockMode), if libwdi is busy they report
+    /// the feature as not available.
+    pub fn collect() -> Self {
+        let wdf_version = get_wdf_version()
+            .map_err(|err| log::warn!("Could not get WDF version: {err}"))
+            .ok()
+            .filter(|version| *version > 0);
+        let mut report = Self {
+            os: std::env::consts::OS.to_string(),
+            os_version: sys::os_version(),
+            elevated: sys::is_elevated(),
+            wdf_version,
+            drivers: DriverType::ALL.into_iter().map(DriverSupport::collect).collect(),
+            pending_installation: sys::pending_installation(),
+            checks: vec![],
+        };
+        report.checks = report.run_checks();
+        report
+    }
+
+    fn run_checks(&self) -> Vec<Check> {
+        let check = |name: &str, verdict, message: String| Check { name: name.to_string(), verdict, message };
+        let mut checks = vec![];
+
+        checks.push(match &self.os_version {
+            Some(v) if (v.major, v.minor) >= (6, 1) => check("os", Verdict::Pass, format!("Windows {}.{} build {}", v.major, v.minor, v.build)),
+            Some(v) => check("os", Verdict::Fail, format!("Windows {}.{} is not supported", v.major, v.minor)),
+            None => check("os", Verdict::Fail, format!("drivers cannot be installed on {}", self.os)),
+        });
+        checks.push(match self.elevated {
+            Some(true) => check("elevated", Verdict::Pass, "running as administrator".to_string()),
+            Some(false) => check("elevated", Verdict::Warn, "not running as administrator, installation will ask for elevation".to_string()),
+            None => check("elevated", Verdict::Warn, "unknown".to_string()),
+        });
+        checks.push(match self.wdf_version {
+            Some(version) => check("wdf", Verdict::Pass, format!("WDF version {version}")),
+            None => check("wdf", Verdict::Warn, "WDF co-installers are not available".to_string()),
+        });
+        checks.extend(self.drivers.iter().map(DriverSupport::check));
+        checks.push(match self.pending_installation {
+            Some(false) => check("pending_installation", Verdict::Pass, "no installation in progress".to_string()),
+            Some(true) => check("pending_installation", Verdict::Warn, "another installation is in progress".to_string()),
+            None => check("pending_installation", Verdict::Warn, "unknown".to_string()),
+        });
+        checks
+    }
+
+    /// Worst verdict of all checks
+    pub fn verdict(&self) -> Verdict {
+        self.checks.iter().map(|check| check.verdict).max().unwrap_or(Verdict::Pass)
+    }
+
+    /// Support of given driver type
+    pub fn driver(&self, driver: DriverType) -> Option<&DriverSupport> {
+        self.drivers.iter().find(|support| support.driver == driver)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Pass => "PASS",
+            Verdict::Warn => "WARN",
+            Verdict::Fail => "FAIL",
+        })
+    }
+}
+
+impl fmt::Display for SystemReport {
+    /// One line per check followed by the overall verdict
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", check.verdict, check.name, check.message)?;
+        }
+        writeln!(f, "Overall: {}", self.verdict())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{mem, os::raw::c_void, ptr};
+
+    use super::OsVersion;
+
+    const TOKEN_QUERY: u32 = 0x0008;
+    const TOKEN_ELEVATION: u32 = 20;
+    const WAIT_OBJECT_0: u32 = 0;
+    const WAIT_TIMEOUT: u32 = 0x102;
+
+    #[repr(C)]
+    struct OsVersionInfo {
+        size: u32,
+        major: u32,
+        minor: u32,
+        build: u32,
+        platform: u32,
+        csd_version: [u16; 128],
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn OpenProcessToken(process: *mut c_void, access: u32, token: *mut *mut c_void) -> i32;
+        fn GetTokenInformation(token: *mut c_void, class: u32, info: *mut c_void, len: u32, ret_len: *mut u32) -> i32;
+    }
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        // Unlike GetVersionEx, reports the actual version regardless of the application manifest
+        fn RtlGetVersion(info: *mut OsVersionInfo) -> i32;
+    }
+
+    #[link(name = "cfgmgr32")]
+    extern "system" {
+        fn CMP_WaitNoPendingInstallEvents(timeout: u32) -> u32;
+    }
+
+    pub fn os_version() -> Option<OsVersion> {
+        let mut info: OsVersionInfo = unsafe { mem::zeroed() };
+        info.size = mem::size_of::<OsVersionInfo>() as u32;
+        if unsafe { RtlGetVersion(&mut info) } != 0 {
+            return None;
+        }
+        Some(OsVersion { major: info.major, minor: info.minor, build: info.build })
+    }
+
+    pub fn is_elevated() -> Option<bool> {
+        let mut token = ptr::null_mut();
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+            return None;
+        }
+        let mut elevated = 0u32;
+        let mut len = 0;
+        let ok = unsafe {
+            GetTokenInformation(token, TOKEN_ELEVATION, &mut elevated as *mut u32 as *mut c_void, 4, &mut len)
+        };
+        unsafe { CloseHandle(token) };
+        (ok != 0).then_some(elevated != 0)
+    }
+
+    pub fn pending_installation() -> Option<bool> {
+        match unsafe { CMP_WaitNoPendingInstallEvents(0) } {
+            WAIT_OBJECT_0 => Some(false),
+            WAIT_TIMEOUT => Some(true),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod sys {
+    use super::OsVersion;
+
+    pub fn os_version() -> Option<OsVersion> {
+        None
+    }
+
+    pub fn is_elevated() -> Option<bool> {
+        None
+    }
+
+    pub fn pending_installation() -> Option<bool> {
+        None
+    }
+}
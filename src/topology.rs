@@ -0,0 +1,420 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::composite::parent_instance;
+use crate::core::{DeviceInfo, DevicesList};
+use crate::enums::Result;
+use crate::recommend::UsbClass;
+
+/// Kind of a node in the [`Topology`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Hub,
+    Device,
+    /// Interface of a composite device
+    Interface,
+    /// Ancestor of a listed device that libwdi does not list, e.g. parent of composite device interfaces
+    Unlisted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopologyNode {
+    pub kind: NodeKind,
+    pub device_id: Option<String>,
+    pub vid: u16,
+    pub pid: u16,
+    pub mi: Option<u8>,
+    /// Empty for unlisted devices
+    pub desc: String,
+    pub driver: Option<String>,
+    /// Port of the parent hub the device is attached to
+    pub port: Option<u32>,
+    /// Location information reported by Windows, e.g. `Port_#0002.Hub_#0001`
+    pub location: Option<String>,
+    pub children: Vec<TopologyNode>,
+}
+
+/// Tree of hubs and devices, built from a list created with
+/// [`list_hubs`](crate::CreateListOptions::list_hubs) enabled
+///
+/// Renders as an indented tree with `Display`:
+/// ```text
+/// Generic USB Hub (05e3:0610), port 4
+///   STM32 BOOTLOADER (0483:df11), port 1, driver WinUSB
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Topology {
+    pub roots: Vec<TopologyNode>,
+}
+
+/// Source of relationships between devices, given their instance IDs
+pub trait DeviceTree {
+    /// Instance ID of the parent device, `None` if unknown
+    fn parent(&self, device_id: &str) -> Option<String>;
+
+    /// Location information, e.g. `Port_#0002.Hub_#0001`
+    fn location(&self, _device_id: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Device tree of the system, queried using cfgmgr32 on Windows
+///
+/// Where it is not available (other platforms or devices that have been disconnected), interfaces
+/// of composite devices are placed under their parent guessed from instance IDs and all other
+/// devices are placed at the root.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTree;
+
+/// Parent guessed from the instance ID of a composite device interface,
+/// e.g. `USB\VID_1234&PID_0001\6&2A1B3C4D&0` for `USB\VID_1234&PID_0001&MI_00\6&2A1B3C4D&0&0000`
+fn guess_parent(device_id: &str) -> Option<String> {
+    let mut parts = device_id.split('\\');
+    let (bus, hardware, _) = (parts.next()?, parts.next()?, parts.next()?);
+    let mi = hardware.to_ascii_uppercase().find("&MI_")?;
+    Some(format!("{bus}\\{}\\{}", &hardware[..mi], parent_instance(device_id)?))
+}
+
+impl DeviceTree for SystemTree {
+    fn parent(&self, device_id: &str) -> Option<String> {
+        cfgmgr::parent(device_id).or_else(|| guess_parent(device_id))
+    }
+
+    fn location(&self, device_id: &str) -> Option<String> {
+        cfgmgr::location(device_id)
+    }
+}
+
+#[cfg(windows)]
+mod cfgmgr {
+    use std::{ffi::{CStr, CString}, os::raw::{c_char, c_void}, ptr};
+
+    const CR_SUCCESS: u32 = 0;
+    const CM_LOCATE_DEVNODE_NORMAL: u32 = 0;
+    const CM_DRP_LOCATION_INFORMATION: u32 = 0x0e;
+    const MAX_DEVICE_ID_LEN: usize = 200;
+
+    #[link(name = "cfgmgr32")]
+    extern "system" {
+        fn CM_Locate_DevNodeA(devinst: *mut u32, device_id: *const c_char, flags: u32) -> u32;
+        fn CM_Get_Parent(parent: *mut u32, devinst: u32, flags: u32) -> u32;
+        fn CM_Get_Device_IDA(devinst: u32, buffer: *mut c_char, len: u32, flags: u32) -> u32;
+        fn CM_Get_DevNode_Registry_PropertyA(
+            devinst: u32,
+            property: u32,
+            data_type: *mut u32,
+            buffer: *mut c_void,
+            len: *mut u32,
+            flags: u32,
+        ) -> u32;
+    }
+
+    fn locate(device_id: &str) -> Option<u32> {
+        let device_id = CString::new(device_id).ok()?;
+        let mut devinst = 0;
+        let ret = unsafe { CM_Locate_DevNodeA(&mut devinst, device_id.as_ptr(), CM_LOCATE_DEVNODE_NORMAL) };
+        (ret == CR_SUCCESS).then_some(devinst)
+    }
+
+    fn string(buf: &[u8]) -> Option<String> {
+        let s = CStr::from_bytes_until_nul(buf).ok()?;
+        Some(s.to_string_lossy().into_owned())
+    }
+
+    pub fn parent(device_id: &str) -> Option<String> {
+        let devinst = locate(device_id)?;
+        let mut parent = 0;
+        if unsafe { CM_Get_Parent(&mut parent, devinst, 0) } != CR_SUCCESS {
+            return None;
+        }
+        let mut buf = [0u8; MAX_DEVICE_ID_LEN + 1];
+        let ret = unsafe { CM_Get_Device_IDA(parent, buf.as_mut_ptr() as *mut c_char, buf.len() as u32, 0) };
+        if ret != CR_SUCCESS {
+            return None;
+        }
+        string(&buf)
+    }
+
+    pub fn location(device_id: &str) -> Option<String> {
+        let devinst = locate(device_id)?;
+        let mut buf = [0u8; 256];
+        let mut len = buf.len() as u32;
+        let ret = unsafe {
+            CM_Get_DevNode_Registry_PropertyA(
+                devinst,
+                CM_DRP_LOCATION_INFORMATION,
+                ptr::null_mut(),
+                buf.as_mut_ptr() as *mut c_void,
+                &mut len,
+                0,
+            )
+        };
+        if ret != CR_SUCCESS {
+            return None;
+        }
+        string(&buf)
+    }
+}
+
+#[cfg(not(windows))]
+mod cfgmgr {
+    pub fn parent(_device_id: &str) -> Option<String> {
+        None
+    }
+
+    pub fn location(_device_id: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Port number from location information like `Port_#0002.Hub_#0001`
+fn port(location: &str) -> Option<u32> {
+    let rest = location.strip_prefix("Port_#")?;
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Vendor and product ID from an instance ID like `USB\VID_1234&PID_0001\...`
+fn ids(device_id: &str) -> (u16, u16) {
+    let id = device_id.to_ascii_uppercase();
+    let field = |name: &str| {
+        let start = id.find(name)? + name.len();
+        u16::from_str_radix(id.get(start..start + 4)?, 16).ok()
+    };
+    (field("VID_").unwrap_or(0), field("PID_").unwrap_or(0))
+}
+
+// Devices may form a cycle only with a broken DeviceTree, but it must not hang
+const MAX_DEPTH: usize = 32;
+
+struct Builder<'t, T> {
+    tree: &'t T,
+    nodes: Vec<TopologyNode>,
+    parents: Vec<Option<usize>>,
+    by_id: HashMap<String, usize>,
+}
+
+impl<'t, T: DeviceTree> Builder<'t, T> {
+    fn add(&mut self, mut node: TopologyNode) -> usize {
+        if let Some(id) = &node.device_id {
+            node.location = self.tree.location(id);
+            node.port = node.location.as_deref().and_then(port);
+            self.by_id.insert(id.to_ascii_uppercase(), self.nodes.len());
+        }
+        self.nodes.push(node);
+        self.parents.push(None);
+        self.nodes.len() - 1
+    }
+
+    fn unlisted(&mut self, device_id: String) -> usize {
+        let (vid, pid) = ids(&device_id);
+        self.add(TopologyNode {
+            kind: NodeKind::Unlisted,
+            device_id: Some(device_id),
+            vid,
+            pid,
+            mi: None,
+            desc: String::new(),
+            driver: None,
+            port: None,
+            location: None,
+            children: vec![],
+        })
+    }
+
+    /// Link node to its ancestors, adding the unlisted ones
+    fn link(&mut self, mut node: usize) {
+        for _ in 0..MAX_DEPTH {
+            let Some(id) = &self.nodes[node].device_id else { return };
+            // Root hubs are children of host controllers, which are not USB devices
+            let Some(parent) = self.tree.parent(id).filter(|p| p.to_ascii_uppercase().starts_with("USB\\")) else {
+                return;
+            };
+            if let Some(&parent) = self.by_id.get(&parent.to_ascii_uppercase()) {
+                if parent != node {
+                    self.parents[node] = Some(parent);
+                }
+                return;
+            }
+            let parent = self.unlisted(parent);
+            self.parents[node] = Some(parent);
+            node = parent;
+        }
+    }
+
+    /// Promote to a root the node at which a cycle of parents closes, so that no node is lost
+    fn break_cycles(&mut self) {
+        // 0: not visited, 1: on the current path, 2: done
+        let mut state = vec![0u8; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            let mut path = vec![];
+            let mut node = Some(start);
+            while let Some(current) = node.filter(|&n| state[n] == 0) {
+                state[current] = 1;
+                path.push(current);
+                node = self.parents[current];
+            }
+            if let Some(current) = node.filter(|&n| state[n] == 1) {
+                log::warn!("Device tree has a cycle at {:?}", self.nodes[current].device_id);
+                self.parents[current] = None;
+            }
+            for visited in path {
+                state[visited] = 2;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Topology {
+        self.break_cycles();
+        let mut children: Vec<Vec<usize>> = vec![vec![]; self.nodes.len()];
+        let mut roots = vec![];
+        for (node, parent) in self.parents.iter().enumerate() {
+            match parent {
+                Some(parent) => children[*parent].push(node),
+                None => roots.push(node),
+            }
+        }
+
+        fn take(nodes: &[TopologyNode], children: &[Vec<usize>], index: usize, depth: usize) -> TopologyNode {
+            let mut node = nodes[index].clone();
+            if depth < MAX_DEPTH {
+                node.children = children[index].iter()
+                    .map(|&child| take(nodes, children, child, depth + 1))
+                    .collect();
+                node.children.sort_by_key(|child| (child.port.is_none(), child.port, child.mi));
+            }
+            node
+        }
+
+        let mut roots: Vec<_> = roots.into_iter()
+            .map(|root| take(&self.nodes, &children, root, 0))
+            .collect();
+        roots.sort_by_key(|root| root.kind != NodeKind::Hub);
+        Topology { roots }
+    }
+}
+
+impl Topology {
+    /// Build the tree using the system device tree, see [`SystemTree`]
+    pub fn new<'a>(devices: impl IntoIterator<Item = &'a DeviceInfo>) -> Self {
+        Self::with_tree(devices, &SystemTree)
+    }
+
+    /// Build the tree with relationships between devices taken from `tree`
+    pub fn with_tree<'a>(devices: impl IntoIterator<Item = &'a DeviceInfo>, tree: &impl DeviceTree) -> Self {
+        let mut builder = Builder { tree, nodes: vec![], parents: vec![], by_id: HashMap::new() };
+        let listed: Vec<_> = devices.into_iter()
+            .map(|dev| {
+                let is_hub = dev.compatible_id()
+                    .and_then(|id| UsbClass::from_compatible_id(&id))
+                    .is_some_and(|class| class.class == UsbClass::HUB);
+                let kind = match () {
+                    _ if is_hub => NodeKind::Hub,
+                    _ if dev.is_composite() => NodeKind::Interface,
+                    _ => NodeKind::Device,
+                };
+                builder.add(TopologyNode {
+                    kind,
+                    device_id: dev.device_id().map(|id| id.into_owned()),
+                    vid: dev.vid(),
+                    pid: dev.pid(),
+                    mi: dev.is_composite().then_some(dev.raw().mi),
+                    desc: dev.desc().into_owned(),
+                    driver: dev.driver().map(|d| d.into_owned()),
+                    port: None,
+                    location: None,
+                    children: vec![],
+                })
+            })
+            .collect();
+        for node in listed {
+            builder.link(node);
+        }
+        builder.finish()
+    }
+
+    /// Render as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl DevicesList {
+    /// See [`Topology::new`]
+    pub fn topology(&self) -> Topology {
+        Topology::new(self.iter())
+    }
+}
+
+impl fmt::Display for TopologyNode {
+    /// Single line description of the node, without its children
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind, &self.device_id) {
+            (NodeKind::Unlisted, Some(id)) => write!(f, "{id}")?,
+            _ => write!(f, "{} ({:04x}:{:04x})", self.desc, self.vid, self.pid)?,
+        }
+        if let Some(mi) = self.mi {
+            write!(f, " interface {mi}")?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ", port {port}")?;
+        }
+        if let Some(driver) = &self.driver {
+            write!(f, ", driver {driver}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Topology {
+    /// Indented tree, one device per line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_node(f: &mut fmt::Formatter<'_>, node: &TopologyNode, depth: usize) -> fmt::Result {
+            writeln!(f, "{:indent$}{node}", "", indent = depth * 2)?;
+            node.children.iter().try_for_each(|child| write_node(f, child, depth + 1))
+        }
+        self.roots.iter().try_for_each(|root| write_node(f, root, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::DeviceDescriptor;
+
+    struct Cycle;
+
+    impl DeviceTree for Cycle {
+        fn parent(&self, device_id: &str) -> Option<String> {
+            let parent = match device_id {
+                r"USB\VID_0001&PID_0001\A" => r"USB\VID_0001&PID_0002\B",
+                r"USB\VID_0001&PID_0002\B" => r"USB\VID_0001&PID_0003\C",
+                r"USB\VID_0001&PID_0003\C" => r"USB\VID_0001&PID_0001\A",
+                r"USB\VID_0001&PID_0004\D" => r"USB\VID_0001&PID_0002\B",
+                _ => return None,
+            };
+            Some(parent.to_string())
+        }
+    }
+
+    #[test]
+    fn cycle_is_broken() {
+        let devices: Vec<_> = ["A", "B", "C", "D"].iter().enumerate()
+            .map(|(i, serial)| {
+                let pid = i as u16 + 1;
+                let mut dev = DeviceDescriptor::builder(1, pid).desc(serial).build().unwrap();
+                dev.set_device_id(Some(&format!("USB\\VID_0001&PID_{pid:04X}\\{serial}"))).unwrap();
+                dev
+            })
+            .collect();
+        let topology = Topology::with_tree(devices.iter().map(|dev| &**dev), &Cycle);
+        assert_eq!(topology.to_string(), "\
+A (0001:0001)
+  C (0001:0003)
+    B (0001:0002)
+      D (0001:0004)
+");
+    }
+}
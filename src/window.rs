@@ -0,0 +1,84 @@
+use std::ptr;
+
+use crate::ffi as wdi;
+
+/// Window used as the parent of dialogs shown by libwdi, e.g. UAC or certificate prompts
+///
+/// Windows validates window handles, so using a handle of a window that has been destroyed
+/// only results in dialogs without a parent or in errors returned by libwdi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowHandle(wdi::HWND);
+
+// Safety: window handles are not tied to the thread that obtained them
+unsafe impl Send for WindowHandle {}
+unsafe impl Sync for WindowHandle {}
+
+impl WindowHandle {
+    /// No parent window
+    pub const NONE: Self = Self(ptr::null_mut());
+
+    pub fn as_raw(self) -> wdi::HWND {
+        self.0
+    }
+
+    pub fn is_none(self) -> bool {
+        self.0.is_null()
+    }
+
+    /// Console window of the current process, if it has one
+    pub fn console() -> Option<Self> {
+        let hwnd = console_window();
+        (!hwnd.is_null()).then_some(Self(hwnd))
+    }
+
+    /// Handle of a window from a GUI toolkit, fails for windows other than Win32 ones
+    #[cfg(feature = "raw-window-handle")]
+    pub fn from_window(window: &impl raw_window_handle::HasRawWindowHandle) -> crate::Result<Self> {
+        Self::try_from(window.raw_window_handle())
+    }
+}
+
+impl Default for WindowHandle {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl From<wdi::HWND> for WindowHandle {
+    fn from(hwnd: wdi::HWND) -> Self {
+        Self(hwnd)
+    }
+}
+
+#[cfg(feature = "raw-window-handle")]
+impl TryFrom<raw_window_handle::RawWindowHandle> for WindowHandle {
+    type Error = crate::Error;
+
+    fn try_from(handle: raw_window_handle::RawWindowHandle) -> crate::Result<Self> {
+        match handle {
+            raw_window_handle::RawWindowHandle::Win32(handle) => Ok(Self(handle.hwnd as wdi::HWND)),
+            _ => Err(crate::Error::NotSupported),
+        }
+    }
+}
+
+#[cfg(feature = "windows")]
+impl From<windows::Win32::Foundation::HWND> for WindowHandle {
+    fn from(hwnd: windows::Win32::Foundation::HWND) -> Self {
+        Self(hwnd.0 as wdi::HWND)
+    }
+}
+
+#[cfg(windows)]
+fn console_window() -> wdi::HWND {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleWindow() -> wdi::HWND;
+    }
+    unsafe { GetConsoleWindow() }
+}
+
+#[cfg(not(windows))]
+fn console_window() -> wdi::HWND {
+    ptr::null_mut()
+}
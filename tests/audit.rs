@@ -0,0 +1,198 @@
+//! Tests of the audit log, in a separate process since the log is global
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, FakeDevice, Function};
+use libwdi_sys::wdi_error;
+
+mod common;
+use common::{setup, TempDir};
+
+/// Tests use the same global log
+static AUDIT: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn operations_are_audited() {
+    let _audit = AUDIT.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempDir::new("audit");
+    let path = dir.join("audit.jsonl");
+    setup(vec![FakeDevice::new(0x1234, 0x0001).desc("Board").driver("usbser")]);
+
+    let log = wdi::AuditLog::open(&path).unwrap().max_size(2048).max_files(10);
+    assert!(wdi::set_audit_log(Some(log)).is_none());
+
+    let mut list = wdi::CreateListOptions::new().list_all(true).create_list().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    wdi::PrepareDriverOptions::new()
+        .driver_type(wdi::DriverType::WinUsb)
+        .prepare_driver(dev, "usb_driver", "board.inf")
+        .unwrap()
+        .install_driver()
+        .unwrap();
+    fake::set_result(Function::InstallTrustedCertificate, wdi_error::WDI_ERROR_USER_CANCEL);
+    wdi::InstallCertOptions::new().install_trusted_certificate("board.cer").unwrap_err();
+    for _ in 0..4 {
+        let dev = list.iter_mut().next().unwrap();
+        wdi::PrepareDriverOptions::new().prepare_driver(dev, "usb_driver", "board.inf").unwrap();
+    }
+
+    // Reopening continues the chain
+    let log = wdi::set_audit_log(None).unwrap();
+    let mut log = wdi::AuditLog::open(log.path()).unwrap();
+    let last = log.append(wdi::AuditRecord {
+        operation: wdi::AuditOperation::InstallCertificate,
+        device: None,
+        driver_before: None,
+        driver_requested: None,
+        options: None,
+        path: None,
+        inf_name: None,
+        cert_name: Some("manual.cer".to_string()),
+        success: true,
+        error: None,
+    }).unwrap();
+    assert_eq!(last.seq, 7);
+
+    assert!(dir.join("audit.jsonl.1").exists(), "log has not been rotated");
+    let verification = wdi::verify_audit_log(&path).unwrap();
+    assert_eq!((verification.entries, verification.first_seq), (8, Some(0)));
+    assert_eq!(verification.last_hash, Some(last.hash));
+
+    let oldest = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .max_by_key(|file| file.extension().and_then(|ext| ext.to_str()?.parse::<u32>().ok()))
+        .unwrap();
+    let text = std::fs::read_to_string(&oldest).unwrap();
+    let entries: Vec<wdi::AuditEntry> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let prepare = &entries[0].record;
+    assert_eq!(prepare.operation, wdi::AuditOperation::PrepareDriver);
+    assert_eq!(prepare.device.as_ref().map(|dev| (dev.vid, dev.pid)), Some((0x1234, 0x0001)));
+    assert_eq!(prepare.inf_name.as_deref(), Some("board.inf"));
+    let install = &entries[1].record;
+    assert_eq!(install.operation, wdi::AuditOperation::InstallDriver);
+    assert_eq!((install.driver_before.as_deref(), install.driver_requested.as_deref()), (Some("usbser"), Some("WinUSB")));
+    let cert = &entries[2].record;
+    assert!(!cert.success);
+    assert_eq!(cert.error, Some(wdi::Error::UserCancel.to_string()));
+
+    // Changing the result of the failed operation breaks its hash
+    std::fs::write(&oldest, text.replacen(r#""success":false"#, r#""success":true"#, 1)).unwrap();
+    match wdi::verify_audit_log(&path) {
+        Err(wdi::Error::Audit(wdi::AuditError::Invalid { line, reason, .. })) => {
+            assert_eq!(line, 3);
+            assert!(reason.contains("hash"), "{reason}");
+        },
+        other => panic!("unexpected result {other:?}"),
+    }
+
+    // Oldest file rotated out, the next one starts with an anchor
+    std::fs::remove_file(&oldest).unwrap();
+    let verification = wdi::verify_audit_log(&path).unwrap();
+    assert!(verification.first_seq > Some(0));
+    let oldest = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .max_by_key(|file| file.extension().and_then(|ext| ext.to_str()?.parse::<u32>().ok()))
+        .unwrap();
+    let text = std::fs::read_to_string(&oldest).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert!(lines[0].starts_with(r#"{"anchor":"#), "{text}");
+
+    // Entry removed from the start of the oldest file
+    std::fs::write(&oldest, [lines[0], lines[2]].join("\n")).unwrap();
+    match wdi::verify_audit_log(&path) {
+        Err(wdi::Error::Audit(wdi::AuditError::Invalid { line, reason, .. })) => assert_eq!((line, reason.contains("previous hash")), (2, true), "{reason}"),
+        other => panic!("unexpected result {other:?}"),
+    }
+    // Anchor removed as well
+    std::fs::write(&oldest, lines[2]).unwrap();
+    match wdi::verify_audit_log(&path) {
+        Err(wdi::Error::Audit(wdi::AuditError::Invalid { line, reason, .. })) => assert_eq!((line, reason.contains("no anchor")), (1, true), "{reason}"),
+        other => panic!("unexpected result {other:?}"),
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn models_are_audited_once() {
+    let _audit = AUDIT.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempDir::new("audit-models");
+    let inf_path = dir.join("family.inf");
+    setup(vec![FakeDevice::new(0xcafe, 0x4001).desc("Board 1")]);
+    {
+        let inf_path = inf_path.clone();
+        fake::set_hook(Function::PrepareDriver, move || {
+            if !inf_path.exists() {
+                let inf = "[Manufacturer]\n%Vendor% = Boards\n[Boards]\n%Board% = Install, USB\\VID_CAFE&PID_4001\n";
+                std::fs::write(&inf_path, inf).unwrap();
+            }
+        });
+    }
+    let log = wdi::AuditLog::open(dir.join("audit.jsonl")).unwrap();
+    assert!(wdi::set_audit_log(Some(log)).is_none());
+
+    let models = [wdi::DeviceModel::new(0xcafe, 0x4002, "Board 2")];
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    wdi::PrepareDriverOptions::new()
+        .prepare_driver_for_models(dev, &models, dir.as_str(), "family.inf")
+        .unwrap();
+    // Failure of the first pass is recorded with the options of the final package
+    std::fs::remove_file(&inf_path).unwrap();
+    fake::set_result(Function::PrepareDriver, wdi_error::WDI_ERROR_ACCESS);
+    let dev = list.iter_mut().next().unwrap();
+    let result = wdi::PrepareDriverOptions::new().prepare_driver_for_models(dev, &models, dir.as_str(), "family.inf");
+    assert!(matches!(result, Err(wdi::Error::Access)));
+
+    let log = wdi::set_audit_log(None).unwrap();
+    let text = std::fs::read_to_string(log.path()).unwrap();
+    let entries: Vec<wdi::AuditEntry> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let records: Vec<_> = entries.iter()
+        .map(|entry| (entry.record.operation, entry.record.success, entry.record.options.as_ref().map(|options| options.external_inf)))
+        .collect();
+    assert_eq!(records, vec![
+        (wdi::AuditOperation::PrepareDriver, true, Some(true)),
+        (wdi::AuditOperation::PrepareDriver, false, Some(true)),
+    ]);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn failures_before_libwdi_are_audited() {
+    let _audit = AUDIT.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempDir::new("audit-early");
+    setup(vec![FakeDevice::new(0x1234, 0x0001).desc("Board")]);
+    let log = wdi::AuditLog::open(dir.join("audit.jsonl")).unwrap();
+    assert!(wdi::set_audit_log(Some(log)).is_none());
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let result = wdi::PrepareDriverOptions::new()
+        .driver_type(wdi::DriverType::Cdc)
+        .device_guid("{01234567-89ab-cdef-0123-456789abcdef}").unwrap()
+        .prepare_driver(dev, "dir", "board.inf");
+    assert!(result.is_err());
+
+    let lock = wdi::InstallLock::options("usb").dir(dir.path()).tool("other");
+    let _held = lock.acquire().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let driver = wdi::PrepareDriverOptions::new().prepare_driver(dev, "dir", "board.inf").unwrap();
+    let result = driver.install_lock(lock.mode(wdi::LockMode::TryLock)).install_driver();
+    assert!(matches!(result, Err(wdi::Error::InstallLock(_))));
+
+    wdi::InstallCertOptions::new().install_trusted_certificate("a\0b").unwrap_err();
+    assert_eq!(fake::calls().iter().filter(|call| !matches!(call, fake::Call::CreateList { .. })).count(), 1);
+
+    let log = wdi::set_audit_log(None).unwrap();
+    let text = std::fs::read_to_string(log.path()).unwrap();
+    let entries: Vec<wdi::AuditEntry> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let records: Vec<_> = entries.iter().map(|entry| (entry.record.operation, entry.record.success)).collect();
+    assert_eq!(records, vec![
+        (wdi::AuditOperation::PrepareDriver, false),
+        (wdi::AuditOperation::PrepareDriver, true),
+        (wdi::AuditOperation::InstallDriver, false),
+        (wdi::AuditOperation::InstallCertificate, false),
+    ]);
+    assert_eq!(entries[2].record.driver_requested.as_deref(), Some("WinUSB"));
+}
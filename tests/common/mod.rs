@@ -0,0 +1,72 @@
+//! Fixtures shared by the integration tests
+
+// Each test binary uses only some of them
+#![allow(dead_code)]
+
+use std::{fs, ops::Deref, path::{Path, PathBuf}};
+
+/// Directory in the system temporary directory, unique for the test process and removed on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("libwdi-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path as passed to libwdi
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+#[cfg(feature = "fake")]
+#[allow(unused_imports)]
+pub use self::fake::*;
+
+#[cfg(feature = "fake")]
+mod fake {
+    use libwdi_sys::fake::{self, FakeDevice};
+
+    /// Devices covering the common cases: a plain device, interfaces of a composite device and a
+    /// device that already has a driver (listed only with `list_all`)
+    pub fn devices() -> Vec<FakeDevice> {
+        vec![
+            FakeDevice::new(0x0483, 0xdf11).desc("STM32 BOOTLOADER"),
+            FakeDevice::new(0x1234, 0x0001).interface(0).desc("Composite interface 0"),
+            FakeDevice::new(0x1234, 0x0001).interface(2).desc("Composite interface 2"),
+            FakeDevice::new(0x2222, 0x3333).desc("  Device with driver  ").driver("WinUSB"),
+        ]
+    }
+
+    /// Reset the fake for the current thread and make it list `devices`
+    pub fn setup(devices: Vec<FakeDevice>) {
+        fake::reset();
+        fake::set_devices(devices);
+    }
+}
@@ -0,0 +1,88 @@
+//! Tests of drivers for devices described by the user, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call};
+
+mod common;
+use common::{devices, setup};
+
+#[test]
+fn descriptor_for_disconnected_device() {
+    fake::reset();
+
+    let dev = wdi::DeviceDescriptor::builder(0xcafe, 0x4001)
+        .interface(1)
+        .desc("Our board")
+        .compatible_id("USB\\Class_ff")
+        .build()
+        .unwrap();
+
+    let mut copy = dev.clone();
+    drop(dev);
+    wdi::PrepareDriverOptions::new()
+        .prepare_driver(&mut copy, "dir", "board.inf")
+        .and_then(|driver| driver.install_driver())
+        .unwrap();
+    let device = fake::SeenDevice {
+        vid: 0xcafe,
+        pid: 0x4001,
+        mi: 1,
+        desc: Some(b"Our board".to_vec()),
+        hardware_id: Some(b"USB\\VID_CAFE&PID_4001&MI_01".to_vec()),
+        device_id: None,
+    };
+    let calls = fake::calls();
+    assert!(matches!(&calls[0], Call::PrepareDriver { device: d, .. } if *d == device));
+    assert!(matches!(&calls[1], Call::InstallDriver { device: d, .. } if *d == device));
+}
+
+#[test]
+fn listed_device_can_be_edited() {
+    setup(devices());
+
+    let list = wdi::CreateListOptions::new().list_all(true).create_list().unwrap();
+    let listed = list.iter().last().unwrap();
+    let mut dev = listed.to_descriptor();
+    drop(list);
+    assert_eq!(dev.desc(), "  Device with driver  ");
+    assert_eq!(dev.driver().as_deref(), Some("WinUSB"));
+    assert_eq!(dev.device_id().as_deref(), Some("USB\\VID_2222&PID_3333\\0001"));
+
+    dev.trim_desc();
+    assert_eq!(dev.desc(), "Device with driver");
+    dev.set_desc("Our product").unwrap();
+    dev.set_hardware_id(Some("USB\\VID_2222&PID_3333&REV_0001")).unwrap();
+    dev.set_compatible_id(None).unwrap();
+    assert!(matches!(dev.set_desc("a\0b"), Err(wdi::Error::Nul(_))));
+    assert_eq!(dev.desc(), "Our product");
+
+    let copy = dev.clone();
+    drop(dev);
+    let mut dev = copy;
+    assert_eq!(dev.compatible_id(), None);
+    wdi::PrepareDriverOptions::new()
+        .prepare_driver(&mut dev, "dir", "device.inf")
+        .unwrap();
+    assert_eq!(fake::calls()[1], Call::PrepareDriver {
+        device: fake::SeenDevice {
+            vid: 0x2222,
+            pid: 0x3333,
+            mi: 0,
+            desc: Some(b"Our product".to_vec()),
+            hardware_id: Some(b"USB\\VID_2222&PID_3333&REV_0001".to_vec()),
+            device_id: Some(b"USB\\VID_2222&PID_3333\\0001".to_vec()),
+        },
+        path: "dir".to_string(),
+        inf_name: "device.inf".to_string(),
+        driver_type: libwdi_sys::wdi_driver_type::WDI_WINUSB,
+        vendor_name: None,
+        device_guid: None,
+        cert_subject: None,
+        disable_cat: false,
+        disable_signing: false,
+        use_wcid_driver: false,
+        external_inf: false,
+    });
+}
@@ -0,0 +1,140 @@
+//! Tests of vendor-signed packages, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call, FakeDevice};
+
+mod common;
+use common::{setup, TempDir};
+
+/// Minimal 64-bit PE image with an empty certificate table entry
+fn pe_image() -> Vec<u8> {
+    let mut image = vec![0u8; 0x200];
+    image[..2].copy_from_slice(b"MZ");
+    image[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+    image[0x40..0x44].copy_from_slice(b"PE\0\0");
+    image[0x58..0x5a].copy_from_slice(&0x20bu16.to_le_bytes());
+    // Number of data directories
+    image[0xc4..0xc8].copy_from_slice(&16u32.to_le_bytes());
+    image[0x180..0x188].copy_from_slice(b"DRIVER\0\0");
+    image
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn external_package_is_validated_and_installed() {
+    use sha2::{Digest, Sha256};
+
+    setup(vec![
+        FakeDevice::new(0xcafe, 0x4001).desc("Our board"),
+        FakeDevice::new(0xcafe, 0x4002).desc("Other board"),
+    ]);
+    let dir = TempDir::new("external");
+    let (package_dir, install_dir) = (dir.join("package"), dir.join("install"));
+    std::fs::create_dir_all(package_dir.join("drivers")).unwrap();
+
+    let inf = r#"; Package signed by the vendor
+[Version]
+Signature = "$Windows NT$"
+Class = USBDevice
+CatalogFile = board.cat
+
+[Manufacturer]
+%Vendor% = Boards, NTamd64, NTarm64
+
+[Boards.NTamd64]
+%Board% = Install, USB\VID_CAFE&PID_4001 ; Our board
+[Boards.NTarm64]
+%Board% = Install, USB\VID_CAFE&PID_4001
+
+[SourceDisksNames]
+1 = %Disk%,,,\drivers
+
+[SourceDisksFiles]
+board.sys = 1
+
+[Strings]
+Vendor = "Vendor, Inc."
+Board = "Our board"
+Disk = "Board driver"
+"#;
+    std::fs::write(package_dir.join("board.inf"), inf).unwrap();
+    let sys = pe_image();
+    std::fs::write(package_dir.join("drivers").join("board.sys"), &sys).unwrap();
+    // Authenticode hash: without the checksum and the certificate table entry
+    let authenticode = Sha256::new()
+        .chain_update(&sys[..0x98])
+        .chain_update(&sys[0x9c..0xe8])
+        .chain_update(&sys[0xf0..])
+        .finalize();
+    let mut catalog = b"catalog header\x04\x20".to_vec();
+    catalog.extend(Sha256::digest(inf.as_bytes()));
+    catalog.extend(b"member\x04\x20");
+    catalog.extend(authenticode);
+    std::fs::write(package_dir.join("board.cat"), catalog).unwrap();
+
+    // Signing the driver changes the checksum and appends the certificate, but not its hash
+    let mut signed = sys.clone();
+    signed[0x98..0x9c].copy_from_slice(&0x1234u32.to_le_bytes());
+    signed[0xe8..0xf0].copy_from_slice(&[0x00, 0x02, 0, 0, 0x10, 0, 0, 0]);
+    signed.extend(b"certificate data");
+    std::fs::write(package_dir.join("drivers").join("board.sys"), &signed).unwrap();
+
+    let package = wdi::ExternalPackage::open(&package_dir, "board.inf").unwrap();
+    assert_eq!(package.inf().hardware_ids(), vec!["USB\\VID_CAFE&PID_4001".to_string()]);
+    assert_eq!(package.inf().value("Strings", "vendor"), Some("Vendor, Inc."));
+    assert_eq!(package.files(), vec![
+        std::path::PathBuf::from("board.inf"),
+        "board.cat".into(),
+        ["drivers", "board.sys"].iter().collect(),
+    ]);
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let other = list.iter_mut().find(|dev| dev.pid() == 0x4002).unwrap();
+    match package.validate(other) {
+        Err(wdi::Error::ExternalPackage(wdi::ExternalPackageError::Invalid { reason, .. })) => assert!(reason.contains("USB\\VID_CAFE&PID_4002"), "{reason}"),
+        other => panic!("unexpected result {other:?}"),
+    }
+
+    let dev = list.iter_mut().find(|dev| dev.pid() == 0x4001).unwrap();
+    let install_str = install_dir.to_str().unwrap();
+    package.install(wdi::PrepareDriverOptions::new(), dev, install_str).unwrap();
+    let calls = fake::calls();
+    assert!(matches!(&calls[1], Call::PrepareDriver { external_inf: true, disable_cat: true, inf_name, .. } if inf_name == "board.inf"));
+    assert!(matches!(&calls[2], Call::InstallDriver { path, .. } if path == install_str));
+    assert_eq!(std::fs::read(install_dir.join("drivers").join("board.sys")).unwrap(), signed);
+    assert!(install_dir.join("board.cat").is_file());
+
+    // Tampered driver
+    let mut tampered = signed;
+    tampered[0x180] = b'X';
+    std::fs::write(package_dir.join("drivers").join("board.sys"), &tampered).unwrap();
+    match package.check_catalog() {
+        Err(wdi::Error::ExternalPackage(wdi::ExternalPackageError::Invalid { file, reason })) => {
+            assert!(file.ends_with("board.sys"), "{file}");
+            assert!(reason.contains("board.cat"), "{reason}");
+        },
+        other => panic!("unexpected result {other:?}"),
+    }
+
+    std::fs::remove_file(package_dir.join("board.cat")).unwrap();
+    assert!(matches!(package.check_files(), Err(wdi::Error::ExternalPackage(wdi::ExternalPackageError::Invalid { file, .. })) if file.ends_with("board.cat")));
+
+    // Files outside of the package directory
+    for (from, to) in [
+        ("1 = %Disk%,,,\\drivers", "1 = %Disk%,,,\\..\\..\\drivers"),
+        ("board.sys = 1", "..\\board.sys = 1"),
+        ("board.sys = 1", "board.sys = 1,..\\system32"),
+        ("board.sys = 1", "C:board.sys = 1"),
+        ("CatalogFile = board.cat", "CatalogFile = \\Windows\\board.cat"),
+        ("CatalogFile = board.cat", "CatalogFile = ..\\board.cat"),
+    ] {
+        std::fs::write(package_dir.join("board.inf"), inf.replace(from, to)).unwrap();
+        match wdi::ExternalPackage::open(&package_dir, "board.inf") {
+            Err(wdi::Error::ExternalPackage(wdi::ExternalPackageError::Invalid { reason, .. })) => assert!(reason.contains("not inside the package"), "{reason}"),
+            other => panic!("{to}: unexpected result {other:?}"),
+        }
+    }
+    assert!(matches!(wdi::ExternalPackage::open(&package_dir, "../board.inf"), Err(wdi::Error::ExternalPackage(wdi::ExternalPackageError::Invalid { .. }))));
+}
@@ -0,0 +1,43 @@
+//! Tests of device lists recorded and replayed, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call};
+
+mod common;
+use common::{devices, setup};
+
+#[test]
+fn list_fixture_is_replayed() {
+    let mut devices = devices();
+    devices[0].desc = Some(b"Ger\xe4t".to_vec());
+    devices[0].driver_version = 0x0006_0001_1db1_0000;
+    devices[2].desc = None;
+    setup(devices);
+
+    let json = {
+        let list = wdi::CreateListOptions::new().list_all(true).trim_whitespaces(true).create_list().unwrap();
+        list.to_fixture().to_json().unwrap()
+    };
+    fake::reset();
+
+    let fixture = wdi::ListFixture::from_json(&json).unwrap();
+    assert!(fixture.options.list_all && fixture.options.trim_whitespaces && !fixture.options.list_hubs);
+    assert_eq!(fixture.devices[0].desc, Some(wdi::RecordedString::Bytes(b"Ger\xe4t".to_vec())));
+    assert_eq!(fixture.devices[2].desc, None);
+    assert_eq!(fixture.devices[3].driver, Some("WinUSB".into()));
+
+    let mut list = fixture.replay().unwrap();
+    assert_eq!(wdi::ListOptionsRecord::from(list.options()), fixture.options);
+    assert_eq!(list.to_fixture(), fixture);
+    let first = list.iter().next().unwrap();
+    assert_eq!(first.desc_bytes(), Some(&b"Ger\xe4t"[..]));
+    assert_eq!(first.driver_version().map(|v| v.get()), Some(0x0006_0001_1db1_0000));
+    assert_eq!(list.iter().nth(2).unwrap().desc_bytes(), None);
+
+    let dev = list.iter_mut().find(|dev| dev.mi().is_some()).unwrap();
+    wdi::PrepareDriverOptions::new().prepare_driver(dev, "usb_driver", "replayed.inf").unwrap();
+    drop(list);
+    assert!(matches!(&fake::calls()[..], [Call::PrepareDriver { device, .. }] if device.mi == 2 && device.desc.is_none()));
+}
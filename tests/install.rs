@@ -0,0 +1,279 @@
+//! Tests of preparing and installing drivers, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call, FakeDevice, Function};
+use libwdi_sys::wdi_error;
+
+mod common;
+use common::{devices, setup, TempDir};
+
+#[test]
+fn prepare_and_install_pass_strings() {
+    setup(devices());
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().find(|dev| dev.mi().is_some()).unwrap();
+
+    wdi::PrepareDriverOptions::new()
+        .driver_type(wdi::DriverType::WinUsb)
+        .vendor_name("Vendor").unwrap()
+        .device_guid("{01234567-89ab-cdef-0123-456789abcdef}").unwrap()
+        .disable_signing(true)
+        .use_wcid_driver(true)
+        .prepare_driver(dev, "C:\\usb_driver", "device.inf")
+        .unwrap()
+        .pending_install_timeout(1000)
+        .install_driver()
+        .unwrap();
+
+    let device = fake::SeenDevice {
+        vid: 0x1234,
+        pid: 0x0001,
+        mi: 2,
+        desc: Some(b"Composite interface 2".to_vec()),
+        hardware_id: Some(b"USB\\VID_1234&PID_0001".to_vec()),
+        device_id: Some(b"USB\\VID_1234&PID_0001\\0001".to_vec()),
+    };
+    let calls = fake::calls();
+    assert_eq!(calls[1..], [
+        Call::PrepareDriver {
+            device: device.clone(),
+            path: "C:\\usb_driver".to_string(),
+            inf_name: "device.inf".to_string(),
+            driver_type: libwdi_sys::wdi_driver_type::WDI_WINUSB,
+            vendor_name: Some("Vendor".to_string()),
+            device_guid: Some("{01234567-89ab-cdef-0123-456789abcdef}".to_string()),
+            cert_subject: None,
+            disable_cat: false,
+            disable_signing: true,
+            use_wcid_driver: true,
+            external_inf: false,
+        },
+        Call::InstallDriver {
+            device,
+            path: "C:\\usb_driver".to_string(),
+            inf_name: "device.inf".to_string(),
+            hwnd: 0,
+            install_filter_driver: false,
+            pending_install_timeout: 1000,
+        },
+    ]);
+}
+
+#[test]
+fn install_errors() {
+    setup(devices());
+    fake::set_result(Function::InstallDriver, wdi_error::WDI_ERROR_NEEDS_ADMIN);
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let driver = wdi::PrepareDriverOptions::new()
+        .prepare_driver(dev, "dir", "device.inf")
+        .unwrap();
+    assert!(matches!(driver.install_driver(), Err(wdi::Error::NeedsAdmin)));
+
+    fake::set_result(Function::PrepareDriver, wdi_error::WDI_ERROR_NOT_SUPPORTED);
+    let dev = list.iter_mut().next().unwrap();
+    let result = wdi::PrepareDriverOptions::new().prepare_driver(dev, "dir", "device.inf");
+    assert!(matches!(result, Err(wdi::Error::NotSupported)));
+}
+
+#[test]
+fn libwdi_checks_are_surfaced() {
+    setup(devices());
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+
+    let dev = list.iter_mut().next().unwrap();
+    let result = wdi::PrepareDriverOptions::new().prepare_driver(dev, "dir", "device.txt");
+    assert!(matches!(result, Err(wdi::Error::InvalidParam)));
+
+    // Embedded, but e.g. not supported by this version of Windows
+    fake::set_driver_supported(libwdi_sys::wdi_driver_type::WDI_WINUSB, false);
+    let dev = list.iter_mut().next().unwrap();
+    let result = wdi::PrepareDriverOptions::new().prepare_driver(dev, "dir", "device.inf");
+    assert!(matches!(result, Err(wdi::Error::NotSupported)));
+    fake::set_driver_supported(libwdi_sys::wdi_driver_type::WDI_WINUSB, true);
+
+    // External INF must have been written before
+    let dev = list.iter_mut().next().unwrap();
+    let result = wdi::PrepareDriverOptions::new().external_inf(true).prepare_driver(dev, "dir", "device.inf");
+    assert!(matches!(result, Err(wdi::Error::NotFound)));
+    let dev = list.iter_mut().next().unwrap();
+    wdi::PrepareDriverOptions::new().prepare_driver(dev, "dir", "device.inf").unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let package = wdi::PrepareDriverOptions::new().external_inf(true).prepare_driver(dev, "dir", "device.inf").unwrap().package();
+
+    // Package bound after its files are gone
+    setup(devices());
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let result = package.bind_in(&mut list).unwrap().install_driver();
+    assert!(matches!(result, Err(wdi::Error::NotFound)));
+}
+
+#[test]
+fn incompatible_options_are_rejected() {
+    setup(devices());
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+
+    let result = wdi::PrepareDriverOptions::new()
+        .driver_type(wdi::DriverType::Cdc)
+        .use_wcid_driver(true)
+        .prepare_driver(list.iter_mut().next().unwrap(), "dir", "device.inf");
+    assert!(matches!(result, Err(wdi::Error::Driver(wdi::DriverError::IncompatibleOption {
+        driver: wdi::DriverType::Cdc,
+        option: wdi::DriverOption::UseWcidDriver,
+    }))));
+    for typ in wdi::DriverType::ALL.into_iter().filter(|typ| !wdi::EMBEDDED_DRIVERS.contains(typ)) {
+        let result = wdi::PrepareDriverOptions::new()
+            .driver_type(typ)
+            .prepare_driver(list.iter_mut().next().unwrap(), "dir", "device.inf");
+        assert!(matches!(result, Err(wdi::Error::Driver(wdi::DriverError::NotEmbedded(t))) if t == typ));
+    }
+    let result = wdi::PrepareDriverOptions::new()
+        .driver_type(wdi::DriverType::Cdc)
+        .device_guid("{01234567-89ab-cdef-0123-456789abcdef}").unwrap()
+        .validate();
+    assert!(matches!(result, Err(wdi::Error::Driver(wdi::DriverError::IncompatibleOption { option: wdi::DriverOption::DeviceGuid, .. }))));
+
+    let result = wdi::PrepareDriverOptions::new()
+        .prepare_driver(list.iter_mut().next().unwrap(), "dir", "device.inf")
+        .unwrap()
+        .install_filter_driver(true)
+        .install_driver();
+    assert!(matches!(result, Err(wdi::Error::Driver(wdi::DriverError::IncompatibleOption {
+        driver: wdi::DriverType::WinUsb,
+        option: wdi::DriverOption::InstallFilterDriver,
+    }))));
+
+    // Nothing reached libwdi except for the valid prepare_driver call
+    let calls = fake::calls();
+    assert_eq!(calls.len(), 2);
+    assert!(matches!(calls[1], Call::PrepareDriver { .. }));
+}
+
+#[test]
+fn drivers_are_recommended_by_class() {
+    setup(vec![
+        FakeDevice::new(0x0483, 0xdf11).compatible_id("USB\\Class_FE&SubClass_01&Prot_02"),
+        FakeDevice::new(0x0483, 0x5740).interface(0).compatible_id("USB\\Class_02&SubClass_02&Prot_01"),
+        FakeDevice::new(0x0483, 0x5740).interface(1).compatible_id("USB\\Class_0A&SubClass_00&Prot_00"),
+        FakeDevice::new(0x046d, 0xc077).compatible_id("USB\\Class_03&SubClass_01&Prot_02").driver("HidUsb"),
+        FakeDevice::new(0x1234, 0x0001).driver("libusbK"),
+        FakeDevice::new(0x1234, 0x0002).compatible_id("garbage"),
+    ]);
+    fake::set_vendor_name(0x0483, "STMicroelectronics");
+
+    let list = wdi::CreateListOptions::new().list_all(true).create_list().unwrap();
+    let recommended: Vec<_> = list.iter().map(wdi::recommend_driver).collect();
+
+    let dfu = &recommended[0];
+    assert_eq!(dfu.class, Some(wdi::UsbClass::new(0xfe, 0x01, 0x02)));
+    assert_eq!(dfu.best(), Some(wdi::DriverType::WinUsb));
+    assert!(dfu.suggestions[0].reason.contains("DFU"));
+
+    assert_eq!(recommended[1].best(), Some(wdi::DriverType::Cdc));
+    assert!(recommended[1].suggestions[0].reason.contains("interface 0"));
+    assert_eq!(recommended[2].best(), Some(wdi::DriverType::Cdc));
+
+    let hid = &recommended[3];
+    assert!(hid.suggestions.is_empty());
+    assert!(hid.leave_alone.is_some());
+    assert_eq!(hid.current, None);
+
+    let vendor = &recommended[4];
+    assert_eq!(vendor.current, Some(wdi::DriverType::LibUsbK));
+    assert_eq!(vendor.best(), Some(wdi::DriverType::LibUsbK));
+    assert!(vendor.is_satisfied());
+    assert_eq!(vendor.suggestions.len(), 3);
+
+    assert_eq!(recommended[5].class, None);
+    assert_eq!(recommended[5].best(), Some(wdi::DriverType::WinUsb));
+
+    let acm = wdi::UsbClass::from_compatible_id("usb\\class_02&subclass_02").unwrap();
+    assert_eq!(acm.to_string(), "Communications (CDC) (02/02/00)");
+}
+
+#[test]
+fn install_certificate() {
+    fake::reset();
+
+    wdi::InstallCertOptions::new()
+        .disable_warning(true)
+        .install_trusted_certificate("cert.cer")
+        .unwrap();
+    assert_eq!(fake::calls(), vec![
+        Call::InstallTrustedCertificate { cert_name: "cert.cer".to_string(), hwnd: 0, disable_warning: true },
+    ]);
+
+    fake::set_result(Function::InstallTrustedCertificate, wdi_error::WDI_ERROR_USER_CANCEL);
+    let result = wdi::InstallCertOptions::new().install_trusted_certificate("cert.cer");
+    assert!(matches!(result, Err(wdi::Error::UserCancel)));
+}
+
+#[test]
+fn window_handles_are_passed() {
+    setup(devices());
+    let window = wdi::WindowHandle::from(0x1234 as libwdi_sys::HWND);
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    wdi::PrepareDriverOptions::new()
+        .prepare_driver(list.iter_mut().next().unwrap(), "dir", "device.inf")
+        .unwrap()
+        .hwnd(window)
+        .install_driver()
+        .unwrap();
+    wdi::InstallCertOptions::new()
+        .hwnd(window)
+        .install_trusted_certificate("cert.cer")
+        .unwrap();
+
+    let hwnds: Vec<_> = fake::calls().into_iter()
+        .filter_map(|call| match call {
+            Call::InstallDriver { hwnd, .. } | Call::InstallTrustedCertificate { hwnd, .. } => Some(hwnd),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(hwnds, [0x1234, 0x1234]);
+
+    wdi::register_logger(window, 0x8000, 1024).unwrap();
+    wdi::unregister_logger(window).unwrap();
+    fake::set_result(Function::RegisterLogger, wdi_error::WDI_ERROR_EXISTS);
+    assert!(matches!(wdi::register_logger(window, 0x8000, 1024), Err(wdi::Error::Exists)));
+    assert!(wdi::WindowHandle::default().is_none());
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn install_lock_is_held_until_installed() {
+    setup(devices());
+    let dir = TempDir::new("install-lock");
+    let owners = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    for function in [Function::PrepareDriver, Function::InstallDriver] {
+        let (owners, dir) = (owners.clone(), dir.to_path_buf());
+        fake::set_hook(function, move || owners.borrow_mut().push(wdi::InstallLock::owner("usb", &dir)));
+    }
+
+    let lock = wdi::InstallLock::options("usb").dir(dir.path()).tool("installer");
+    let other = lock.clone().tool("cli").mode(wdi::LockMode::TryLock);
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let driver = wdi::PrepareDriverOptions::new()
+        .install_lock(lock.clone())
+        .prepare_driver(dev, dir.as_str(), "device.inf")
+        .unwrap();
+    // Nobody can install another driver between preparing and installing this one
+    assert!(matches!(other.acquire(), Err(wdi::Error::InstallLock(wdi::InstallLockError::Held(Some(owner)))) if owner.tool == "installer"));
+    driver.install_driver().unwrap();
+    assert_eq!(wdi::InstallLock::owner("usb", dir.path()), None);
+
+    // Bound package takes the lock only for the installation
+    let dev = list.iter_mut().next().unwrap();
+    let package = wdi::PrepareDriverOptions::new().prepare_driver(dev, dir.as_str(), "device.inf").unwrap().package();
+    package.bind_in(&mut list).unwrap().install_lock(lock).install_driver().unwrap();
+    drop(other.acquire().unwrap());
+
+    let tools: Vec<_> = owners.borrow().iter().map(|owner| owner.as_ref().map(|owner| owner.tool.clone())).collect();
+    assert_eq!(tools, vec![Some("installer".to_string()), Some("installer".to_string()), None, Some("installer".to_string())]);
+}
@@ -0,0 +1,76 @@
+//! Tests of the cross-process install lock, other processes are simulated by acquiring the lock again
+
+use std::time::{Duration, Instant};
+
+use libwdi as wdi;
+
+mod common;
+use common::TempDir;
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn owner_is_reported_to_waiting_process() {
+    let dir = TempDir::new("lock-owner");
+    let options = wdi::InstallLock::options("usb").dir(dir.path());
+
+    let lock = options.clone().tool("installer").acquire().unwrap();
+    assert_eq!(lock.stale_owner(), None);
+    let owner = wdi::InstallLock::owner("usb", dir.path()).unwrap();
+    assert_eq!((owner.pid, owner.tool.as_str()), (std::process::id(), "installer"));
+
+    let other = options.clone().tool("cli").mode(wdi::LockMode::TryLock);
+    match other.acquire() {
+        Err(wdi::Error::InstallLock(wdi::InstallLockError::Held(Some(found)))) => assert_eq!(found, owner),
+        other => panic!("unexpected result {other:?}"),
+    }
+
+    let mut waited_for = None;
+    let start = Instant::now();
+    let result = other.clone()
+        .mode(wdi::LockMode::Timeout(Duration::from_millis(150)))
+        .acquire_with(|owner| waited_for = owner.cloned());
+    assert!(matches!(result, Err(wdi::Error::InstallLock(wdi::InstallLockError::Held(Some(_))))));
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!(waited_for, Some(owner));
+    assert!(wdi::InstallLockError::Held(waited_for).to_string().contains("installer (pid"));
+
+    drop(lock);
+    assert_eq!(wdi::InstallLock::owner("usb", dir.path()), None);
+    let value = other.run(|| Ok(42)).unwrap();
+    assert_eq!(value, 42);
+}
+
+fn write_owner(dir: &std::path::Path, pid: u32, tool: &str) {
+    let owner = format!(r#"{{"pid": {pid}, "tool": "{tool}", "started": 0}}"#);
+    std::fs::write(dir.join("libwdi-usb.owner.json"), owner).unwrap();
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn stale_lock_is_taken_over() {
+    let dir = TempDir::new("lock-stale");
+    // Left by a process that crashed while holding the lock
+    let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+        .arg("--list")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    child.wait().unwrap();
+    write_owner(&dir, child.id(), "crashed");
+
+    let lock = wdi::InstallLock::options("usb").dir(dir.path()).mode(wdi::LockMode::TryLock).acquire().unwrap();
+    assert_eq!(lock.stale_owner().map(|owner| owner.tool.as_str()), Some("crashed"));
+    assert_eq!(wdi::InstallLock::owner("usb", dir.path()).unwrap().pid, std::process::id());
+    drop(lock);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn owner_file_does_not_keep_free_lock() {
+    let dir = TempDir::new("lock-reused");
+    // Left by a crashed process whose PID has been reused by a running one
+    write_owner(&dir, std::process::id(), "crashed");
+
+    let lock = wdi::InstallLock::options("usb").dir(dir.path()).mode(wdi::LockMode::TryLock).acquire().unwrap();
+    assert_eq!(lock.stale_owner().map(|owner| owner.tool.as_str()), Some("crashed"));
+}
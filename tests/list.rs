@@ -0,0 +1,161 @@
+//! Tests of listing devices and borrowing them from the list, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call, FakeDevice, Function};
+use libwdi_sys::wdi_error;
+
+mod common;
+use common::{devices, setup};
+
+#[test]
+fn list_walks_all_devices() {
+    setup(devices());
+
+    let list = wdi::CreateListOptions::new()
+        .list_all(true)
+        .create_list()
+        .unwrap();
+    let found: Vec<_> = list.iter()
+        .map(|dev| (dev.vid(), dev.pid(), dev.mi().map(|mi| mi.get()), dev.desc().into_owned()))
+        .collect();
+
+    assert_eq!(found, vec![
+        (0x0483, 0xdf11, None, "STM32 BOOTLOADER".to_string()),
+        (0x1234, 0x0001, None, "Composite interface 0".to_string()),
+        (0x1234, 0x0001, Some(2), "Composite interface 2".to_string()),
+        (0x2222, 0x3333, None, "  Device with driver  ".to_string()),
+    ]);
+    assert_eq!(fake::calls(), vec![
+        Call::CreateList { list_all: true, list_hubs: false, trim_whitespaces: false },
+    ]);
+}
+
+#[test]
+fn list_options_are_passed() {
+    setup(devices());
+
+    let list = wdi::CreateListOptions::new()
+        .list_all(true)
+        .trim_whitespaces(true)
+        .create_list()
+        .unwrap();
+    let dev = list.iter().last().unwrap();
+    assert_eq!(dev.desc(), "Device with driver");
+    assert_eq!(dev.driver().as_deref(), Some("WinUSB"));
+
+    let list = wdi::CreateListOptions::new().create_list().unwrap();
+    assert_eq!(list.iter().count(), 3);
+}
+
+#[test]
+fn list_is_destroyed_on_drop() {
+    setup(devices());
+
+    let list = wdi::CreateListOptions::new().create_list().unwrap();
+    let other = wdi::CreateListOptions::new().create_list().unwrap();
+    assert_eq!(fake::live_lists(), 2);
+    drop(list);
+    assert_eq!(fake::live_lists(), 1);
+    drop(other);
+    assert_eq!(fake::live_lists(), 0);
+}
+
+#[test]
+fn destroy_errors_are_not_fatal() {
+    setup(devices());
+    fake::set_result(Function::DestroyList, wdi_error::WDI_ERROR_BUSY);
+
+    drop(wdi::CreateListOptions::new().create_list().unwrap());
+    assert_eq!(fake::live_lists(), 0);
+}
+
+#[test]
+fn list_errors() {
+    fake::reset();
+    assert!(matches!(wdi::CreateListOptions::new().create_list(), Err(wdi::Error::NoDevice)));
+
+    fake::set_devices(devices());
+    fake::set_result(Function::CreateList, wdi_error::WDI_ERROR_RESOURCE);
+    assert!(matches!(wdi::CreateListOptions::new().create_list(), Err(wdi::Error::Resource)));
+    assert_eq!(fake::live_lists(), 0);
+}
+
+#[test]
+fn strings_are_total() {
+    let mut missing = FakeDevice::new(0x1111, 0x2222);
+    missing.desc = None;
+    missing.hardware_id = None;
+    setup(vec![
+        // "Café ™" in code page 1252
+        FakeDevice::new(0x0483, 0xdf11).desc(b"Caf\xe9 \x99".to_vec()).hardware_id(b"USB\\\xff".to_vec()),
+        missing,
+    ]);
+
+    let list = wdi::CreateListOptions::new().create_list().unwrap();
+    let devs: Vec<_> = list.iter().collect();
+
+    assert_eq!(devs[0].desc(), "Caf\u{fffd} \u{fffd}");
+    assert_eq!(devs[0].desc_bytes(), Some(&b"Caf\xe9 \x99"[..]));
+    assert!(matches!(devs[0].try_desc(), Err(wdi::Error::Utf8(_))));
+    assert_eq!(devs[0].desc_with(wdi::CodePage::Id(1252)).unwrap(), "Café ™");
+    assert!(matches!(devs[0].desc_with(wdi::CodePage::Utf8), Err(wdi::Error::Utf8(_))));
+    assert_eq!(devs[0].hardware_id_bytes(), Some(&b"USB\\\xff"[..]));
+    assert!(devs[0].try_hardware_id().is_err());
+    assert_eq!(devs[0].try_driver().unwrap(), None);
+
+    assert_eq!(devs[1].desc(), "");
+    assert_eq!(devs[1].desc_bytes(), None);
+    assert!(matches!(devs[1].try_desc(), Err(wdi::Error::Internal)));
+    assert!(matches!(devs[1].desc_with(wdi::CodePage::Ansi), Err(wdi::Error::Internal)));
+    assert_eq!(devs[1].hardware_id(), None);
+    assert_eq!(devs[1].try_device_id().unwrap(), Some("USB\\VID_1111&PID_2222\\0001"));
+}
+
+#[test]
+fn shared_views_can_coexist() {
+    setup(devices());
+
+    let list = wdi::CreateListOptions::new().create_list().unwrap();
+    let first: Vec<&wdi::DeviceInfo> = list.iter().collect();
+    let second: Vec<&wdi::DeviceInfo> = (&list).into_iter().collect();
+    for (a, b) in first.iter().zip(second.iter()) {
+        assert!(std::ptr::eq(*a, *b));
+        assert_eq!(a.desc(), b.desc());
+        assert_eq!(a.hardware_id(), b.hardware_id());
+    }
+}
+
+#[test]
+fn exclusive_views_are_disjoint() {
+    setup(devices());
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    // Hold exclusive references to all devices at once and use them out of order
+    let mut all: Vec<&mut wdi::DeviceInfo> = list.iter_mut().collect();
+    assert_eq!(all.len(), 3);
+    let last = all.pop().unwrap();
+    let first = all.remove(0);
+    for dev in [last, first].into_iter().chain(all) {
+        wdi::PrepareDriverOptions::new()
+            .prepare_driver(dev, "dir", "device.inf")
+            .and_then(|driver| driver.install_driver())
+            .unwrap();
+    }
+
+    // All devices are still valid after being passed to libwdi
+    let descs: Vec<_> = list.iter().map(|dev| dev.desc().into_owned()).collect();
+    assert_eq!(descs, ["STM32 BOOTLOADER", "Composite interface 0", "Composite interface 2"]);
+    let prepared: Vec<_> = fake::calls().into_iter()
+        .filter_map(|call| match call {
+            Call::PrepareDriver { device, .. } => device.desc,
+            _ => None,
+        })
+        .collect();
+    assert_eq!(prepared, [
+        b"Composite interface 2".to_vec(),
+        b"STM32 BOOTLOADER".to_vec(),
+        b"Composite interface 0".to_vec(),
+    ]);
+}
@@ -0,0 +1,58 @@
+//! Tests of serialization of libwdi calls, in a separate process since the lock mode is global
+
+#![cfg(feature = "fake")]
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, FakeDevice, Function};
+
+#[test]
+fn calls_wait_according_to_lock_mode() {
+    // Fake libwdi state is per thread, the lock is not
+    fake::set_devices(vec![FakeDevice::new(0x1234, 0x0002)]);
+    let (started_tx, started_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+
+    let installer = thread::spawn(move || {
+        fake::set_devices(vec![FakeDevice::new(0x1234, 0x0001)]);
+        fake::set_hook(Function::PrepareDriver, move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+        let dev = list.iter_mut().next().unwrap();
+        wdi::PrepareDriverOptions::new().prepare_driver(dev, "usb_driver", "device.inf").map(|_| ())
+    });
+    started_rx.recv().unwrap();
+
+    assert_eq!(wdi::lock_mode(), wdi::LockMode::Block);
+    wdi::set_lock_mode(wdi::LockMode::TryLock);
+    assert!(matches!(wdi::CreateListOptions::new().create_list(), Err(wdi::Error::Busy)));
+    assert!(matches!(wdi::set_log_level(wdi::LogLevel::Debug), Err(wdi::Error::Busy)));
+    assert!(matches!(wdi::get_wdf_version(), Err(wdi::Error::Busy)));
+    assert!(matches!(wdi::is_driver_supported(wdi::DriverType::WinUsb), Err(wdi::Error::Busy)));
+    // Logs must stay readable while a driver is being installed
+    assert_eq!(wdi::read_logger(&mut [0; 16]).unwrap(), 0);
+
+    wdi::set_lock_mode(wdi::LockMode::Timeout(Duration::from_millis(50)));
+    let start = Instant::now();
+    assert!(matches!(wdi::CreateListOptions::new().create_list(), Err(wdi::Error::Busy)));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    wdi::set_lock_mode(wdi::LockMode::Block);
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        release_tx.send(()).unwrap();
+    });
+    let start = Instant::now();
+    wdi::CreateListOptions::new().create_list().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(40));
+
+    releaser.join().unwrap();
+    installer.join().unwrap().unwrap();
+    wdi::set_lock_mode(wdi::LockMode::TryLock);
+    wdi::CreateListOptions::new().create_list().unwrap();
+}
@@ -0,0 +1,122 @@
+//! Tests of prepared packages saved and installed later, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call, FakeDevice, Function};
+
+mod common;
+use common::{devices, setup, TempDir};
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn package_is_installed_after_reenumeration() {
+    setup(devices());
+
+    let dir = TempDir::new("package");
+    let dir_str = dir.as_str();
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().find(|dev| dev.mi().is_some()).unwrap();
+    let package = wdi::PrepareDriverOptions::new()
+        .driver_type(wdi::DriverType::Cdc)
+        .vendor_name("Vendor").unwrap()
+        .prepare_driver(dev, dir_str, "device.inf")
+        .unwrap()
+        .package();
+    assert_eq!(package.manifest_path(), package.save().unwrap());
+    drop(list);
+    fake::calls();
+
+    // Device order changes, but the same device should be found
+    let mut devices = devices();
+    devices.reverse();
+    fake::set_devices(devices);
+    let loaded = wdi::PreparedPackage::load(&dir).unwrap();
+    assert_eq!(loaded, package);
+    assert_eq!(loaded.options.driver_type, wdi::DriverType::Cdc);
+    assert_eq!(loaded.options.vendor_name.as_deref(), Some("Vendor"));
+
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    loaded.bind_in(&mut list).unwrap().install_driver().unwrap();
+    let calls = fake::calls();
+    let Call::InstallDriver { device, path, inf_name, .. } = calls.last().unwrap() else {
+        panic!("driver not installed: {calls:?}");
+    };
+    assert_eq!((device.mi, device.desc.as_deref()), (2, Some(&b"Composite interface 2"[..])));
+    assert_eq!((path.as_str(), inf_name.as_str()), (dir_str, "device.inf"));
+
+    let other = list.iter_mut().find(|dev| dev.vid() == 0x0483).unwrap();
+    assert!(matches!(loaded.bind(other), Err(wdi::Error::NoDevice)));
+
+    fake::set_devices(vec![FakeDevice::new(0x1234, 0x0001).interface(0)]);
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    assert!(matches!(loaded.bind_in(&mut list), Err(wdi::Error::NoDevice)));
+
+    // Manifests written by a newer version are not mistaken for a missing libwdi
+    let manifest = std::fs::read_to_string(package.manifest_path()).unwrap();
+    std::fs::write(package.manifest_path(), manifest.replace("\"version\": 1", "\"version\": 2")).unwrap();
+    assert!(matches!(wdi::PreparedPackage::load(&dir), Err(wdi::Error::File(wdi::FileError::Json(_)))));
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "uses the file system")]
+fn driver_is_prepared_for_models() {
+    setup(vec![FakeDevice::new(0xcafe, 0x4001).desc("Board 1")]);
+    let dir = TempDir::new("models");
+    let inf_path = dir.join("family.inf");
+
+    // INF as generated by libwdi, in UTF-16 with CRLF line endings
+    let generated = "[Version]\r\nSignature = \"$Windows NT$\"\r\nCatalogFile = family.cat\r\n\r\n\
+        [Manufacturer]\r\n%ProviderName% = libusbDevice_WinUSB,NTx86,NTamd64\r\n\r\n\
+        [libusbDevice_WinUSB.NTx86]\r\n%DeviceName% = USB_Install, USB\\%DeviceID%\r\n\r\n\
+        [libusbDevice_WinUSB.NTamd64]\r\n%DeviceName% = USB_Install, USB\\%DeviceID%\r\n\r\n\
+        [Strings]\r\nProviderName = \"libwdi\"\r\nDeviceName = \"Board 1\"\r\nDeviceID = \"VID_CAFE&PID_4001\"\r\n";
+    let utf16: Vec<u8> = [0xff, 0xfe].into_iter().chain(generated.encode_utf16().flat_map(u16::to_le_bytes)).collect();
+    {
+        let inf_path = inf_path.clone();
+        fake::set_hook(Function::PrepareDriver, move || {
+            if !inf_path.exists() {
+                std::fs::write(&inf_path, &utf16).unwrap();
+            }
+        });
+    }
+
+    let models = [
+        wdi::DeviceModel::new(0xcafe, 0x4001, "Board 1"),
+        wdi::DeviceModel::new(0xcafe, 0x4002, "Board \"2\" 100%"),
+        wdi::DeviceModel::new(0xcafe, 0x4003, "Board 3").interface(1),
+    ];
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let driver = wdi::PrepareDriverOptions::new()
+        .prepare_driver_for_models(dev, &models, dir.as_str(), "family.inf")
+        .unwrap();
+    assert!(driver.package().options.external_inf);
+    drop(driver);
+
+    let calls = fake::calls();
+    assert!(matches!(&calls[1], Call::PrepareDriver { disable_cat: true, external_inf: false, .. }));
+    assert!(matches!(&calls[2], Call::PrepareDriver { disable_cat: false, external_inf: true, .. }));
+
+    let inf = wdi::InfFile::load(&inf_path).unwrap();
+    assert_eq!(inf.hardware_ids(), vec![
+        "USB\\VID_CAFE&PID_4001".to_string(),
+        "USB\\VID_CAFE&PID_4002".to_string(),
+        "USB\\VID_CAFE&PID_4003&MI_01".to_string(),
+    ]);
+    let models_section: Vec<_> = inf.section("libusbDevice_WinUSB.NTamd64").collect();
+    assert_eq!(models_section.len(), 3);
+    assert_eq!(models_section[1].key.as_deref(), Some("Board \"2\" 100%"));
+    assert_eq!(models_section[1].values, vec!["USB_Install".to_string(), "USB\\VID_CAFE&PID_4002".to_string()]);
+
+    let data = std::fs::read(&inf_path).unwrap();
+    assert_eq!(&data[..2], &[0xff, 0xfe]);
+    let units: Vec<u16> = data[2..].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let text = String::from_utf16(&units).unwrap();
+    assert!(text.contains("[libusbDevice_WinUSB.NTx86]\r\n%DeviceName% = USB_Install, USB\\%DeviceID%\r\n\
+        \"Board \"\"2\"\" 100%%\" = USB_Install, USB\\VID_CAFE&PID_4002\r\n"), "{text}");
+
+    let bad = [wdi::DeviceModel::new(0xcafe, 0x4004, "Two\nlines")];
+    assert!(matches!(wdi::add_inf_models(&inf_path, &bad), Err(wdi::Error::InvalidParam)));
+}
@@ -0,0 +1,107 @@
+//! Tests of progress reporting, in a separate process since the libwdi log is global
+
+#![cfg(feature = "fake")]
+
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, FakeDevice, Function};
+use libwdi_sys::wdi_error;
+
+const PREPARE_LOG: [&str; 5] = [
+    "Extracting driver files...",
+    "Successfully created 'C:\\dir\\device.inf'",
+    "Creating and self-signing 'device.cat'",
+    "Added certificate 'CN=USB\\VID_0483' to 'Trusted Root' store",
+    "Unrelated message",
+];
+
+#[test]
+fn progress_is_driven_by_log() {
+    fake::push_log("first");
+    fake::push_log("second");
+    let mut buf = [0; 64];
+    let n = wdi::read_logger(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"first");
+    let n = wdi::read_logger(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"second");
+    assert_eq!(wdi::read_logger(&mut buf).unwrap(), 0);
+
+    fake::set_devices(vec![FakeDevice::new(0x0483, 0xdf11).desc("STM32 BOOTLOADER")]);
+    // Messages are logged while the call is running and must be reported before it returns
+    let reported = Arc::new(AtomicUsize::new(0));
+    let reported_during_call = Arc::new(AtomicUsize::new(0));
+    fake::set_hook(Function::PrepareDriver, {
+        let (reported, reported_during_call) = (reported.clone(), reported_during_call.clone());
+        move || {
+            PREPARE_LOG.iter().for_each(|line| fake::push_log(line));
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while reported.load(Ordering::SeqCst) < PREPARE_LOG.len() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
+            }
+            reported_during_call.store(reported.load(Ordering::SeqCst), Ordering::SeqCst);
+        }
+    });
+
+    let mut events = vec![];
+    let mut list = wdi::CreateListOptions::new().create_list().unwrap();
+    let driver = wdi::PrepareDriverOptions::new()
+        .prepare_driver_with_progress(list.iter_mut().next().unwrap(), "dir", "device.inf", &mut |event: &wdi::ProgressEvent| {
+            if matches!(event.kind, wdi::ProgressKind::Log(_)) {
+                reported.fetch_add(1, Ordering::SeqCst);
+            }
+            events.push(event.clone())
+        })
+        .unwrap();
+    assert!(reported_during_call.load(Ordering::SeqCst) > 0, "no message reported during the call");
+    let phases: Vec<_> = events.iter()
+        .filter_map(|event| match &event.kind {
+            wdi::ProgressKind::Phase(phase, _) => Some(*phase),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(phases, [
+        wdi::Phase::ExtractingFiles,
+        wdi::Phase::GeneratingInf,
+        wdi::Phase::GeneratingCatalog,
+        wdi::Phase::InstallingCertificate,
+    ]);
+    assert_eq!(reported.load(Ordering::SeqCst), PREPARE_LOG.len());
+    assert!(matches!(events.last().unwrap().kind, wdi::ProgressKind::Finished(Ok(()))));
+    assert!(events.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+
+    fake::set_result(Function::InstallDriver, wdi_error::WDI_ERROR_PENDING_INSTALLATION);
+    fake::set_hook(Function::InstallDriver, || fake::push_log("Waiting for pending installation to complete"));
+    let mut kinds = vec![];
+    let result = driver.install_driver_with_progress(&mut |event: &wdi::ProgressEvent| kinds.push(event.kind.clone()));
+    assert!(matches!(result, Err(wdi::Error::PendingInstallation)));
+    assert!(matches!(kinds.as_slice(), [
+        wdi::ProgressKind::Phase(wdi::Phase::Installing, None),
+        wdi::ProgressKind::Phase(wdi::Phase::WaitingForPendingInstall, Some(_)),
+        wdi::ProgressKind::Log(_),
+        wdi::ProgressKind::Finished(Err(wdi::Error::PendingInstallation)),
+    ]));
+
+    // Messages of the logger registered by the application are left to it
+    let app_window = wdi::WindowHandle::from(1 as libwdi_sys::HWND);
+    wdi::register_logger(app_window, 0x8001, 4096).unwrap();
+    let dev = list.iter_mut().next().unwrap();
+    let mut kinds = vec![];
+    wdi::PrepareDriverOptions::new()
+        .prepare_driver_with_progress(dev, "dir", "device.inf", &mut |event: &wdi::ProgressEvent| kinds.push(event.kind.clone()))
+        .unwrap();
+    assert!(matches!(kinds.as_slice(), [
+        wdi::ProgressKind::Phase(wdi::Phase::ExtractingFiles, None),
+        wdi::ProgressKind::Finished(Ok(())),
+    ]));
+    let mut buf = [0; 64];
+    let n = wdi::read_logger(&mut buf).unwrap();
+    assert_eq!(&buf[..n], PREPARE_LOG[0].as_bytes());
+    wdi::unregister_logger(app_window).unwrap();
+
+    assert_eq!(wdi::Phase::from_log_line("Installing driver(s)..."), Some(wdi::Phase::Installing));
+    assert_eq!(wdi::Phase::from_log_line("Verifying installation"), Some(wdi::Phase::Verifying));
+    assert_eq!(wdi::Phase::from_log_line("Device description: foo"), None);
+}
@@ -0,0 +1,66 @@
+//! Tests of the system information functions and the system report, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self};
+
+#[test]
+fn misc_functions() {
+    fake::reset();
+    fake::set_vendor_name(0x0483, "STMicroelectronics");
+    fake::set_wdf_version(Some(1011));
+    fake::set_driver_supported(libwdi_sys::wdi_driver_type::WDI_LIBUSB0, false);
+    fake::set_file_embedded(Some("amd64"), "winusbcoinstaller2.dll");
+
+    assert_eq!(wdi::get_vendor_name(0x0483), Some("STMicroelectronics"));
+    assert_eq!(wdi::get_vendor_name(0x0001), None);
+    assert_eq!(wdi::try_get_vendor_name(0x0483).unwrap(), Some("STMicroelectronics"));
+    assert_eq!(wdi::get_vendor_name_bytes(0x0483), Some(&b"STMicroelectronics"[..]));
+    assert_eq!(wdi::get_wdf_version().unwrap(), 1011);
+    assert!(wdi::is_driver_supported(wdi::DriverType::WinUsb).unwrap().is_some());
+    assert!(wdi::is_driver_supported(wdi::DriverType::LibUsb0).unwrap().is_none());
+    assert!(wdi::is_file_embedded(Some("amd64"), "winusbcoinstaller2.dll").unwrap());
+    assert!(!wdi::is_file_embedded(None, "winusbcoinstaller2.dll").unwrap());
+
+    wdi::set_log_level(wdi::LogLevel::Warning).unwrap();
+    assert_eq!(fake::log_level(), libwdi_sys::wdi_log_level::WDI_LOG_LEVEL_WARNING);
+}
+
+#[test]
+fn system_report_checks() {
+    fake::reset();
+    fake::set_wdf_version(Some(1011));
+    fake::set_driver_supported(libwdi_sys::wdi_driver_type::WDI_LIBUSB0, false);
+    let arch = if cfg!(target_arch = "x86_64") { "amd64" } else if cfg!(target_arch = "aarch64") { "arm64" } else { "x86" };
+    fake::set_file_embedded(Some(arch), "winusbcoinstaller2.dll");
+
+    let report = wdi::SystemReport::collect();
+    let check = |name: &str| report.checks.iter().find(|check| check.name == name).unwrap();
+
+    assert_eq!(report.wdf_version, Some(1011));
+    assert_eq!(check("wdf").verdict, wdi::Verdict::Pass);
+
+    let winusb = report.driver(wdi::DriverType::WinUsb).unwrap();
+    assert!(winusb.supported);
+    assert!(winusb.file.as_ref().unwrap().embedded);
+    assert_eq!(check("driver_winusb").verdict, wdi::Verdict::Pass);
+
+    let libusb0 = report.driver(wdi::DriverType::LibUsb0).unwrap();
+    assert!(!libusb0.supported);
+    let expected = if libusb0.embedded { wdi::Verdict::Fail } else { wdi::Verdict::Warn };
+    assert_eq!(check("driver_libusb0").verdict, expected);
+
+    if cfg!(not(windows)) {
+        assert_eq!(report.os_version, None);
+        assert_eq!(check("os").verdict, wdi::Verdict::Fail);
+        assert_eq!(report.verdict(), wdi::Verdict::Fail);
+    }
+
+    let text = report.to_string();
+    assert!(text.contains("[PASS] wdf: WDF version 1011\n"));
+    assert!(text.ends_with(&format!("Overall: {}\n", report.verdict())));
+    let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["drivers"][0]["driver"], "WinUsb");
+    assert_eq!(json["checks"][2]["verdict"], "pass");
+}
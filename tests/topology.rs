@@ -0,0 +1,116 @@
+//! Tests of composite devices and the hub topology built from the device tree, running against fake libwdi from libwdi-sys
+
+#![cfg(feature = "fake")]
+
+use libwdi as wdi;
+use libwdi_sys::fake::{self, Call, FakeDevice};
+
+mod common;
+use common::setup;
+
+#[test]
+fn composite_interfaces_are_grouped() {
+    let interface = |mi: u8, parent: &str| {
+        FakeDevice::new(0x1234, 0x0001)
+            .interface(mi)
+            .device_id(format!("USB\\VID_1234&PID_0001&MI_{mi:02X}\\{parent}&{mi:04X}"))
+    };
+    setup(vec![
+        interface(2, "6&AAAA&0"),
+        FakeDevice::new(0x0483, 0xdf11),
+        interface(0, "6&aaaa&0"),
+        interface(0, "6&BBBB&0"),
+        interface(2, "6&BBBB&0"),
+        FakeDevice::new(0x5678, 0x0002).interface(1),
+        FakeDevice::new(0x5678, 0x0002).interface(1),
+    ]);
+
+    let mut list = wdi::CreateListOptions::new().list_all(true).create_list().unwrap();
+    let devices = list.composite_devices();
+    let found: Vec<_> = devices.iter()
+        .map(|dev| (dev.vid, dev.parent.as_deref(), dev.interfaces.iter().map(|i| i.mi().map_or(0, |mi| mi.get())).collect::<Vec<_>>()))
+        .collect();
+    assert_eq!(found, vec![
+        (0x1234, Some("6&AAAA&0"), vec![0, 2]),
+        (0x1234, Some("6&BBBB&0"), vec![0, 2]),
+        (0x5678, None, vec![1]),
+        (0x5678, None, vec![1]),
+    ]);
+    assert!(devices[1].interface(2).is_some());
+    assert!(devices[1].interface(1).is_none());
+
+    let mut devices = list.composite_devices_mut();
+    for dev in devices[0].interfaces.iter_mut() {
+        wdi::PrepareDriverOptions::new()
+            .prepare_driver(dev, "usb_driver", "composite.inf")
+            .unwrap()
+            .install_driver()
+            .unwrap();
+    }
+    let installed = fake::calls().into_iter()
+        .filter_map(|call| match call {
+            Call::InstallDriver { device, .. } => Some(device.mi),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(installed, [0, 2]);
+}
+
+#[test]
+fn topology_is_built_from_device_tree() {
+    use std::collections::HashMap;
+
+    struct Tree(HashMap<&'static str, (&'static str, &'static str)>);
+
+    impl wdi::DeviceTree for Tree {
+        fn parent(&self, device_id: &str) -> Option<String> {
+            self.0.get(device_id).map(|(parent, _)| parent.to_string())
+        }
+
+        fn location(&self, device_id: &str) -> Option<String> {
+            self.0.get(device_id).map(|(_, location)| location.to_string())
+        }
+    }
+
+    setup(vec![
+        FakeDevice::new(0x1234, 0x0001).interface(1).device_id("USB\\VID_1234&PID_0001&MI_01\\7&1&0&0001"),
+        FakeDevice::new(0x1234, 0x0001).interface(0).device_id("USB\\VID_1234&PID_0001&MI_00\\7&1&0&0000").desc("Board"),
+        FakeDevice::new(0x0483, 0xdf11).device_id("USB\\VID_0483&PID_DF11\\SN1").desc("DFU").driver("WinUSB"),
+        FakeDevice::new(0x05e3, 0x0610).device_id("USB\\VID_05E3&PID_0610\\5&1").desc("Hub")
+            .compatible_id("USB\\Class_09&SubClass_00&Prot_02").hub(),
+        FakeDevice::new(0x2222, 0x3333).device_id("USB\\VID_2222&PID_3333\\X").desc("Elsewhere"),
+    ]);
+    let tree = Tree(HashMap::from([
+        ("USB\\VID_1234&PID_0001&MI_00\\7&1&0&0000", ("USB\\VID_1234&PID_0001\\SERIAL", "0000.0014.0000.001.003.000.000.000.000")),
+        ("USB\\VID_1234&PID_0001&MI_01\\7&1&0&0001", ("USB\\VID_1234&PID_0001\\SERIAL", "0000.0014.0000.001.003.000.000.000.000")),
+        ("USB\\VID_1234&PID_0001\\SERIAL", ("usb\\vid_05e3&pid_0610\\5&1", "Port_#0003.Hub_#0002")),
+        ("USB\\VID_0483&PID_DF11\\SN1", ("USB\\VID_05E3&PID_0610\\5&1", "Port_#0001.Hub_#0002")),
+        ("USB\\VID_05E3&PID_0610\\5&1", ("PCI\\VEN_8086&DEV_A36D\\3&1", "Port_#0004.Hub_#0001")),
+    ]));
+
+    let list = wdi::CreateListOptions::new().list_all(true).list_hubs(true).create_list().unwrap();
+    let topology = wdi::Topology::with_tree(list.iter(), &tree);
+    assert_eq!(topology.to_string(), "\
+Hub (05e3:0610), port 4
+  DFU (0483:df11), port 1, driver WinUSB
+  USB\\VID_1234&PID_0001\\SERIAL, port 3
+    Board (1234:0001) interface 0
+    Fake USB device (1234:0001) interface 1
+Elsewhere (2222:3333)
+");
+    let hub = &topology.roots[0];
+    assert_eq!(hub.kind, wdi::NodeKind::Hub);
+    assert_eq!(hub.children[1].kind, wdi::NodeKind::Unlisted);
+    assert_eq!((hub.children[1].vid, hub.children[1].pid), (0x1234, 0x0001));
+
+    let json: serde_json::Value = serde_json::from_str(&topology.to_json().unwrap()).unwrap();
+    assert_eq!(json["roots"][0]["children"][1]["children"][0]["kind"], "interface");
+    assert_eq!(json["roots"][0]["location"], "Port_#0004.Hub_#0001");
+
+    // Without the system device tree only composite interfaces are grouped
+    let guessed = list.topology();
+    assert_eq!(guessed.roots.len(), 4);
+    let parent = guessed.roots.iter().find(|node| node.kind == wdi::NodeKind::Unlisted).unwrap();
+    assert_eq!(parent.device_id.as_deref(), Some("USB\\VID_1234&PID_0001\\7&1&0"));
+    assert_eq!(parent.children.len(), 2);
+}